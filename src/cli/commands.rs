@@ -1,4 +1,7 @@
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+
+use super::completions::{CompletionShell, find_flag, takes_value};
+use crate::config::ConfigFormat;
 
 #[derive(Parser)]
 #[command(author, version, about)]
@@ -10,6 +13,16 @@ pub struct Cli {
     #[arg(short, long)]
     pub list: bool,
 
+    /// Output format for --list: colored text, or every task's metadata as
+    /// JSON or YAML for scripts and editor integrations
+    #[arg(long, value_enum, default_value_t = ListFormat::Text, requires = "list")]
+    pub format: ListFormat,
+
+    /// List tasks (with --list or in the picker) sorted by name rather than in
+    /// the order the config declares them
+    #[arg(long)]
+    pub sort: bool,
+
     /// Interactive mode
     #[arg(short, long)]
     pub interactive: bool,
@@ -26,11 +39,96 @@ pub struct Cli {
     #[arg(long, value_name = "DIR")]
     pub cwd: Option<std::path::PathBuf>,
 
+    /// Run up to N commands at once: independent dependencies and the steps of
+    /// `parallel` tasks run concurrently
+    #[arg(short, long, value_name = "N")]
+    pub jobs: Option<usize>,
+
+    /// Run tasks even when their inputs are unchanged since the last successful run
+    #[arg(long)]
+    pub force: bool,
+
+    /// With --dry-run, preview the commands another platform would run
+    /// (linux, macos or windows). For a task with a `platform` variable of
+    /// its own, it must come before the task name
+    #[arg(long, value_name = "OS")]
+    pub platform: Option<String>,
+
+    /// Fail on a missing required variable instead of prompting for it (the
+    /// default whenever stdin isn't a terminal, e.g. in CI)
+    #[arg(long)]
+    pub no_input: bool,
+
+    /// Read variable values from a JSON file (repeatable; later files win).
+    /// They override defaults, and `--name=value` overrides them
+    #[arg(long, value_name = "FILE")]
+    pub var_file: Vec<std::path::PathBuf>,
+
     /// Read tasks from a Makefile in this directory (or an ancestor)
     #[arg(long)]
     pub make: bool,
 }
 
+/// Subcommands a task can share its name with: when the config defines such a
+/// task, `robin <name> ...` runs the task and the subcommand is out of reach.
+pub(crate) const TASK_NAMED_SUBCOMMANDS: &[&str] = &["watch", "completions", "validate", "convert"];
+
+impl Cli {
+    /// Parses `args` (the program name first) like [`Parser::parse_from`],
+    /// except that a subcommand in [`TASK_NAMED_SUBCOMMANDS`] for which
+    /// `is_task` returns true is read as a task name instead. Exits with
+    /// clap's message on invalid arguments.
+    pub fn parse_with_tasks(args: Vec<String>, is_task: impl Fn(&str) -> bool) -> Self {
+        Self::try_parse_with_tasks(args, is_task).unwrap_or_else(|e| e.exit())
+    }
+
+    /// [`Cli::parse_with_tasks`], returning clap's error instead of exiting.
+    pub fn try_parse_with_tasks(
+        args: Vec<String>,
+        is_task: impl Fn(&str) -> bool,
+    ) -> Result<Self, clap::Error> {
+        match task_subcommand(&args, is_task) {
+            Some(i) => {
+                // Everything before the name is robin's own flags; the rest is
+                // the task invocation, as for any other task.
+                let mut cli = Self::try_parse_from(&args[..i])?;
+                cli.command = Some(Commands::Run(args[i..].to_vec()));
+                Ok(cli)
+            }
+            None => Self::try_parse_from(args),
+        }
+    }
+}
+
+/// The position in `args` of a subcommand that names one of the tasks, if the
+/// first word after robin's own flags is one.
+fn task_subcommand(args: &[String], is_task: impl Fn(&str) -> bool) -> Option<usize> {
+    let cli = Cli::command();
+    let mut i = 1;
+    while let Some(word) = args.get(i) {
+        if word == "--" {
+            return None;
+        }
+        if !word.starts_with('-') {
+            let named = TASK_NAMED_SUBCOMMANDS.contains(&word.as_str()) && is_task(word);
+            return named.then_some(i);
+        }
+        // `--jobs=4` and `-j4` carry their value; `--jobs 4` takes the next word.
+        let attached = word.contains('=') || (!word.starts_with("--") && word.len() > 2);
+        let value_follows = find_flag(&cli, word).is_some_and(takes_value) && !attached;
+        i += if value_follows { 2 } else { 1 };
+    }
+    None
+}
+
+/// How `--list` prints the tasks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ListFormat {
+    Text,
+    Json,
+    Yaml,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize a new .robin.json file
@@ -46,6 +144,9 @@ pub enum Commands {
         name: String,
         /// Command script
         script: String,
+        /// Add it to the global task library, available in every directory
+        #[arg(long)]
+        global: bool,
     },
 
     /// Remove a command
@@ -53,6 +154,9 @@ pub enum Commands {
     Remove {
         /// Command name to remove
         name: String,
+        /// Remove it from the global task library
+        #[arg(long)]
+        global: bool,
     },
 
     /// Rename a command
@@ -61,18 +165,64 @@ pub enum Commands {
         from: String,
         /// New command name
         to: String,
+        /// Rename it in the global task library
+        #[arg(long)]
+        global: bool,
     },
 
     /// Rewrite .robin.json so every task uses the object form with a `desc`
     /// field ready to be filled in (existing string/array tasks keep working)
     Migrate,
 
+    /// Rewrite the config in another format (.robin.json, .robin.yaml or
+    /// robin.toml), replacing the original file
+    Convert {
+        /// Format to convert to
+        #[arg(long, value_enum)]
+        to: ConfigFormat,
+    },
+
+    /// Check .robin.json and the configs it includes for mistakes: schema
+    /// violations, unknown or cyclic task references, malformed placeholders,
+    /// clashing task names and missing includes. Exits non-zero on errors
+    Validate {
+        /// Config file to check (default: the nearest .robin.json)
+        #[arg(value_name = "FILE")]
+        file: Option<std::path::PathBuf>,
+    },
+
     /// Check development environment setup
     Doctor,
 
     /// Update development tools to their latest versions
     DoctorUpdate,
 
+    /// Run a task and rerun it whenever its input files (or --watch globs) change
+    Watch {
+        /// Extra glob to watch, relative to the project root (repeatable)
+        #[arg(long, value_name = "GLOB")]
+        watch: Vec<String>,
+        /// Task name followed by its --var=value arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+
+    /// Print a shell completion script that completes task names, variables
+    /// and their choices from the nearest .robin.json
+    Completions {
+        /// Shell to generate the script for
+        #[arg(value_enum)]
+        shell: CompletionShell,
+    },
+
+    /// Print completions for a command line (called by the completion scripts)
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        /// The command line up to the cursor
+        #[arg(default_value = "", allow_hyphen_values = true)]
+        line: String,
+    },
+
     /// Run a script
     #[command(external_subcommand)]
     Run(Vec<String>),
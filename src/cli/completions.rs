@@ -0,0 +1,408 @@
+use clap::{Arg, CommandFactory, ValueEnum};
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use super::commands::{Cli, TASK_NAMED_SUBCOMMANDS};
+use crate::config::{PLATFORMS, RobinConfig, TaskSources, VarSpec, config_vars, find_config_path};
+use crate::makefile::{find_makefile_path, load_makefile_scripts};
+use crate::scripts::task_infos;
+
+/// Shells `robin completions` can generate a script for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// The completion script for `shell`. Rather than a fixed word list, each
+/// script hands the command line to `robin __complete`, so tasks and their
+/// variables are always read from the nearest config.
+pub fn completion_script(shell: CompletionShell) -> &'static str {
+    match shell {
+        CompletionShell::Bash => BASH_SCRIPT,
+        CompletionShell::Zsh => ZSH_SCRIPT,
+        CompletionShell::Fish => FISH_SCRIPT,
+        CompletionShell::Powershell => POWERSHELL_SCRIPT,
+    }
+}
+
+const BASH_SCRIPT: &str = r#"# robin completions for bash. Add to ~/.bashrc:
+#   eval "$(robin completions bash)"
+_robin() {
+    local line="${COMP_LINE:0:COMP_POINT}"
+    local word="${line##*[[:space:]]}"
+    # bash splits `--env=st` into several words at `=` (and `:`), so only the
+    # part after the last break is replaced.
+    local head="${word%"${word##*[=:]}"}"
+    local IFS=$'\n'
+    COMPREPLY=($(robin __complete "$line" 2>/dev/null))
+    COMPREPLY=("${COMPREPLY[@]#"$head"}")
+    [[ ${#COMPREPLY[@]} -eq 1 && ${COMPREPLY[0]} == *= ]] && compopt -o nospace
+}
+complete -o default -F _robin robin
+"#;
+
+const ZSH_SCRIPT: &str = r#"#compdef robin
+# robin completions for zsh. Add to ~/.zshrc (after compinit):
+#   eval "$(robin completions zsh)"
+_robin() {
+    local -a candidates
+    candidates=("${(@f)$(robin __complete "${(j: :)words[1,CURRENT]}" 2>/dev/null)}")
+    compadd -S '' -- ${(M)candidates:#*=}
+    compadd -- ${candidates:#*=}
+}
+compdef _robin robin
+"#;
+
+const FISH_SCRIPT: &str = r#"# robin completions for fish. Add to ~/.config/fish/config.fish:
+#   robin completions fish | source
+complete -c robin -f -a '(robin __complete (commandline -cp) 2>/dev/null)'
+"#;
+
+const POWERSHELL_SCRIPT: &str = r#"# robin completions for PowerShell. Add to $PROFILE:
+#   robin completions powershell | Out-String | Invoke-Expression
+Register-ArgumentCompleter -Native -CommandName robin -ScriptBlock {
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $line = $commandAst.ToString()
+    $length = $cursorPosition - $commandAst.Extent.StartOffset
+    if ($length -gt $line.Length) { $line = $line.PadRight($length) } else { $line = $line.Substring(0, $length) }
+    robin __complete $line 2>$null | ForEach-Object {
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }
+}
+"#;
+
+/// Completes the last word of `line`, a command line up to the cursor (its
+/// first word is the program name), from the nearest `.robin.json`, or the
+/// nearest Makefile when `--make` is on the line.
+pub fn complete_line(line: &str) -> Vec<String> {
+    let mut words: Vec<String> = line.split_whitespace().skip(1).map(String::from).collect();
+    // A trailing space means a new, still empty, word is being completed.
+    if line.is_empty() || line.ends_with(char::is_whitespace) {
+        words.push(String::new());
+    }
+    let config_path = find_config_path();
+    complete(&words, config_path.parent(), |make| {
+        if make {
+            let scripts = load_makefile_scripts(&find_makefile_path()).ok();
+            return (scripts.unwrap_or_default(), Vec::new());
+        }
+        match RobinConfig::load(&config_path) {
+            Ok(config) => {
+                let shared = config_vars(&config.vars).unwrap_or_default();
+                (config.scripts, shared)
+            }
+            Err(_) => Default::default(),
+        }
+    })
+}
+
+/// Completes the last of `words` (the arguments after `robin`, the last one
+/// being the word under the cursor, possibly empty). `load_scripts` is asked
+/// for the tasks and the config's top-level `vars`, with `true` for Makefile
+/// targets; `root` is the config's directory, where `choices_from` runs.
+///
+/// - Before a task name: robin's own flags and subcommands, and task names.
+/// - Inside a multi-word task name (`deploy st`): the next word of the
+///   matching names.
+/// - After a task name: its `--var=` flags, and for `--var=` the enum choices
+///   (read from `choices_from` when the variable declares it).
+pub fn complete(
+    words: &[String],
+    root: Option<&Path>,
+    load_scripts: impl Fn(bool) -> (IndexMap<String, Value>, Vec<VarSpec>),
+) -> Vec<String> {
+    let Some((current, typed)) = words.split_last() else {
+        return Vec::new();
+    };
+    let cli = Cli::command();
+    let make = typed.iter().any(|w| w == "--make");
+
+    // Robin's own flags and subcommand come before the task name.
+    let mut command = &cli;
+    let mut rest = typed;
+    while let Some((word, tail)) = rest.split_first() {
+        if word.starts_with('-') && word != "--" {
+            let arg = find_flag(command, word);
+            if arg.is_some_and(takes_value) && !word.contains('=') {
+                if tail.is_empty() {
+                    // The word under the cursor is this flag's value.
+                    return arg.map(|a| flag_values(a, current)).unwrap_or_default();
+                }
+                rest = &tail[1..];
+            } else {
+                rest = tail;
+            }
+            continue;
+        }
+        // A task sharing a subcommand's name is run instead of it.
+        let task_named = |name: &str| {
+            TASK_NAMED_SUBCOMMANDS.contains(&name) && load_scripts(make).0.contains_key(name)
+        };
+        match command.find_subcommand(word) {
+            Some(sub) if task_named(sub.get_name()) => break,
+            Some(sub) if sub.get_name() == "watch" => {
+                command = sub;
+                rest = tail;
+            }
+            Some(sub) if sub.get_name() == "completions" && tail.is_empty() => {
+                return matching(
+                    CompletionShell::value_variants()
+                        .iter()
+                        .filter_map(|s| s.to_possible_value().map(|v| v.get_name().to_string())),
+                    current,
+                );
+            }
+            // Other subcommands take free-form arguments.
+            Some(_) => return Vec::new(),
+            None => break,
+        }
+    }
+
+    // Arguments after `--` go to the task's command; leave them to the shell.
+    if rest.iter().any(|w| w == "--") {
+        return Vec::new();
+    }
+
+    let task_words: Vec<&str> = rest
+        .iter()
+        .take_while(|w| !w.starts_with("--"))
+        .map(String::as_str)
+        .collect();
+    let (scripts, shared) = load_scripts(make);
+
+    if task_words.is_empty() && current.starts_with('-') {
+        return matching(
+            command
+                .get_arguments()
+                .filter(|a| !a.is_hide_set())
+                .filter_map(|a| a.get_long())
+                .map(|long| format!("--{}", long)),
+            current,
+        );
+    }
+
+    if current.starts_with("--") {
+        let name = task_words.join(" ");
+        if !scripts.contains_key(&name) {
+            return Vec::new();
+        }
+        let mut variables = task_infos(&scripts, &TaskSources::default(), &shared)
+            .into_iter()
+            .find(|info| info.name == name)
+            .map(|info| info.variables)
+            .unwrap_or_default();
+
+        return match current.split_once('=') {
+            Some((flag, _)) => matching(
+                variables
+                    .iter_mut()
+                    .filter(|v| format!("--{}", v.name) == flag)
+                    .flat_map(|v| {
+                        // A failing `choices_from` just completes nothing.
+                        let _ = v.load_choices(root);
+                        v.values()
+                    })
+                    .map(|choice| format!("{}={}", flag, choice)),
+                current,
+            ),
+            None => matching(
+                variables
+                    .iter()
+                    .map(|v| format!("--{}=", v.name))
+                    .filter(|flag| !typed.iter().any(|w| w.starts_with(flag.as_str()))),
+                current,
+            ),
+        };
+    }
+
+    // Once variables have started, no more task name words follow.
+    if rest.len() > task_words.len() {
+        return Vec::new();
+    }
+
+    let mut prefix = task_words.join(" ");
+    if !prefix.is_empty() {
+        prefix.push(' ');
+    }
+    prefix.push_str(current);
+    let mut candidates: BTreeSet<String> = scripts
+        .keys()
+        .filter(|name| name.starts_with(&prefix))
+        .filter_map(|name| name.split(' ').nth(task_words.len()))
+        .map(String::from)
+        .collect();
+    if task_words.is_empty() && command.get_name() != "watch" {
+        candidates.extend(matching(
+            command
+                .get_subcommands()
+                .filter(|s| !s.is_hide_set())
+                .map(|s| s.get_name().to_string()),
+            current,
+        ));
+    }
+    candidates.into_iter().collect()
+}
+
+pub(super) fn find_flag<'a>(command: &'a clap::Command, word: &str) -> Option<&'a Arg> {
+    let flag = word.split('=').next().unwrap_or(word);
+    command.get_arguments().find(|a| {
+        flag.strip_prefix("--")
+            .is_some_and(|long| a.get_long() == Some(long))
+            || flag
+                .strip_prefix('-')
+                .and_then(|s| s.chars().next())
+                .is_some_and(|short| !flag.starts_with("--") && a.get_short() == Some(short))
+    })
+}
+
+pub(super) fn takes_value(arg: &Arg) -> bool {
+    arg.get_num_args().is_some_and(|n| n.takes_values())
+        || matches!(
+            arg.get_action(),
+            clap::ArgAction::Set | clap::ArgAction::Append
+        )
+}
+
+/// The values a flag accepts, for flags with a fixed set of them.
+fn flag_values(arg: &Arg, current: &str) -> Vec<String> {
+    let values: Vec<String> = match arg.get_long() {
+        Some("platform") => PLATFORMS.iter().map(|p| p.to_string()).collect(),
+        _ => arg
+            .get_possible_values()
+            .iter()
+            .map(|v| v.get_name().to_string())
+            .collect(),
+    };
+    matching(values, current)
+}
+
+fn matching(candidates: impl IntoIterator<Item = String>, current: &str) -> Vec<String> {
+    candidates
+        .into_iter()
+        .filter(|c| c.starts_with(current))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scripts(_make: bool) -> (IndexMap<String, Value>, Vec<VarSpec>) {
+        let scripts = serde_json::from_value(json!({
+            "build": "cargo build",
+            "deploy staging": "deploy --env={{env=[staging, production]}} --tag={{tag}}",
+            "deploy production": "deploy --env=production",
+            "lane": {
+                "cmd": "fastlane {{lane}}",
+                "vars": { "lane": { "choices_from": "printf 'beta\\nrelease\\n'" } }
+            },
+            "push": "docker push {{registry}}/app",
+            "test": "cargo test",
+        }))
+        .unwrap();
+        let shared = config_vars(
+            &serde_json::from_value(json!({
+                "registry": { "choices": ["ghcr.io", "docker.io"] }
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+        (scripts, shared)
+    }
+
+    fn complete_words(words: &[&str]) -> Vec<String> {
+        let words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        complete(&words, None, scripts)
+    }
+
+    #[test]
+    fn completes_task_names_and_subcommands() {
+        let all = complete_words(&[""]);
+        assert!(all.contains(&"build".to_string()));
+        assert!(all.contains(&"deploy".to_string()));
+        assert!(all.contains(&"watch".to_string()));
+        assert!(!all.contains(&"__complete".to_string()));
+
+        assert_eq!(complete_words(&["b"]), vec!["build"]);
+    }
+
+    #[test]
+    fn completes_the_next_word_of_multi_word_names() {
+        assert_eq!(
+            complete_words(&["deploy", ""]),
+            vec!["production", "staging"]
+        );
+        assert_eq!(complete_words(&["deploy", "st"]), vec!["staging"]);
+        assert_eq!(
+            complete_words(&["--dry-run", "deploy", "p"]),
+            vec!["production"]
+        );
+    }
+
+    #[test]
+    fn completes_variables_and_their_choices() {
+        assert_eq!(
+            complete_words(&["deploy", "staging", "--"]),
+            vec!["--env=", "--tag="]
+        );
+        assert_eq!(
+            complete_words(&["deploy", "staging", "--env="]),
+            vec!["--env=staging", "--env=production"]
+        );
+        assert_eq!(
+            complete_words(&["deploy", "staging", "--env=p"]),
+            vec!["--env=production"]
+        );
+        assert_eq!(
+            complete_words(&["deploy", "staging", "--env=staging", "--"]),
+            vec!["--tag="]
+        );
+        assert_eq!(
+            complete_words(&["lane", "--lane="]),
+            vec!["--lane=beta", "--lane=release"]
+        );
+        assert_eq!(
+            complete_words(&["push", "--registry=g"]),
+            vec!["--registry=ghcr.io"]
+        );
+    }
+
+    #[test]
+    fn completes_robin_flags_and_their_values() {
+        assert!(complete_words(&["--dr"]).contains(&"--dry-run".to_string()));
+        assert_eq!(complete_words(&["--platform", "w"]), vec!["windows"]);
+        assert_eq!(complete_words(&["-l", "--format", "y"]), vec!["yaml"]);
+        assert_eq!(complete_words(&["completions", "z"]), vec!["zsh"]);
+    }
+
+    #[test]
+    fn completes_nothing_after_the_passthrough_separator() {
+        assert!(complete_words(&["build", "--", "--"]).is_empty());
+        assert!(complete_words(&["build", "--", "b"]).is_empty());
+    }
+
+    #[test]
+    fn completes_task_names_after_watch() {
+        assert_eq!(
+            complete_words(&["watch", "--watch", "src/**", "t"]),
+            vec!["test"]
+        );
+        assert_eq!(complete_words(&["watch", "--w"]), vec!["--watch"]);
+    }
+
+    #[test]
+    fn completes_the_variables_of_a_task_named_like_a_subcommand() {
+        let words: Vec<String> = ["watch", "--"].iter().map(|w| w.to_string()).collect();
+        let completions = complete(&words, None, |_| {
+            let scripts = serde_json::from_value(json!({ "watch": "tsc -w {{project}}" }));
+            (scripts.unwrap(), Vec::new())
+        });
+        assert_eq!(completions, vec!["--project="]);
+    }
+}
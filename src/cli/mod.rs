@@ -1,3 +1,5 @@
 mod commands;
+mod completions;
 
-pub use commands::{Cli, Commands};
+pub use commands::{Cli, Commands, ListFormat};
+pub use completions::{CompletionShell, complete, complete_line, completion_script};
@@ -0,0 +1,684 @@
+use serde::Serialize;
+use serde_json::ser::PrettyFormatter;
+use serde_json::{Map, Value};
+use std::ops::Range;
+
+use super::format::to_toml;
+use super::json5::{Kind, Member, Node, parse};
+
+/// Rewrites `content`, a JSON document holding `before`, so that it holds
+/// `after`, touching only the members that differ: everything else (member
+/// order, indentation, comments, members `before` doesn't know about) stays
+/// as written.
+/// A member renamed in place keeps its position. New members go after the
+/// member that precedes them in `after`, indented like their siblings.
+///
+/// Objects written on one line are rewritten whole when they change. Returns
+/// `None` when `content` doesn't look like `before`, so the caller can fall
+/// back to writing the document from scratch.
+pub(crate) fn edit_json(content: &str, before: &Value, after: &Value) -> Option<String> {
+    let root = parse(content).ok()?;
+    let unit = match &root.kind {
+        Kind::Object(members) if is_multiline(content, &root) => members
+            .first()
+            .map(|m| indent_at(content, m.key_span.start).to_string()),
+        _ => None,
+    }
+    .filter(|unit| !unit.is_empty())
+    .unwrap_or_else(|| "  ".to_string());
+
+    let mut edits = Vec::new();
+    let mut editor = Editor {
+        content,
+        unit: &unit,
+        edits: &mut edits,
+    };
+    if editor
+        .object(&root, before.as_object()?, after.as_object()?)
+        .is_none()
+    {
+        // A document written on one line stays on one line.
+        if content[root.span.clone()].contains('\n') {
+            return None;
+        }
+        edits = vec![(root.span.clone(), render_inline(after))];
+    }
+    apply(content, edits)
+}
+
+/// Rewrites `content`, a TOML document holding `before`, so that it holds
+/// `after`, the way [`edit_json`] does: only the values that differ are
+/// rewritten, so comments, key order and formatting elsewhere stay as written.
+/// New and changed values are written the way a whole document would be, and
+/// new tables go after their siblings. Returns `None` when `content` doesn't
+/// parse.
+pub(crate) fn edit_toml(content: &str, before: &Value, after: &Value) -> Option<String> {
+    let mut document = content.parse::<toml_edit::DocumentMut>().ok()?;
+    edit_table(
+        document.as_table_mut(),
+        &mut Vec::new(),
+        before.as_object()?,
+        after.as_object()?,
+    )?;
+    Some(document.to_string())
+}
+
+fn edit_table(
+    table: &mut toml_edit::Table,
+    path: &mut Vec<String>,
+    before: &Map<String, Value>,
+    after: &Map<String, Value>,
+) -> Option<()> {
+    let mut removed = Vec::new();
+    for (key, value) in before {
+        if after.get(key).is_none_or(Value::is_null) {
+            if let Some((key, item)) = table.remove_entry(key) {
+                removed.push((value, key, item));
+            }
+        }
+    }
+    for (key, value) in after {
+        if value.is_null() || before.get(key) == Some(value) {
+            continue;
+        }
+        // A renamed key keeps its value as written, and its comments.
+        if let Some(i) = removed.iter().position(|(old, _, _)| *old == value) {
+            let (_, old_key, item) = removed.remove(i);
+            let renamed = toml_edit::Key::new(key).with_leaf_decor(old_key.leaf_decor().clone());
+            table.insert_formatted(&renamed, item);
+            continue;
+        }
+        path.push(key.clone());
+        match (before.get(key), value, table.get_mut(key)) {
+            (
+                Some(Value::Object(old)),
+                Value::Object(new),
+                Some(toml_edit::Item::Table(nested)),
+            ) => {
+                edit_table(nested, path, old, new)?;
+            }
+            (_, _, existing) => {
+                let inline = matches!(existing, Some(toml_edit::Item::Value(_)));
+                let mut item = rendered_item(path, value)?;
+                if inline {
+                    item = toml_edit::Item::Value(item.into_value().ok()?);
+                }
+                table.insert(key, item);
+            }
+        }
+        path.pop();
+    }
+    // A renamed key was inserted last: put it back where `after` has it.
+    let position = |key: &str| after.keys().position(|k| k == key).unwrap_or(usize::MAX);
+    let positions: Vec<usize> = table.iter().map(|(key, _)| position(key)).collect();
+    if !positions.is_sorted() {
+        table.sort_values_by(|a, _, b, _| position(a).cmp(&position(b)));
+    }
+    Some(())
+}
+
+/// `value` written as TOML, as the item at `path` of a document.
+fn rendered_item(path: &[String], value: &Value) -> Option<toml_edit::Item> {
+    let wrapped = path.iter().rev().fold(value.clone(), |value, key| {
+        Value::Object(Map::from_iter([(key.clone(), value)]))
+    });
+    let document = to_toml(&wrapped)?.parse::<toml_edit::DocumentMut>().ok()?;
+    let mut item = path
+        .iter()
+        .try_fold(document.as_item(), |item, key| item.get(key))?
+        .clone();
+    forget_positions(&mut item);
+    Some(item)
+}
+
+/// Clears where the tables in `item` were in the document they were read
+/// from, so they're placed after their siblings in the one they go into, each
+/// after a blank line.
+fn forget_positions(item: &mut toml_edit::Item) {
+    if let toml_edit::Item::Table(table) = item {
+        table.set_position(None);
+        if !table.is_implicit() {
+            table.decor_mut().set_prefix("\n");
+        }
+        for (_, nested) in table.iter_mut() {
+            forget_positions(nested);
+        }
+    }
+}
+
+/// A replacement of the text in `range`.
+type Edit = (Range<usize>, String);
+
+struct Editor<'a> {
+    content: &'a str,
+    /// One level of indentation, as the document uses it.
+    unit: &'a str,
+    edits: &'a mut Vec<Edit>,
+}
+
+impl Editor<'_> {
+    /// Edits the members of a multi-line object in place. `None` when that
+    /// isn't possible (the object is on one line, or nothing of it is kept),
+    /// for the caller to rewrite the object whole.
+    fn object(
+        &mut self,
+        node: &Node,
+        before: &Map<String, Value>,
+        after: &Map<String, Value>,
+    ) -> Option<()> {
+        let Kind::Object(members) = &node.kind else {
+            return None;
+        };
+        if before == after {
+            return Some(());
+        }
+        let member = |key: &str| members.iter().find(|m| m.key == key);
+        if !is_multiline(self.content, node) || !before.keys().all(|key| member(key).is_some()) {
+            return None;
+        }
+
+        let removed: Vec<&String> = before.keys().filter(|k| !after.contains_key(*k)).collect();
+        let added: Vec<&String> = after.keys().filter(|k| !before.contains_key(*k)).collect();
+        // A member swapped for another at the same place is a rename.
+        let renamed: Vec<(&str, &str)> = removed
+            .iter()
+            .filter_map(|from| {
+                let at = before.keys().position(|k| k == *from);
+                let to = added
+                    .iter()
+                    .find(|to| after.keys().position(|k| k == **to) == at)?;
+                Some((from.as_str(), to.as_str()))
+            })
+            .collect();
+        // Where each member of `after` is in the file.
+        let in_file = |key: &str| match renamed.iter().find(|(_, to)| *to == key) {
+            Some((from, _)) => member(from),
+            None if before.contains_key(key) => member(key),
+            None => None,
+        };
+
+        let kept: Vec<&Member> = members
+            .iter()
+            .filter(|m| {
+                !before.contains_key(&m.key)
+                    || after.contains_key(&m.key)
+                    || renamed.iter().any(|(from, _)| *from == m.key)
+            })
+            .collect();
+        if kept.is_empty() {
+            return None;
+        }
+        let indent = indent_at(self.content, members[0].key_span.start).to_string();
+
+        for (key, value) in after {
+            let Some(old) = in_file(key) else {
+                continue;
+            };
+            if old.key != *key {
+                self.edits.push((old.key_span.clone(), self.key(old, key)));
+            }
+            if before[&old.key] != *value {
+                self.value(old, &before[&old.key], value);
+            }
+        }
+
+        for old in members.iter().filter(|m| removed.contains(&&m.key)) {
+            if renamed.iter().any(|(from, _)| *from == old.key) {
+                continue;
+            }
+            self.remove(old)?;
+        }
+        // When the file's last member goes, the one now last mustn't keep a
+        // comma the file didn't have after its last member, unless new
+        // members follow it.
+        let last = members.last()?;
+        let new_last = members
+            .iter()
+            .rev()
+            .find(|m| kept.iter().any(|k| std::ptr::eq(*k, *m)))?;
+        let trailing_comma = last.comma.is_some();
+        let mut drop_comma = !std::ptr::eq(new_last, last) && !trailing_comma;
+
+        // New members go after the one preceding them in `after`, or before
+        // the first member when none does.
+        let mut groups: Vec<(Option<&Member>, Vec<String>)> = vec![(None, Vec::new())];
+        for (key, value) in after {
+            match in_file(key) {
+                Some(m) => groups.push((Some(m), Vec::new())),
+                None => groups.last_mut().unwrap().1.push(format!(
+                    "{}: {}",
+                    json_string(key),
+                    render_pretty(value, &indent, self.unit)
+                )),
+            }
+        }
+        for (anchor, added) in groups.iter().filter(|(_, added)| !added.is_empty()) {
+            match anchor {
+                Some(m) => {
+                    let ends = std::ptr::eq(*m, new_last);
+                    self.insert_after(m, added, &indent, ends && !trailing_comma);
+                    drop_comma &= !ends;
+                }
+                None => {
+                    let first = kept.iter().min_by_key(|m| m.key_span.start)?;
+                    self.insert_before(first, added, &indent);
+                }
+            }
+        }
+        if let Some(comma) = new_last.comma.filter(|_| drop_comma) {
+            self.edits.push((comma..comma + 1, String::new()));
+        }
+        Some(())
+    }
+
+    /// Writes new members on lines of their own after `member` (and the
+    /// comment that ends its line), with a comma after every one of them but
+    /// the last when `last` says the object ends there.
+    fn insert_after(&mut self, member: &Member, added: &[String], indent: &str, last: bool) {
+        let end = member.comma.map_or(member.value.span.end, |c| c + 1);
+        match self.line_end(end) {
+            Some(line_end) => {
+                if member.comma.is_none() {
+                    let at = member.value.span.end;
+                    self.edits.push((at..at, ",".to_string()));
+                }
+                let lines: String = added
+                    .iter()
+                    .enumerate()
+                    .map(|(i, m)| {
+                        let comma = if last && i + 1 == added.len() {
+                            ""
+                        } else {
+                            ","
+                        };
+                        format!("{}{}{}\n", indent, m, comma)
+                    })
+                    .collect();
+                self.edits.push((line_end..line_end, lines));
+            }
+            None => {
+                let at = member.value.span.end;
+                let text: String = added
+                    .iter()
+                    .map(|m| format!(",\n{}{}", indent, m))
+                    .collect();
+                self.edits.push((at..at, text));
+                if let Some(comma) = member.comma.filter(|_| last) {
+                    self.edits.push((comma..comma + 1, String::new()));
+                }
+            }
+        }
+    }
+
+    /// Writes new members before `member` and the comments above it.
+    fn insert_before(&mut self, member: &Member, added: &[String], indent: &str) {
+        let edit = match self.line_start(member.key_span.start) {
+            Some(start) => {
+                let at = self.comments_above(start);
+                let lines = added.iter().map(|m| format!("{}{},\n", indent, m));
+                (at..at, lines.collect())
+            }
+            None => {
+                let at = member.key_span.start;
+                let text = added.iter().map(|m| format!("{},\n{}", m, indent));
+                (at..at, text.collect())
+            }
+        };
+        self.edits.push(edit);
+    }
+
+    /// `key` written for `member`: bare like its old key when that was an
+    /// unquoted JSON5 key and `key` can be one too.
+    fn key(&self, member: &Member, key: &str) -> String {
+        let bare = !self.content[member.key_span.clone()].starts_with(['"', '\'']);
+        let identifier = key.starts_with(|c: char| c.is_alphabetic() || c == '_' || c == '$')
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '$');
+        match bare && identifier {
+            true => key.to_string(),
+            false => json_string(key),
+        }
+    }
+
+    /// Replaces the value of `member`, editing it in place when both are
+    /// objects.
+    fn value(&mut self, member: &Member, before: &Value, after: &Value) {
+        if let (Value::Object(b), Value::Object(a)) = (before, after) {
+            let mut nested = Vec::new();
+            let mut editor = Editor {
+                content: self.content,
+                unit: self.unit,
+                edits: &mut nested,
+            };
+            if editor.object(&member.value, b, a).is_some() {
+                self.edits.extend(nested);
+                return;
+            }
+        }
+        let written = &self.content[member.value.span.clone()];
+        // Objects and arrays written on one line stay on one line.
+        let inline = !written.contains('\n')
+            && match &member.value.kind {
+                Kind::Object(members) => !members.is_empty(),
+                Kind::Array(items) => !items.is_empty(),
+                Kind::Scalar(_) => false,
+            };
+        let rendered = match inline {
+            true => render_inline(after),
+            false => render_pretty(
+                after,
+                indent_at(self.content, member.key_span.start),
+                self.unit,
+            ),
+        };
+        self.edits.push((member.value.span.clone(), rendered));
+    }
+
+    /// Removes the lines `member` is written on, with the comments on the
+    /// lines right above it and at the end of its last line. `None` when it
+    /// shares its lines with something else.
+    fn remove(&mut self, member: &Member) -> Option<()> {
+        let start = self.line_start(member.key_span.start)?;
+        let end = self.line_end(member.comma.map_or(member.value.span.end, |c| c + 1))?;
+        self.edits
+            .push((self.comments_above(start)..end, String::new()));
+        Some(())
+    }
+
+    /// The start of the line `offset` is on, when only indentation precedes
+    /// it there.
+    fn line_start(&self, offset: usize) -> Option<usize> {
+        let start = self.content[..offset].rfind('\n').map_or(0, |i| i + 1);
+        self.content[start..offset]
+            .bytes()
+            .all(|b| matches!(b, b' ' | b'\t'))
+            .then_some(start)
+    }
+
+    /// The start of the next line, when only whitespace or a comment follows
+    /// `offset` on its line.
+    fn line_end(&self, offset: usize) -> Option<usize> {
+        let rest = &self.content[offset..];
+        let mut line = rest.trim_start_matches([' ', '\t', '\r']);
+        if let Some(comment) = line.strip_prefix("/*") {
+            let close = comment.find("*/")?;
+            line = comment[close + 2..].trim_start_matches([' ', '\t', '\r']);
+        }
+        let newline = match line.starts_with("//") {
+            true => line.find('\n')?,
+            false => line.starts_with('\n').then_some(0)?,
+        };
+        let end = offset + (rest.len() - line.len()) + newline + 1;
+        (!self.content[offset..end - 1].contains('\n')).then_some(end)
+    }
+
+    /// The start of the run of comment lines right above the line starting
+    /// at `line_start`: the comments that describe what is on it.
+    fn comments_above(&self, mut line_start: usize) -> usize {
+        while line_start > 0 {
+            let previous = self.content[..line_start - 1]
+                .rfind('\n')
+                .map_or(0, |i| i + 1);
+            let text = self.content[previous..line_start - 1].trim();
+            let comment =
+                text.starts_with("//") || (text.starts_with("/*") && text.ends_with("*/"));
+            if !comment {
+                break;
+            }
+            line_start = previous;
+        }
+        line_start
+    }
+}
+
+/// Applies non-overlapping edits to `content`.
+fn apply(content: &str, mut edits: Vec<Edit>) -> Option<String> {
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut out = String::with_capacity(content.len());
+    let mut pos = 0;
+    for (range, text) in edits {
+        if range.start < pos {
+            return None;
+        }
+        out.push_str(&content[pos..range.start]);
+        out.push_str(&text);
+        pos = range.end;
+    }
+    out.push_str(&content[pos..]);
+    Some(out)
+}
+
+/// Whether the object's first member is on a line of its own.
+fn is_multiline(content: &str, node: &Node) -> bool {
+    match &node.kind {
+        Kind::Object(members) => members
+            .first()
+            .is_some_and(|m| content[node.span.start..m.key_span.start].contains('\n')),
+        _ => false,
+    }
+}
+
+/// The whitespace the line holding `offset` starts with.
+fn indent_at(content: &str, offset: usize) -> &str {
+    let start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &content[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn json_string(s: &str) -> String {
+    Value::String(s.to_string()).to_string()
+}
+
+/// `value` as pretty JSON, for a member whose line starts with `indent`.
+fn render_pretty(value: &Value, indent: &str, unit: &str) -> String {
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(
+        &mut out,
+        PrettyFormatter::with_indent(unit.as_bytes()),
+    );
+    value
+        .serialize(&mut serializer)
+        .expect("a JSON value always serializes");
+    String::from_utf8(out)
+        .expect("JSON is UTF-8")
+        .replace('\n', &format!("\n{}", indent))
+}
+
+/// `value` as JSON on one line: `{ "cmd": "make", "deps": ["a", "b"] }`.
+fn render_inline(value: &Value) -> String {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            let members: Vec<String> = map
+                .iter()
+                .map(|(key, value)| format!("{}: {}", json_string(key), render_inline(value)))
+                .collect();
+            format!("{{ {} }}", members.join(", "))
+        }
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(render_inline).collect();
+            format!("[{}]", items.join(", "))
+        }
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigFormat;
+    use serde_json::json;
+
+    /// `content` edited by `change`, which gets its data.
+    fn edit(content: &str, change: impl FnOnce(&mut Map<String, Value>)) -> String {
+        let before = parse(content).unwrap().into_value();
+        let mut after = before.clone();
+        change(after.as_object_mut().unwrap());
+        let edited = edit_json(content, &before, &after).expect("editable in place");
+        assert_eq!(parse(&edited).unwrap().into_value(), after);
+        edited
+    }
+
+    fn scripts(config: &mut Map<String, Value>) -> &mut Map<String, Value> {
+        config["scripts"].as_object_mut().unwrap()
+    }
+
+    const CONFIG: &str = r#"{
+    "$schema": "https://example.com/robin.json",
+    "scripts": {
+        "zeta": "cargo zeta",
+        "alpha": { "cmd": "cargo alpha", "desc": "First" },
+        "mid": [
+            "a",
+            "b"
+        ]
+    },
+    "x-editor": true
+}
+"#;
+
+    #[test]
+    fn added_members_go_last_with_their_siblings_indentation() {
+        let edited = edit(CONFIG, |config| {
+            scripts(config).insert("new".to_string(), json!("echo new"));
+        });
+        assert_eq!(
+            edited,
+            CONFIG.replace(
+                "            \"b\"\n        ]\n",
+                "            \"b\"\n        ],\n        \"new\": \"echo new\"\n"
+            )
+        );
+    }
+
+    #[test]
+    fn removed_members_take_their_lines_and_a_dangling_comma_with_them() {
+        let edited = edit(CONFIG, |config| {
+            scripts(config).shift_remove("alpha");
+        });
+        assert_eq!(
+            edited,
+            CONFIG.replace(
+                "        \"alpha\": { \"cmd\": \"cargo alpha\", \"desc\": \"First\" },\n",
+                ""
+            )
+        );
+
+        let edited = edit(CONFIG, |config| {
+            scripts(config).shift_remove("mid");
+        });
+        assert!(edited.contains("\"desc\": \"First\" }\n    },"), "{edited}");
+        assert!(!edited.contains("mid"));
+    }
+
+    #[test]
+    fn renamed_members_keep_their_place() {
+        let edited = edit(CONFIG, |config| {
+            let scripts = scripts(config);
+            let value = scripts.shift_remove("alpha").unwrap();
+            scripts.shift_insert(1, "beta".to_string(), value);
+        });
+        assert_eq!(edited, CONFIG.replace("\"alpha\"", "\"beta\""));
+    }
+
+    #[test]
+    fn comments_and_trailing_commas_survive_edits() {
+        let content = r#"{
+  // Shared tasks
+  scripts: {
+    // Needs the nightly toolchain.
+    fmt: "cargo +nightly fmt", // fast
+    /* CI only */
+    "lint": "cargo clippy",
+  },
+}
+"#;
+        let edited = edit(content, |config| {
+            let scripts = scripts(config);
+            let value = scripts.shift_remove("fmt").unwrap();
+            scripts.shift_insert(0, "format".to_string(), value);
+            scripts.insert("test".to_string(), json!("cargo test"));
+        });
+        assert_eq!(
+            edited,
+            content.replace("fmt:", "format:").replace(
+                "\"lint\": \"cargo clippy\",\n",
+                "\"lint\": \"cargo clippy\",\n    \"test\": \"cargo test\",\n"
+            )
+        );
+
+        let edited = edit(content, |config| {
+            scripts(config).shift_remove("fmt");
+        });
+        assert_eq!(
+            edited,
+            "{\n  // Shared tasks\n  scripts: {\n    /* CI only */\n    \"lint\": \"cargo clippy\",\n  },\n}\n"
+        );
+    }
+
+    #[test]
+    fn toml_edits_keep_comments_and_order() {
+        let content = r#"# Shared tasks
+[scripts]
+zeta = "cargo zeta" # the last one
+alpha = { cmd = "cargo alpha", desc = "First" }
+
+# Release
+[scripts.ship]
+cmd = "cargo publish"
+desc = "Publish"
+"#;
+        let toml = |content: &str| ConfigFormat::Toml.parse(content).unwrap();
+        let before = toml(content);
+        let mut after = before.clone();
+        let scripts = after["scripts"].as_object_mut().unwrap();
+        let zeta = scripts.shift_remove("zeta").unwrap();
+        scripts.shift_insert(0, "omega".to_string(), zeta);
+        scripts["alpha"]["desc"] = json!("Renamed");
+        scripts["ship"]["desc"] = json!("Publish the crate");
+        scripts.insert("lint".to_string(), json!({ "cmd": "cargo clippy" }));
+
+        let edited = edit_toml(content, &before, &after).unwrap();
+        assert_eq!(toml(&edited), after);
+        assert_eq!(
+            edited,
+            r#"# Shared tasks
+[scripts]
+omega = "cargo zeta" # the last one
+alpha = { cmd = "cargo alpha", desc = "Renamed" }
+
+# Release
+[scripts.ship]
+cmd = "cargo publish"
+desc = "Publish the crate"
+
+[scripts.lint]
+cmd = "cargo clippy"
+"#
+        );
+    }
+
+    #[test]
+    fn changed_values_keep_their_style() {
+        let edited = edit(CONFIG, |config| {
+            let scripts = scripts(config);
+            scripts["zeta"] = json!({ "cmd": "cargo zeta", "desc": "" });
+            scripts["alpha"]["desc"] = json!("Renamed");
+        });
+        assert_eq!(
+            edited,
+            CONFIG
+                .replace(
+                    "\"zeta\": \"cargo zeta\"",
+                    "\"zeta\": {\n            \"cmd\": \"cargo zeta\",\n            \"desc\": \"\"\n        }"
+                )
+                .replace("\"First\"", "\"Renamed\"")
+        );
+
+        let one_line = "{ \"scripts\": { \"a\": \"x\" } }\n";
+        let edited = edit(one_line, |config| {
+            scripts(config).insert("b".to_string(), json!("y"));
+        });
+        assert_eq!(edited, "{ \"scripts\": { \"a\": \"x\", \"b\": \"y\" } }\n");
+    }
+}
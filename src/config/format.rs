@@ -0,0 +1,385 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::fmt;
+use std::path::Path;
+
+use super::json5::{self, Node};
+
+/// The config file names robin looks for in each directory, in order of
+/// precedence: when a directory holds several, the first one wins.
+pub const CONFIG_FILES: &[&str] = &[".robin.json", ".robin.yaml", ".robin.yml", "robin.toml"];
+
+/// The personal overrides file robin looks for next to the config, in order of
+/// precedence. It's meant to be git-ignored: its tasks and variables replace or
+/// extend the shared ones.
+pub const LOCAL_CONFIG_FILES: &[&str] = &[
+    ".robin.local.json",
+    ".robin.local.yaml",
+    ".robin.local.yml",
+    "robin.local.toml",
+];
+
+/// The formats a config can be written in. Every format maps to the same
+/// [`RobinConfig`](super::RobinConfig) model; the file extension decides which
+/// one a file is in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConfigFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+/// A config file that can't be parsed, with where the parser stopped when it
+/// says.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub message: String,
+    /// The 1-based line and column of the error.
+    pub position: Option<(usize, usize)>,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => {
+                write!(f, "{} at line {} column {}", self.message, line, column)
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
+impl ConfigFormat {
+    /// The format of the config at `path`: YAML for `.yaml`/`.yml`, TOML for
+    /// `.toml`, JSON otherwise.
+    pub fn of(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+        }
+    }
+
+    /// The file name a project config in this format gets.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Json => ".robin.json",
+            Self::Yaml => ".robin.yaml",
+            Self::Toml => "robin.toml",
+        }
+    }
+
+    /// Parses `content` into a JSON value, whatever the format. JSON is read
+    /// leniently, with the comments and trailing commas of JSONC and the
+    /// syntax JSON5 adds (see [`json5::parse`]).
+    pub fn parse(self, content: &str) -> Result<Value, SyntaxError> {
+        match self {
+            Self::Json => json5::parse(content).map(Node::into_value),
+            Self::Yaml => serde_norway::from_str(content).map_err(|e| SyntaxError {
+                position: e.location().map(|l| (l.line(), l.column())),
+                message: strip_position(e.to_string()),
+            }),
+            Self::Toml => content
+                .parse::<toml_edit::DocumentMut>()
+                .map(|document| table_to_json(document.as_table()))
+                .map_err(|e| SyntaxError {
+                    message: e.message().to_string(),
+                    position: e.span().map(|span| line_column(content, span.start)),
+                }),
+        }
+    }
+
+    /// The comments in `content`, each as written, as far as this format's
+    /// parser tells: a `#` inside a string (`"echo #1"`, a YAML block scalar)
+    /// or one YAML reads as part of a value (`https://x/#a`) isn't one. Nothing
+    /// when `content` doesn't parse.
+    pub fn comments(self, content: &str) -> Vec<String> {
+        if self == Self::Json {
+            return json5::comments(content)
+                .into_iter()
+                .map(|span| content[span].to_string())
+                .collect();
+        }
+        // YAML and TOML parsers don't report comments, so a `#` starts one
+        // when cutting the line there leaves the parsed document unchanged.
+        let Ok(parsed) = self.parse(content) else {
+            return Vec::new();
+        };
+        let mut comments = Vec::new();
+        let mut line_start = 0;
+        for line in content.split_inclusive('\n') {
+            let line_end = line_start + line.trim_end_matches(['\n', '\r']).len();
+            for (i, _) in line.match_indices('#') {
+                let hash = line_start + i;
+                let cut = format!("{}{}", &content[..hash], &content[line_end..]);
+                if self.parse(&cut).is_ok_and(|value| value == parsed) {
+                    comments.push(content[hash..line_end].to_string());
+                    break;
+                }
+            }
+            line_start += line.len();
+        }
+        comments
+    }
+
+    /// Serializes `value` in this format.
+    pub fn render<T: Serialize>(self, value: &T) -> Result<String> {
+        let rendered = match self {
+            Self::Json => serde_json::to_string_pretty(value)?,
+            Self::Yaml => serde_norway::to_string(value)?,
+            Self::Toml => to_toml(&serde_json::to_value(value)?)
+                .context("Only an object can be written as TOML")?,
+        };
+        Ok(rendered)
+    }
+}
+
+/// Drops the " at line X column Y" serde_norway puts in its messages, for when
+/// the position is reported separately.
+fn strip_position(message: String) -> String {
+    Regex::new(r" at line \d+ column \d+")
+        .unwrap()
+        .replace_all(&message, "")
+        .into_owned()
+}
+
+/// The 1-based line and column (in characters) of a byte offset.
+pub(crate) fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+fn table_to_json(table: &toml_edit::Table) -> Value {
+    Value::Object(
+        table
+            .iter()
+            .filter_map(|(key, item)| item_to_json(item).map(|v| (key.to_string(), v)))
+            .collect(),
+    )
+}
+
+fn item_to_json(item: &toml_edit::Item) -> Option<Value> {
+    match item {
+        toml_edit::Item::None => None,
+        toml_edit::Item::Value(value) => Some(value_to_json(value)),
+        toml_edit::Item::Table(table) => Some(table_to_json(table)),
+        toml_edit::Item::ArrayOfTables(tables) => {
+            Some(Value::Array(tables.iter().map(table_to_json).collect()))
+        }
+    }
+}
+
+fn value_to_json(value: &toml_edit::Value) -> Value {
+    use toml_edit::Value as Toml;
+    match value {
+        Toml::String(s) => Value::String(s.value().clone()),
+        Toml::Integer(i) => Value::from(*i.value()),
+        Toml::Float(f) => Value::from(*f.value()),
+        Toml::Boolean(b) => Value::Bool(*b.value()),
+        Toml::Datetime(d) => Value::String(d.value().to_string()),
+        Toml::Array(items) => Value::Array(items.iter().map(value_to_json).collect()),
+        Toml::InlineTable(table) => Value::Object(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), value_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Writes a JSON object as a TOML document: plain values first, then a
+/// `[table]` for each nested object. Objects inside arrays become inline
+/// tables, and `null`s, which TOML has no way to write, are left out.
+pub(super) fn to_toml(value: &Value) -> Option<String> {
+    let mut out = String::new();
+    write_table(&mut out, &[], value.as_object()?);
+    Some(out)
+}
+
+fn write_table(out: &mut String, path: &[String], table: &Map<String, Value>) {
+    let (tables, values): (Vec<_>, Vec<_>) = table
+        .iter()
+        .filter(|(_, value)| !value.is_null())
+        .partition(|(_, value)| value.is_object());
+
+    if !path.is_empty() && (!values.is_empty() || tables.is_empty()) {
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let header: Vec<String> = path.iter().map(|k| toml_key(k)).collect();
+        out.push_str(&format!("[{}]\n", header.join(".")));
+    }
+    for (key, value) in values {
+        out.push_str(&format!("{} = {}\n", toml_key(key), toml_value(value)));
+    }
+    for (key, value) in tables {
+        let mut nested = path.to_vec();
+        nested.push(key.clone());
+        write_table(out, &nested, value.as_object().unwrap());
+    }
+}
+
+fn toml_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    match bare {
+        true => key.to_string(),
+        false => toml_string(key),
+    }
+}
+
+fn toml_value(value: &Value) -> String {
+    match value {
+        Value::String(s) if s.contains('\n') => toml_multiline_string(s),
+        Value::String(s) => toml_string(s),
+        Value::Array(items) => {
+            let items: Vec<String> = items
+                .iter()
+                .filter(|item| !item.is_null())
+                .map(toml_value)
+                .collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Object(map) => {
+            let fields: Vec<String> = map
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| format!("{} = {}", toml_key(key), toml_value(value)))
+                .collect();
+            match fields.is_empty() {
+                true => "{}".to_string(),
+                false => format!("{{ {} }}", fields.join(", ")),
+            }
+        }
+        other => other.to_string(),
+    }
+}
+
+fn toml_string(s: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A `"""` string, so that scripts keep their lines. The newline right after
+/// the opening quotes is dropped by TOML parsers.
+fn toml_multiline_string(s: &str) -> String {
+    let mut body = String::new();
+    for c in s.chars() {
+        match c {
+            '\\' => body.push_str("\\\\"),
+            '"' => body.push_str("\\\""),
+            '\n' | '\t' => body.push(c),
+            c if c.is_control() => body.push_str(&format!("\\u{:04X}", c as u32)),
+            c => body.push(c),
+        }
+    }
+    format!("\"\"\"\n{}\"\"\"", body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(
+            ConfigFormat::of(Path::new(".robin.json")),
+            ConfigFormat::Json
+        );
+        assert_eq!(
+            ConfigFormat::of(Path::new("a/.robin.yml")),
+            ConfigFormat::Yaml
+        );
+        assert_eq!(
+            ConfigFormat::of(Path::new("robin.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(ConfigFormat::of(Path::new("robin")), ConfigFormat::Json);
+    }
+
+    #[test]
+    fn every_format_round_trips_a_config() {
+        let config = json!({
+            "$schema": "https://example.com/robin.schema.json",
+            "include": ["base.json"],
+            "scripts": {
+                "build": "cargo build",
+                "check": ["@build", "cargo test -- \"{{@}}\""],
+                "setup": {
+                    "script": "set -e\ncd app\nnpm ci\n",
+                    "desc": "Install \\ deps",
+                    "env": { "CI": "1", "RETRIES": 3 },
+                    "cmd": null
+                },
+                "open docs": { "cmd": { "macos": "open d", "linux": "xdg-open d" } },
+                "deploy": {
+                    "cmd": "deploy {{env}}",
+                    "parallel": false,
+                    "vars": { "env": { "type": "enum", "choices": ["a", "b"] } }
+                }
+            }
+        });
+        let mut expected = config.clone();
+        expected["scripts"]["setup"]
+            .as_object_mut()
+            .unwrap()
+            .remove("cmd");
+
+        for format in [ConfigFormat::Json, ConfigFormat::Yaml, ConfigFormat::Toml] {
+            let rendered = format.render(&config).unwrap();
+            let mut parsed = format.parse(&rendered).unwrap();
+            if format != ConfigFormat::Toml {
+                parsed["scripts"]["setup"]
+                    .as_object_mut()
+                    .unwrap()
+                    .remove("cmd");
+            }
+            assert_eq!(parsed, expected, "{}:\n{}", format.name(), rendered);
+        }
+    }
+
+    #[test]
+    fn syntax_errors_say_where_they_are() {
+        let error = |format: ConfigFormat, content: &str| format.parse(content).unwrap_err();
+
+        let json = error(ConfigFormat::Json, "{\n  \"a\": 1\n  \"b\": 2\n}");
+        assert_eq!(json.position, Some((3, 3)));
+        assert_eq!(json.message, "expected `,` or `}`");
+
+        let yaml = error(ConfigFormat::Yaml, "scripts:\n  build: [cargo\n");
+        assert_eq!(yaml.position.map(|(line, _)| line), Some(3));
+        assert!(!yaml.message.contains("at line"), "{}", yaml.message);
+
+        let toml = error(ConfigFormat::Toml, "[scripts]\nbuild = cargo build\n");
+        assert_eq!(toml.position, Some((2, 9)));
+    }
+}
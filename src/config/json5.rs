@@ -0,0 +1,573 @@
+use serde_json::{Map, Number, Value};
+use std::ops::Range;
+
+use super::SyntaxError;
+use super::line_column;
+
+/// A value read from a JSON document, with where it is, and for objects and
+/// arrays, where the values in them are.
+pub(crate) struct Node {
+    pub span: Range<usize>,
+    pub kind: Kind,
+}
+
+pub(crate) enum Kind {
+    Object(Vec<Member>),
+    Array(Vec<Node>),
+    Scalar(Value),
+}
+
+pub(crate) struct Member {
+    pub key: String,
+    pub key_span: Range<usize>,
+    pub value: Node,
+    /// The offset of the comma that follows the member, if any.
+    pub comma: Option<usize>,
+}
+
+impl Node {
+    pub fn into_value(self) -> Value {
+        match self.kind {
+            Kind::Object(members) => Value::Object(
+                members
+                    .into_iter()
+                    .map(|m| (m.key, m.value.into_value()))
+                    .collect::<Map<_, _>>(),
+            ),
+            Kind::Array(items) => Value::Array(items.into_iter().map(Node::into_value).collect()),
+            Kind::Scalar(value) => value,
+        }
+    }
+}
+
+/// Reads a JSON document, also accepting what JSONC and JSON5 add to JSON:
+/// `//` and `/* */` comments, trailing commas, unquoted keys, single-quoted
+/// strings, hexadecimal numbers and numbers with a leading `+` or `.`.
+pub(crate) fn parse(content: &str) -> Result<Node, SyntaxError> {
+    Parser::new(content).document()
+}
+
+/// Where the `//` and `/* */` comments of `content` are, or nothing when it
+/// doesn't parse.
+pub(crate) fn comments(content: &str) -> Vec<Range<usize>> {
+    let mut parser = Parser::new(content);
+    match parser.document() {
+        Ok(_) => parser.comments,
+        Err(_) => Vec::new(),
+    }
+}
+
+/// The byte offset in `content` of the value at `path`, or of its key when it
+/// is an object member, so that the position points at the name of the
+/// offending field.
+pub(crate) fn locate(content: &str, path: &[String]) -> Option<usize> {
+    let mut node = &parse(content).ok()?;
+    let mut located = node.span.start;
+    for segment in path {
+        match &node.kind {
+            Kind::Object(members) => {
+                let member = members.iter().rev().find(|m| m.key == *segment)?;
+                located = member.key_span.start;
+                node = &member.value;
+            }
+            Kind::Array(items) => {
+                node = items.get(segment.parse::<usize>().ok()?)?;
+                located = node.span.start;
+            }
+            Kind::Scalar(_) => return None,
+        }
+    }
+    Some(located)
+}
+
+/// The keys that appear more than once in the same object of `content`, each
+/// as the path to the object with the repeated key, and the offset of every
+/// repeat. Only the last of them is kept when the document is read.
+pub(crate) fn duplicate_keys(content: &str) -> Vec<(Vec<String>, usize)> {
+    fn walk(node: &Node, path: &mut Vec<String>, out: &mut Vec<(Vec<String>, usize)>) {
+        match &node.kind {
+            Kind::Object(members) => {
+                for (i, member) in members.iter().enumerate() {
+                    path.push(member.key.clone());
+                    if members[..i].iter().any(|m| m.key == member.key) {
+                        out.push((path.clone(), member.key_span.start));
+                    }
+                    walk(&member.value, path, out);
+                    path.pop();
+                }
+            }
+            Kind::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i.to_string());
+                    walk(item, path, out);
+                    path.pop();
+                }
+            }
+            Kind::Scalar(_) => {}
+        }
+    }
+    let mut found = Vec::new();
+    if let Ok(node) = parse(content) {
+        walk(&node, &mut Vec::new(), &mut found);
+    }
+    found
+}
+
+struct Parser<'a> {
+    content: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+    /// The comments skipped so far.
+    comments: Vec<Range<usize>>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(content: &'a str) -> Self {
+        Self {
+            content,
+            bytes: content.as_bytes(),
+            pos: 0,
+            comments: Vec::new(),
+        }
+    }
+
+    fn document(&mut self) -> Result<Node, SyntaxError> {
+        self.skip_trivia()?;
+        let node = self.value()?;
+        self.skip_trivia()?;
+        if self.pos < self.bytes.len() {
+            return Err(self.error("trailing characters"));
+        }
+        Ok(node)
+    }
+
+    fn error(&self, message: &str) -> SyntaxError {
+        self.error_at(self.pos, message)
+    }
+
+    fn error_at(&self, pos: usize, message: &str) -> SyntaxError {
+        SyntaxError {
+            message: message.to_string(),
+            position: Some(line_column(self.content, pos)),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next(&mut self, expected: &str) -> Result<u8, SyntaxError> {
+        let byte = self
+            .peek()
+            .ok_or_else(|| self.error(&format!("EOF while parsing {}", expected)))?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_trivia(&mut self) -> Result<(), SyntaxError> {
+        loop {
+            match (self.peek(), self.bytes.get(self.pos + 1)) {
+                (Some(b), _) if b.is_ascii_whitespace() => self.pos += 1,
+                (Some(b'/'), Some(b'/')) => {
+                    let start = self.pos;
+                    while self.peek().is_some_and(|b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                    self.comments.push(start..self.pos);
+                }
+                (Some(b'/'), Some(b'*')) => {
+                    let start = self.pos;
+                    let end = self.content[self.pos + 2..]
+                        .find("*/")
+                        .ok_or_else(|| self.error_at(start, "unterminated comment"))?;
+                    self.pos += end + 4;
+                    self.comments.push(start..self.pos);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Reads the comma after a member or item, if there is one, up to what
+    /// follows it.
+    fn separator(&mut self) -> Result<Option<usize>, SyntaxError> {
+        self.skip_trivia()?;
+        let comma = (self.peek() == Some(b',')).then_some(self.pos);
+        if comma.is_some() {
+            self.pos += 1;
+            self.skip_trivia()?;
+        }
+        Ok(comma)
+    }
+
+    fn value(&mut self) -> Result<Node, SyntaxError> {
+        let start = self.pos;
+        let kind = match self.peek() {
+            None => return Err(self.error("EOF while parsing a value")),
+            Some(b'{') => self.object()?,
+            Some(b'[') => self.array()?,
+            Some(quote @ (b'"' | b'\'')) => Kind::Scalar(Value::String(self.string(quote)?)),
+            Some(_) => Kind::Scalar(self.literal()?),
+        };
+        Ok(Node {
+            span: start..self.pos,
+            kind,
+        })
+    }
+
+    fn object(&mut self) -> Result<Kind, SyntaxError> {
+        self.pos += 1;
+        self.skip_trivia()?;
+        let mut members = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b'}') => break,
+                None => return Err(self.error("EOF while parsing an object")),
+                _ => {}
+            }
+            let key_start = self.pos;
+            let key = match self.peek() {
+                Some(quote @ (b'"' | b'\'')) => self.string(quote)?,
+                _ => self.identifier()?,
+            };
+            let key_span = key_start..self.pos;
+            self.skip_trivia()?;
+            if self.next("an object")? != b':' {
+                return Err(self.error_at(self.pos - 1, "expected `:`"));
+            }
+            self.skip_trivia()?;
+            let value = self.value()?;
+            let comma = self.separator()?;
+            members.push(Member {
+                key,
+                key_span,
+                value,
+                comma,
+            });
+            if comma.is_none() && self.peek().is_some_and(|b| b != b'}') {
+                return Err(self.error("expected `,` or `}`"));
+            }
+        }
+        self.pos += 1;
+        Ok(Kind::Object(members))
+    }
+
+    fn array(&mut self) -> Result<Kind, SyntaxError> {
+        self.pos += 1;
+        self.skip_trivia()?;
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b']') => break,
+                None => return Err(self.error("EOF while parsing a list")),
+                _ => {}
+            }
+            items.push(self.value()?);
+            if self.separator()?.is_none() && self.peek().is_some_and(|b| b != b']') {
+                return Err(self.error("expected `,` or `]`"));
+            }
+        }
+        self.pos += 1;
+        Ok(Kind::Array(items))
+    }
+
+    /// An unquoted key, as JSON5 allows: letters, digits, `_` and `$`.
+    fn identifier(&mut self) -> Result<String, SyntaxError> {
+        let start = self.pos;
+        let rest = &self.content[start..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+            .unwrap_or(rest.len());
+        if len == 0 || rest.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(self.error("key must be a string"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+
+    fn string(&mut self, quote: u8) -> Result<String, SyntaxError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let rest = &self.content[self.pos..];
+            let plain = rest
+                .find([quote as char, '\\', '\n'])
+                .ok_or_else(|| self.error_at(start, "unterminated string"))?;
+            out.push_str(&rest[..plain]);
+            self.pos += plain;
+            match self.next("a string")? {
+                b'\n' => return Err(self.error_at(start, "unterminated string")),
+                b'\\' => self.escape(&mut out)?,
+                _ => return Ok(out),
+            }
+        }
+    }
+
+    fn escape(&mut self, out: &mut String) -> Result<(), SyntaxError> {
+        let start = self.pos - 1;
+        let c = self.content[self.pos..]
+            .chars()
+            .next()
+            .ok_or_else(|| self.error("EOF while parsing a string"))?;
+        self.pos += c.len_utf8();
+        match c {
+            'b' => out.push('\u{8}'),
+            'f' => out.push('\u{c}'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'v' => out.push('\u{b}'),
+            '0' => out.push('\0'),
+            // A backslash at the end of a line continues the string.
+            '\n' => {}
+            '\r' => {
+                if self.peek() == Some(b'\n') {
+                    self.pos += 1;
+                }
+            }
+            'x' => out.push(self.hex_char(2, start)?),
+            'u' => {
+                let unpaired =
+                    |parser: &Self| parser.error_at(start, "unpaired surrogate in escape");
+                let high = self.hex(4, start)?;
+                let code = match high {
+                    // A character past U+FFFF is written as a surrogate pair.
+                    0xD800..=0xDBFF => {
+                        if !self.content[self.pos..].starts_with("\\u") {
+                            return Err(unpaired(self));
+                        }
+                        self.pos += 2;
+                        let low = self.hex(4, start)?;
+                        if !(0xDC00..=0xDFFF).contains(&low) {
+                            return Err(unpaired(self));
+                        }
+                        0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                    }
+                    0xDC00..=0xDFFF => return Err(unpaired(self)),
+                    code => code,
+                };
+                out.push(
+                    char::from_u32(code)
+                        .ok_or_else(|| self.error_at(start, "invalid unicode code point"))?,
+                );
+            }
+            other => out.push(other),
+        }
+        Ok(())
+    }
+
+    fn hex(&mut self, digits: usize, start: usize) -> Result<u32, SyntaxError> {
+        let text = self
+            .content
+            .get(self.pos..self.pos + digits)
+            .filter(|t| t.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error_at(start, "invalid escape"))?;
+        self.pos += digits;
+        Ok(u32::from_str_radix(text, 16).expect("checked hex digits"))
+    }
+
+    fn hex_char(&mut self, digits: usize, start: usize) -> Result<char, SyntaxError> {
+        let code = self.hex(digits, start)?;
+        char::from_u32(code).ok_or_else(|| self.error_at(start, "invalid escape"))
+    }
+
+    /// `true`, `false`, `null` or a number.
+    fn literal(&mut self) -> Result<Value, SyntaxError> {
+        let start = self.pos;
+        let rest = &self.content[start..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || matches!(c, '+' | '-' | '.')))
+            .unwrap_or(rest.len());
+        let word = &rest[..len];
+        let value = match word {
+            "true" => Some(Value::Bool(true)),
+            "false" => Some(Value::Bool(false)),
+            "null" => Some(Value::Null),
+            _ => number(word)
+                .transpose()
+                .map_err(|message| self.error(message))?,
+        };
+        match value {
+            Some(value) => {
+                self.pos += len;
+                Ok(value)
+            }
+            None if word.is_empty() => Err(self.error("expected value")),
+            None if word.trim_start_matches(['+', '-']).starts_with(['I', 'N']) => {
+                Err(self.error("Infinity and NaN can't be used in a config"))
+            }
+            None => Err(self.error(&format!("expected value, found `{}`", word))),
+        }
+    }
+}
+
+/// A JSON5 number: JSON's, plus a leading `+`, a leading or trailing `.`, and
+/// hexadecimal integers. `None` when `word` isn't a number at all; an error for
+/// one written in a way JSON5 doesn't allow or too large to read.
+fn number(word: &str) -> Option<Result<Value, &'static str>> {
+    let (negative, digits) = match word.as_bytes().first()? {
+        b'-' => (true, &word[1..]),
+        b'+' => (false, &word[1..]),
+        _ => (false, word),
+    };
+    if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        // `from_str_radix` would also take a sign after the `0x`.
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        return Some(match i64::from_str_radix(hex, 16) {
+            Ok(n) => Ok(Value::from(if negative { -n } else { n })),
+            Err(_) => Err("number out of range"),
+        });
+    }
+    if !digits.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return None;
+    }
+    if digits.starts_with('0') && digits[1..].starts_with(|c: char| c.is_ascii_digit()) {
+        return Some(Err("numbers can't start with a leading zero"));
+    }
+    let sign = if negative { "-" } else { "" };
+    let mut text = format!("{}{}", sign, digits);
+    if digits.starts_with('.') {
+        text = format!("{}0{}", sign, digits);
+    }
+    if let Some(base) = text.strip_suffix('.') {
+        text = base.to_string();
+    }
+    if let Ok(n) = text.parse::<i64>() {
+        return Some(Ok(Value::from(n)));
+    }
+    if let Ok(n) = text.parse::<u64>() {
+        return Some(Ok(Value::from(n)));
+    }
+    let n: f64 = text.parse().ok()?;
+    Some(
+        Number::from_f64(n)
+            .map(Value::Number)
+            .ok_or("number out of range"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn value(content: &str) -> Value {
+        parse(content).unwrap().into_value()
+    }
+
+    fn error(content: &str) -> String {
+        let e = parse(content).err().expect("should not parse");
+        let (line, column) = e.position.unwrap();
+        format!("{}:{}: {}", line, column, e.message)
+    }
+
+    #[test]
+    fn plain_json_reads_as_serde_json_does() {
+        let content = r#"{ "a": [1, -2.5, 1e3, true, null], "b": "é\n\"q\"", "c": {} }"#;
+        assert_eq!(
+            value(content),
+            serde_json::from_str::<Value>(content).unwrap()
+        );
+    }
+
+    #[test]
+    fn comments_trailing_commas_and_json5_syntax_are_accepted() {
+        let content = r#"
+            // Tasks for the app.
+            {
+                /* release
+                   tasks */
+                scripts: {
+                    'build': "cargo build", // the usual
+                    $deploy: 'ship \'it\'',
+                    long: "a \
+b",
+                },
+                n: [+1, .5, 5., 0x1F, -0x10,],
+            }
+        "#;
+        assert_eq!(
+            value(content),
+            json!({
+                "scripts": { "build": "cargo build", "$deploy": "ship 'it'", "long": "a b" },
+                "n": [1, 0.5, 5, 31, -16]
+            })
+        );
+    }
+
+    #[test]
+    fn errors_say_where_they_are() {
+        assert_eq!(
+            error("{\n  \"a\": 1\n  \"b\": 2\n}"),
+            "3:3: expected `,` or `}`"
+        );
+        assert_eq!(error("{ \"a\": tru }"), "1:8: expected value, found `tru`");
+        assert_eq!(error("{ \"a\": \"x }"), "1:8: unterminated string");
+        assert_eq!(error("{ /* a"), "1:3: unterminated comment");
+        assert_eq!(error("[1, 2"), "1:6: EOF while parsing a list");
+        assert_eq!(
+            error("{ \"a\": NaN }"),
+            "1:8: Infinity and NaN can't be used in a config"
+        );
+        assert_eq!(error("{} x"), "1:4: trailing characters");
+    }
+
+    #[test]
+    fn leading_zeros_are_rejected() {
+        assert_eq!(
+            error("[01]"),
+            "1:2: numbers can't start with a leading zero"
+        );
+        assert_eq!(
+            error("[-007.5]"),
+            "1:2: numbers can't start with a leading zero"
+        );
+        assert_eq!(
+            value("[0, 0.5, -0, 0e1, 0x0F]"),
+            json!([0, 0.5, 0, 0.0, 15])
+        );
+    }
+
+    #[test]
+    fn unpaired_surrogates_are_rejected() {
+        assert_eq!(value(r#""\uD83D\uDE00""#), json!("😀"));
+        assert_eq!(
+            error(r#""\uD800\u0041""#),
+            "1:2: unpaired surrogate in escape"
+        );
+        assert_eq!(error(r#""\uD800x""#), "1:2: unpaired surrogate in escape");
+        assert_eq!(error(r#""\uDC00""#), "1:2: unpaired surrogate in escape");
+        assert_eq!(error(r#""\uZZZZ""#), "1:2: invalid escape");
+        assert_eq!(error(r#""\x4""#), "1:2: invalid escape");
+    }
+
+    #[test]
+    fn numbers_too_large_to_read_are_rejected() {
+        assert_eq!(error("[0x1FFFFFFFFFFFFFFFF]"), "1:2: number out of range");
+        assert_eq!(error("[1e999]"), "1:2: number out of range");
+        assert_eq!(error("[0x+1F]"), "1:2: expected value, found `0x+1F`");
+        assert_eq!(value("[18446744073709551615]"), json!([u64::MAX]));
+    }
+
+    #[test]
+    fn locate_finds_keys_and_array_items() {
+        let content = "{\n  \"include\": [\"a.json\", \"b.json\"],\n  // tasks\n  \"scripts\": {\n    \"say \\\"hi\\\"\": \"echo {\",\n    build: { \"cmd\": \"make\" },\n  }\n}\n";
+        let at = |path: &[&str]| {
+            let path: Vec<String> = path.iter().map(|s| s.to_string()).collect();
+            locate(content, &path).map(|offset| line_column(content, offset))
+        };
+
+        assert_eq!(at(&[]), Some((1, 1)));
+        assert_eq!(at(&["include", "1"]), Some((2, 25)));
+        assert_eq!(at(&["scripts", "say \"hi\""]), Some((5, 5)));
+        assert_eq!(at(&["scripts", "build", "cmd"]), Some((6, 14)));
+        assert_eq!(at(&["scripts", "missing"]), None);
+    }
+}
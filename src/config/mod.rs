@@ -1,5 +1,18 @@
+mod edit;
+mod format;
+mod json5;
 mod robin_config;
+mod vars;
 
+pub(crate) use edit::{edit_json, edit_toml};
+pub(crate) use format::line_column;
+pub use format::{CONFIG_FILES, ConfigFormat, LOCAL_CONFIG_FILES, SyntaxError};
+pub(crate) use json5::{duplicate_keys, locate};
 pub use robin_config::{
-    RobinConfig, SCHEMA_URL, find_config_from, find_config_path, script_command, script_description,
+    PLATFORMS, RobinConfig, SCHEMA_URL, TaskSources, config_in, current_platform, find_config_from,
+    find_config_path, global_config_path, is_script_block, local_config_in, missing_config_error,
+    script_block, script_command, script_command_for, script_cwd, script_deps, script_description,
+    script_env, script_env_files, script_inputs, script_outputs, script_parallel, script_platforms,
+    script_shell, shell_argv,
 };
+pub use vars::{ChoicesSource, VarSpec, VarType, config_vars, read_var_file, script_vars};
@@ -1,11 +1,15 @@
 use anyhow::{Context, Result};
+use colored::Colorize;
+use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use super::{CONFIG_FILES, ConfigFormat, LOCAL_CONFIG_FILES, edit_json, edit_toml};
 use crate::CONFIG_FILE;
+use crate::utils::shell_program;
 
 /// Canonical location of the published JSON Schema for `.robin.json`. Generated
 /// configs point their `$schema` here so editors can offer autocomplete and
@@ -13,29 +17,85 @@ use crate::CONFIG_FILE;
 pub const SCHEMA_URL: &str = "https://raw.githubusercontent.com/cesarferreira/robin/refs/heads/main/schema/robin.schema.json";
 
 /// Walks up from `start` (inclusive) looking for the first directory that
-/// contains a `.robin.json`. Returns the path to that config, or `None` when no
-/// ancestor holds one. This lets `robin` be run from any subdirectory of a
-/// project, mirroring how `git` and `cargo` locate their root files.
+/// contains a config: `.robin.json`, `.robin.yaml`, `.robin.yml` or
+/// `robin.toml`, in that order of precedence (see [`CONFIG_FILES`]). Returns
+/// the path to that config, or `None` when no ancestor holds one. This lets
+/// `robin` be run from any subdirectory of a project, mirroring how `git` and
+/// `cargo` locate their root files.
 pub fn find_config_from(start: &Path) -> Option<PathBuf> {
     let mut dir = Some(start);
     while let Some(current) = dir {
-        let candidate = current.join(CONFIG_FILE);
-        if candidate.is_file() {
-            return Some(candidate);
+        if let Some(found) = config_in(current) {
+            return Some(found);
         }
         dir = current.parent();
     }
     None
 }
 
-/// Resolves the config path for read commands: the nearest `.robin.json` found
-/// by walking up from the current directory, falling back to `./.robin.json`
-/// so that "not found" errors still name a sensible location.
+/// The error for a project without a config, naming every file robin looks for.
+pub fn missing_config_error() -> anyhow::Error {
+    let (last, others) = CONFIG_FILES
+        .split_last()
+        .expect("CONFIG_FILES is not empty");
+    anyhow::anyhow!(
+        "No {} or {} found. Run 'robin init' first",
+        others.join(", "),
+        last
+    )
+}
+
+/// The config directly in `dir`, if any, by the precedence of [`CONFIG_FILES`].
+pub fn config_in(dir: &Path) -> Option<PathBuf> {
+    CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The user's global task library: `global.json` in robin's directory under the
+/// user config dir (`~/.config/robin/global.json` on Linux), or the file
+/// `ROBIN_GLOBAL_CONFIG` names. Setting that variable to an empty value turns
+/// the library off.
+pub fn global_config_path() -> Option<PathBuf> {
+    match std::env::var_os("ROBIN_GLOBAL_CONFIG") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => Some(dirs::config_dir()?.join("robin").join("global.json")),
+    }
+}
+
+/// The local overrides file directly in `dir`, if any, by the precedence of
+/// [`LOCAL_CONFIG_FILES`].
+pub fn local_config_in(dir: &Path) -> Option<PathBuf> {
+    LOCAL_CONFIG_FILES
+        .iter()
+        .map(|name| dir.join(name))
+        .find(|candidate| candidate.is_file())
+}
+
+/// Resolves the config path for read commands: the nearest config found by
+/// walking up from the current directory, falling back to `./.robin.json` so
+/// that "not found" errors still name a sensible location.
 pub fn find_config_path() -> PathBuf {
     let cwd = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
     find_config_from(&cwd).unwrap_or_else(|| cwd.join(CONFIG_FILE))
 }
 
+/// Where the tasks of a loaded config come from (see
+/// [`RobinConfig::load_with_sources`]).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TaskSources {
+    /// The file each task is declared in: the config, an included config or
+    /// the local overrides file.
+    pub files: HashMap<String, PathBuf>,
+    /// The tasks the local overrides file replaces, with the file that
+    /// declares the replaced one.
+    pub overridden: HashMap<String, PathBuf>,
+    /// The tasks that come from the global task library.
+    pub global: HashSet<String>,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct RobinConfig {
     /// Optional pointer to the JSON Schema, preserved across edits so editor
@@ -44,10 +104,32 @@ pub struct RobinConfig {
     pub schema: Option<String>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub include: Vec<String>,
-    pub scripts: HashMap<String, Value>,
+    /// Default shell for every task declared in this file (see [`shell_argv`]);
+    /// a task's own `shell` takes precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<Value>,
+    /// Variables every task can use, declared once: a value, or a declaration
+    /// like those in a task's `vars` (see [`config_vars`](super::config_vars)).
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub vars: IndexMap<String, Value>,
+    /// Environment variables whose values are masked wherever robin prints
+    /// commands, errors or notifications, like `secret` variables.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secret_env: Vec<String>,
+    pub scripts: IndexMap<String, Value>,
 }
 
-/// Returns the executable part of a script entry.
+/// The operating systems a task's `cmd` can have a dedicated variant for. A
+/// `default` variant covers every platform without one.
+pub const PLATFORMS: &[&str] = &["linux", "macos", "windows"];
+
+/// The platform robin is running on, as named in platform-specific `cmd`
+/// variants (`linux`, `macos`, `windows`, ...).
+pub fn current_platform() -> &'static str {
+    std::env::consts::OS
+}
+
+/// Returns the executable part of a script entry for the current platform.
 ///
 /// A script may be written in three shapes:
 ///   - a bare string:          `"cargo build"`
@@ -55,15 +137,69 @@ pub struct RobinConfig {
 ///   - an object with metadata: `{ "cmd": <string|array>, "desc": "..." }`
 ///
 /// For the string/array forms the entry is itself the command; for the object
-/// form the command lives under `cmd`. Returns `None` for unsupported shapes.
+/// form the command lives under `cmd`, which may also be keyed by platform
+/// (see [`script_command_for`]). Returns `None` for unsupported shapes.
 pub fn script_command(entry: &Value) -> Option<&Value> {
+    script_command_for(entry, current_platform())
+}
+
+/// [`script_command`] for a given platform. When `cmd` is an object of
+/// variants (`{ "linux": ..., "macos": ..., "default": ... }`), picks the one
+/// for `platform`, falling back to `default`; `None` when neither exists.
+pub fn script_command_for<'a>(entry: &'a Value, platform: &str) -> Option<&'a Value> {
     match entry {
         Value::String(_) | Value::Array(_) => Some(entry),
-        Value::Object(map) => map.get("cmd"),
+        Value::Object(map) => match map.get("cmd")? {
+            Value::Object(variants) => variants.get(platform).or_else(|| variants.get("default")),
+            cmd => Some(cmd),
+        },
         _ => None,
     }
 }
 
+/// Returns the platforms a script entry declares `cmd` variants for, in the
+/// order of [`PLATFORMS`] followed by `default`. Empty when the command isn't
+/// platform-specific.
+pub fn script_platforms(entry: &Value) -> Vec<&'static str> {
+    let Some(Value::Object(variants)) = entry.as_object().and_then(|map| map.get("cmd")) else {
+        return Vec::new();
+    };
+    PLATFORMS
+        .iter()
+        .chain(&["default"])
+        .copied()
+        .filter(|p| variants.contains_key(*p))
+        .collect()
+}
+
+/// Returns the body of a script entry's `script` block: a string, or an array
+/// of lines joined with newlines. The body always ends with a newline, which
+/// is what marks a command as a block to run as one script (see
+/// [`is_script_block`]).
+pub fn script_block(entry: &Value) -> Option<String> {
+    let body = match entry.as_object()?.get("script")? {
+        Value::String(s) => s.clone(),
+        Value::Array(lines) => lines
+            .iter()
+            .map(|line| line.as_str())
+            .collect::<Option<Vec<_>>>()?
+            .join("\n"),
+        _ => return None,
+    };
+    if body.ends_with('\n') {
+        Some(body)
+    } else {
+        Some(body + "\n")
+    }
+}
+
+/// True when a command is a multi-line script block (a `script` field, or a
+/// `cmd` string spanning several lines) rather than a single command line.
+/// Blocks run as one script, so `cd` and `export` carry over between lines.
+pub fn is_script_block(command: &str) -> bool {
+    command.contains('\n')
+}
+
 /// Returns the human-readable description of a script entry, when it uses the
 /// object form and carries a non-empty `desc`.
 pub fn script_description(entry: &Value) -> Option<&str> {
@@ -74,62 +210,409 @@ pub fn script_description(entry: &Value) -> Option<&str> {
         .filter(|s| !s.is_empty())
 }
 
+/// Returns the names of the tasks a script entry depends on, from the `deps`
+/// list of the object form. String/array entries have no dependencies.
+pub fn script_deps(entry: &Value) -> Vec<&str> {
+    string_list(entry, "deps")
+}
+
+/// Returns the `inputs` globs of a script entry: the files whose contents decide
+/// whether the task is up to date. Empty unless the object form declares them.
+pub fn script_inputs(entry: &Value) -> Vec<&str> {
+    string_list(entry, "inputs")
+}
+
+/// Returns the `outputs` paths of a script entry: files or directories the task
+/// produces, which must exist for it to be considered up to date.
+pub fn script_outputs(entry: &Value) -> Vec<&str> {
+    string_list(entry, "outputs")
+}
+
+/// Reads an object-form field holding a list of strings, ignoring non-string
+/// items. Missing fields and non-object entries yield an empty list.
+fn string_list<'a>(entry: &'a Value, key: &str) -> Vec<&'a str> {
+    entry
+        .as_object()
+        .and_then(|map| map.get(key))
+        .and_then(Value::as_array)
+        .map(|items| items.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// Returns the task's own environment variables from the `env` map of the object
+/// form, in key order. String values are taken as-is; numbers and booleans are
+/// written out (`"PORT": 8080` becomes `PORT=8080`); anything else is ignored.
+pub fn script_env(entry: &Value) -> Vec<(String, String)> {
+    let Some(env) = entry
+        .as_object()
+        .and_then(|map| map.get("env"))
+        .and_then(Value::as_object)
+    else {
+        return Vec::new();
+    };
+    env.iter()
+        .filter_map(|(key, value)| match value {
+            Value::String(s) => Some((key.clone(), s.clone())),
+            Value::Number(_) | Value::Bool(_) => Some((key.clone(), value.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the `env_file` paths of a script entry, either a single path or a
+/// list, loaded in order for that task only.
+pub fn script_env_files(entry: &Value) -> Vec<&str> {
+    match entry.as_object().and_then(|map| map.get("env_file")) {
+        Some(Value::String(path)) => vec![path.as_str()],
+        _ => string_list(entry, "env_file"),
+    }
+}
+
+/// Returns the working directory a task's commands run in, from the `cwd` field
+/// of the object form (relative to the config that declares the task).
+pub fn script_cwd(entry: &Value) -> Option<&str> {
+    entry
+        .as_object()?
+        .get("cwd")?
+        .as_str()
+        .filter(|s| !s.is_empty())
+}
+
+/// Returns the shell a task's commands run under, from the `shell` field of the
+/// object form, as an argv (see [`shell_argv`]). `None` when the task doesn't
+/// pick one; an error when its `shell` isn't valid.
+pub fn script_shell(entry: &Value) -> Result<Option<Vec<String>>> {
+    match entry.as_object().and_then(|map| map.get("shell")) {
+        Some(shell) => shell_argv(shell).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Turns a `shell` setting into the program and arguments that precede each
+/// command: an array is used as-is and a string is split on whitespace, so
+/// `["bash", "-euo", "pipefail", "-c"]` and `"python3 -c"` both work. A lone
+/// program name gets the flag that makes it run a command string: `-Command`
+/// for PowerShell, `/C` for `cmd`, `-c` otherwise. An error when empty or not
+/// a string/array of strings.
+pub fn shell_argv(value: &Value) -> Result<Vec<String>> {
+    let invalid = || anyhow::anyhow!("expected a command or an array of strings, found {}", value);
+    let mut argv: Vec<String> = match value {
+        Value::String(s) => s.split_whitespace().map(str::to_string).collect(),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().map(str::to_string))
+            .collect::<Option<_>>()
+            .ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    if argv.is_empty() {
+        return Err(invalid());
+    }
+    if argv.len() == 1 {
+        let flag = match shell_program(&argv[0]).as_str() {
+            "pwsh" | "powershell" => "-Command",
+            "cmd" => "/C",
+            _ => "-c",
+        };
+        argv.push(flag.to_string());
+    }
+    Ok(argv)
+}
+
+/// Gives a script entry declared in an included file that file's default
+/// `shell`, unless the task picks its own. String and array entries are turned
+/// into the object form to carry it.
+fn apply_default_shell(entry: Value, shell: &Value) -> Value {
+    match entry {
+        Value::Object(mut map) => {
+            map.entry("shell").or_insert_with(|| shell.clone());
+            Value::Object(map)
+        }
+        Value::String(_) | Value::Array(_) => {
+            serde_json::json!({ "cmd": entry, "shell": shell })
+        }
+        other => other,
+    }
+}
+
+/// Whether `new`, a config in `format` written from scratch, leaves out a
+/// comment of `old`, the file it replaces. Only real comments count, as the
+/// format's parser reads them: a `#` inside a command or a script block isn't
+/// one.
+fn drops_comments(format: ConfigFormat, old: &str, new: &str) -> bool {
+    let kept = format.comments(new);
+    format
+        .comments(old)
+        .iter()
+        .any(|comment| !kept.contains(comment))
+}
+
+/// The fields of a script entry holding paths relative to the config file.
+const PATH_FIELDS: &[&str] = &["cwd", "env_file", "inputs", "outputs"];
+
+/// Rewrites the relative paths of a script entry declared in an included file so
+/// they stay relative to that file once merged into a config living in another
+/// directory. `dir` is the included file's directory as written in `include`.
+fn rebase_paths(entry: &mut Value, dir: &Path) {
+    if dir.as_os_str().is_empty() {
+        return;
+    }
+    let Some(map) = entry.as_object_mut() else {
+        return;
+    };
+    let rebase = |value: &mut Value| {
+        if let Value::String(path) = value {
+            if !Path::new(path.as_str()).is_absolute() {
+                *path = format!("{}/{}", dir.to_string_lossy().trim_end_matches('/'), path);
+            }
+        }
+    };
+    for field in PATH_FIELDS {
+        match map.get_mut(*field) {
+            Some(Value::Array(items)) => items.iter_mut().for_each(rebase),
+            Some(value) => rebase(value),
+            None => {}
+        }
+    }
+}
+
+/// Returns true when a script entry uses the object form with `"parallel": true`,
+/// meaning the entries of its `cmd` sequence are independent and may run
+/// concurrently.
+pub fn script_parallel(entry: &Value) -> bool {
+    entry
+        .as_object()
+        .and_then(|map| map.get("parallel"))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
 impl RobinConfig {
     pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with_sources(path).map(|(config, _)| config)
+    }
+
+    /// [`RobinConfig::load`], also returning where each task comes from: `path`
+    /// itself, the included config it came from, the local overrides file or
+    /// the global task library.
+    pub fn load_with_sources(path: &Path) -> Result<(Self, TaskSources)> {
+        Self::load_with_global(path, global_config_path().as_deref())
+    }
+
+    /// [`RobinConfig::load_with_sources`] with `global` as the global task
+    /// library (if it exists). Its tasks are merged under the project's: a
+    /// project task of the same name wins. Without a project config, the
+    /// global tasks are all there is.
+    pub fn load_with_global(path: &Path, global: Option<&Path>) -> Result<(Self, TaskSources)> {
+        let global = global.filter(|global| global.is_file());
+        if let Some(global) = global.filter(|_| !path.exists()) {
+            let (config, files) = Self::load_tree(global)
+                .with_context(|| format!("Failed to load global tasks: {}", global.display()))?;
+            let sources = TaskSources {
+                global: files.keys().cloned().collect(),
+                files,
+                ..TaskSources::default()
+            };
+            return Ok((config, sources));
+        }
+
+        let (mut config, files) = Self::load_tree(path)?;
+        let mut sources = TaskSources {
+            files,
+            ..TaskSources::default()
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        if let Some(local_path) = local_config_in(dir) {
+            config = config.merge_local(&local_path, &mut sources)?;
+        }
+        if let Some(global) = global {
+            config = config.merge_global(global, &mut sources)?;
+        }
+
+        Ok((config, sources))
+    }
+
+    /// The config at `path` with its includes merged in, and the file each
+    /// task was declared in.
+    fn load_tree(path: &Path) -> Result<(Self, HashMap<String, PathBuf>)> {
         let mut config = Self::load_raw(path)?;
+        let mut sources: HashMap<String, PathBuf> = config
+            .scripts
+            .keys()
+            .map(|name| (name.clone(), path.to_path_buf()))
+            .collect();
 
         // Load and merge included configs
         if !config.include.is_empty() {
             let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
-            config = config.merge_includes(base_dir)?;
+            config = config.merge_includes(base_dir, &mut sources)?;
         }
 
-        Ok(config)
+        Ok((config, sources))
     }
 
     /// Parses a single config file without following `include` — the scripts are
     /// exactly those declared in this file. Used by commands (like `migrate`)
     /// that rewrite the file in place and must not inline included scripts.
+    /// The file is read as JSON, YAML or TOML depending on its extension.
     pub fn load_raw(path: &Path) -> Result<Self> {
         if !path.exists() {
-            return Err(anyhow::anyhow!(
-                "No .robin.json found. Run 'robin init' first"
-            ));
+            return Err(missing_config_error());
         }
 
         let content = fs::read_to_string(path)
             .with_context(|| format!("Failed to read config file: {}", path.display()))?;
 
-        let config: Self = serde_json::from_str(&content)
-            .with_context(|| "The .robin.json file exists but contains malformed JSON. Please check the file format.")?;
+        let format = ConfigFormat::of(path);
+        let malformed = || {
+            format!(
+                "The {} file exists but contains malformed {}. Run 'robin validate' to find where.",
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                format.name()
+            )
+        };
+        let document = format.parse(&content).with_context(malformed)?;
+        let config: Self = serde_json::from_value(document).with_context(malformed)?;
 
         Ok(config)
     }
 
-    fn merge_includes(&self, base_dir: &Path) -> Result<Self> {
+    fn merge_includes(
+        &self,
+        base_dir: &Path,
+        sources: &mut HashMap<String, PathBuf>,
+    ) -> Result<Self> {
         let mut merged_scripts = self.scripts.clone();
+        let mut merged_vars = self.vars.clone();
+        let mut secret_env = self.secret_env.clone();
 
         for include_path in &self.include {
             let full_path = base_dir.join(include_path);
-            let included_config = Self::load(&full_path)
+            let (included_config, mut included_sources) = Self::load_tree(&full_path)
                 .with_context(|| format!("Failed to load included config: {}", include_path))?;
 
             // Merge scripts from included config; existing keys take precedence.
-            for (key, value) in included_config.scripts {
+            let include_dir = Path::new(include_path).parent().unwrap_or(Path::new(""));
+            for (key, mut value) in included_config.scripts {
+                rebase_paths(&mut value, include_dir);
+                // Included tasks keep the shell of the file that declares them.
+                if let Some(shell) = &included_config.shell {
+                    value = apply_default_shell(value, shell);
+                }
+                if let Some(source) = included_sources.remove(&key) {
+                    sources.entry(key.clone()).or_insert(source);
+                }
                 merged_scripts.entry(key).or_insert(value);
             }
+            // Shared variables too: the including file's win.
+            for (name, var) in included_config.vars {
+                merged_vars.entry(name).or_insert(var);
+            }
+            for name in included_config.secret_env {
+                if !secret_env.contains(&name) {
+                    secret_env.push(name);
+                }
+            }
         }
 
         Ok(Self {
             schema: self.schema.clone(),
             include: self.include.clone(),
+            shell: self.shell.clone(),
+            vars: merged_vars,
+            secret_env,
             scripts: merged_scripts,
         })
     }
 
+    /// Merges the local overrides file at `local_path` (and its includes) over
+    /// this config: the reverse of [`merge_includes`](Self::merge_includes),
+    /// its tasks and variables win. A task it replaces keeps its position.
+    fn merge_local(mut self, local_path: &Path, sources: &mut TaskSources) -> Result<Self> {
+        let (local, local_sources) = Self::load_tree(local_path)
+            .with_context(|| format!("Failed to load local overrides: {}", local_path.display()))?;
+
+        for (key, mut value) in local.scripts {
+            if let Some(shell) = &local.shell {
+                value = apply_default_shell(value, shell);
+            }
+            if let Some(source) = local_sources.get(&key) {
+                if let Some(replaced) = sources.files.insert(key.clone(), source.clone()) {
+                    sources.overridden.insert(key.clone(), replaced);
+                }
+            }
+            self.scripts.insert(key, value);
+        }
+        self.vars.extend(local.vars);
+        for name in local.secret_env {
+            if !self.secret_env.contains(&name) {
+                self.secret_env.push(name);
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Merges the global task library at `global_path` under this config, like
+    /// an include: the project's tasks and variables win. Global tasks run
+    /// from the project, so their paths aren't rebased.
+    fn merge_global(mut self, global_path: &Path, sources: &mut TaskSources) -> Result<Self> {
+        let (global, global_sources) = Self::load_tree(global_path)
+            .with_context(|| format!("Failed to load global tasks: {}", global_path.display()))?;
+
+        for (key, mut value) in global.scripts {
+            if self.scripts.contains_key(&key) {
+                continue;
+            }
+            if let Some(shell) = &global.shell {
+                value = apply_default_shell(value, shell);
+            }
+            if let Some(source) = global_sources.get(&key) {
+                sources.files.insert(key.clone(), source.clone());
+            }
+            sources.global.insert(key.clone());
+            self.scripts.insert(key, value);
+        }
+        for (name, var) in global.vars {
+            self.vars.entry(name).or_insert(var);
+        }
+        for name in global.secret_env {
+            if !self.secret_env.contains(&name) {
+                self.secret_env.push(name);
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Writes the config to `path`, in the format its extension names. An
+    /// existing JSON or TOML config is edited in place (see [`edit_json`] and
+    /// [`edit_toml`]), so task order, comments and everything that didn't
+    /// change stay as written. YAML is rewritten whole, in the same order,
+    /// with a warning when that drops comments.
     pub fn save(&self, path: &Path) -> Result<()> {
-        let content =
-            serde_json::to_string_pretty(self).with_context(|| "Failed to serialize config")?;
+        let format = ConfigFormat::of(path);
+        let existing = fs::read_to_string(path).ok();
+        let edited = existing
+            .as_deref()
+            .and_then(|content| self.edited(format, content));
+        let content = match edited {
+            Some(content) => content,
+            None => {
+                let content = format
+                    .render(self)
+                    .with_context(|| "Failed to serialize config")?;
+                if existing.is_some_and(|old| drops_comments(format, &old, &content)) {
+                    eprintln!(
+                        "{} {} was rewritten whole, so its comments are lost",
+                        "warning:".yellow().bold(),
+                        path.display()
+                    );
+                }
+                content
+            }
+        };
 
         fs::write(path, content)
             .with_context(|| format!("Failed to write config to: {}", path.display()))?;
@@ -137,8 +620,21 @@ impl RobinConfig {
         Ok(())
     }
 
+    /// `content`, a JSON or TOML config, edited to hold this config instead.
+    /// `None` when it can't be edited in place.
+    fn edited(&self, format: ConfigFormat, content: &str) -> Option<String> {
+        let before: Self = serde_json::from_value(format.parse(content).ok()?).ok()?;
+        let before = serde_json::to_value(before).ok()?;
+        let after = serde_json::to_value(self).ok()?;
+        match format {
+            ConfigFormat::Json => edit_json(content, &before, &after),
+            ConfigFormat::Toml => edit_toml(content, &before, &after),
+            ConfigFormat::Yaml => None,
+        }
+    }
+
     pub fn create_template() -> Self {
-        let mut scripts = HashMap::new();
+        let mut scripts = IndexMap::new();
         scripts.insert("clean".to_string(), Value::String("...".to_string()));
         scripts.insert(
             "deploy staging".to_string(),
@@ -158,25 +654,48 @@ impl RobinConfig {
         Self {
             schema: Some(SCHEMA_URL.to_string()),
             include: Vec::new(),
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
             scripts,
         }
     }
 
-    /// Renames a task from `from` to `to`, preserving its definition. Errors if
-    /// `from` does not exist or `to` is already taken, so no task is silently
-    /// overwritten.
+    /// Renames a task from `from` to `to`, preserving its definition and its
+    /// place among the other tasks. Errors if `from` does not exist or `to` is
+    /// already taken, so no task is silently overwritten.
     pub fn rename_script(&mut self, from: &str, to: &str) -> Result<()> {
         if self.scripts.contains_key(to) {
             return Err(anyhow::anyhow!("A command named '{}' already exists", to));
         }
-        let value = self
+        let index = self
             .scripts
-            .remove(from)
+            .get_index_of(from)
             .ok_or_else(|| anyhow::anyhow!("Unknown command: {}", from))?;
-        self.scripts.insert(to.to_string(), value);
+        let (_, value) = self.scripts.shift_remove_index(index).unwrap();
+        self.scripts.shift_insert(index, to.to_string(), value);
         Ok(())
     }
 
+    /// Rewrites the config at `path` in another format, next to it under that
+    /// format's file name (see [`ConfigFormat::file_name`]), and removes the
+    /// original. Only that file is converted; the configs it includes keep
+    /// their format. Returns the new path, or `path` itself when it is already
+    /// in that format. Errors rather than overwrite an existing file.
+    pub fn convert(path: &Path, to: ConfigFormat) -> Result<PathBuf> {
+        let config = Self::load_raw(path)?;
+        if ConfigFormat::of(path) == to {
+            return Ok(path.to_path_buf());
+        }
+        let target = path.with_file_name(to.file_name());
+        if target.exists() {
+            return Err(anyhow::anyhow!("{} already exists", target.display()));
+        }
+        config.save(&target)?;
+        fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        Ok(target)
+    }
+
     /// Rewrites every script into the object form `{ "cmd": ..., "desc": "" }`,
     /// leaving an empty `desc` ready to be filled in. Entries already in object
     /// form are kept as-is. This is what `robin migrate` applies so users can
@@ -199,6 +718,9 @@ impl RobinConfig {
             // keeping any pointer the user already set.
             schema: self.schema.clone().or_else(|| Some(SCHEMA_URL.to_string())),
             include: self.include.clone(),
+            shell: self.shell.clone(),
+            vars: self.vars.clone(),
+            secret_env: self.secret_env.clone(),
             scripts,
         }
     }
@@ -244,13 +766,187 @@ mod tests {
         assert_eq!(script_description(&json!("cargo build")), None);
     }
 
+    #[test]
+    fn script_deps_reads_deps_list_from_object() {
+        assert_eq!(
+            script_deps(&json!({ "cmd": "x", "deps": ["a", "b"] })),
+            vec!["a", "b"]
+        );
+        assert!(script_deps(&json!({ "cmd": "x" })).is_empty());
+        assert!(script_deps(&json!("cargo build")).is_empty());
+    }
+
+    #[test]
+    fn script_inputs_and_outputs_read_string_lists() {
+        let entry = json!({ "cmd": "gen", "inputs": ["src/**/*.proto"], "outputs": ["gen/"] });
+        assert_eq!(script_inputs(&entry), vec!["src/**/*.proto"]);
+        assert_eq!(script_outputs(&entry), vec!["gen/"]);
+        assert!(script_inputs(&json!("gen")).is_empty());
+    }
+
+    #[test]
+    fn script_parallel_is_opt_in() {
+        assert!(script_parallel(
+            &json!({ "cmd": ["a", "b"], "parallel": true })
+        ));
+        assert!(!script_parallel(&json!({ "cmd": ["a", "b"] })));
+        assert!(!script_parallel(&json!(["a", "b"])));
+    }
+
+    #[test]
+    fn script_env_reads_map_and_env_files() {
+        let entry = json!({
+            "cmd": "deploy",
+            "env": { "REGION": "eu-west-1", "PORT": 8080, "NESTED": {} },
+            "env_file": ".env.staging"
+        });
+        assert_eq!(
+            script_env(&entry),
+            vec![
+                ("REGION".to_string(), "eu-west-1".to_string()),
+                ("PORT".to_string(), "8080".to_string()),
+            ]
+        );
+        assert_eq!(script_env_files(&entry), vec![".env.staging"]);
+        assert_eq!(
+            script_env_files(&json!({ "cmd": "x", "env_file": [".env", ".env.local"] })),
+            vec![".env", ".env.local"]
+        );
+        assert!(script_env(&json!("deploy")).is_empty());
+    }
+
+    #[test]
+    fn rebase_paths_prefixes_relative_paths_only() {
+        let mut entry = json!({
+            "cmd": "make",
+            "cwd": ".",
+            "env_file": ".env",
+            "inputs": ["src/**", "/abs/file"],
+            "desc": "not a path"
+        });
+        rebase_paths(&mut entry, Path::new("packages/api"));
+        assert_eq!(script_cwd(&entry), Some("packages/api/."));
+        assert_eq!(script_env_files(&entry), vec!["packages/api/.env"]);
+        assert_eq!(
+            script_inputs(&entry),
+            vec!["packages/api/src/**", "/abs/file"]
+        );
+        assert_eq!(script_description(&entry), Some("not a path"));
+
+        let mut same_dir = json!({ "cmd": "make", "cwd": "web" });
+        rebase_paths(&mut same_dir, Path::new(""));
+        assert_eq!(script_cwd(&same_dir), Some("web"));
+    }
+
+    #[test]
+    fn script_command_for_picks_platform_variant_or_default() {
+        let entry = json!({
+            "cmd": { "macos": "open coverage/index.html", "default": "xdg-open coverage/index.html" }
+        });
+        assert_eq!(
+            script_command_for(&entry, "macos"),
+            Some(&json!("open coverage/index.html"))
+        );
+        assert_eq!(
+            script_command_for(&entry, "linux"),
+            Some(&json!("xdg-open coverage/index.html"))
+        );
+        assert_eq!(script_platforms(&entry), vec!["macos", "default"]);
+
+        let no_default = json!({ "cmd": { "windows": "start coverage\\index.html" } });
+        assert_eq!(script_command_for(&no_default, "linux"), None);
+        assert!(script_platforms(&json!({ "cmd": "make" })).is_empty());
+        assert_eq!(
+            script_command_for(&json!("make"), "windows"),
+            Some(&json!("make"))
+        );
+    }
+
+    #[test]
+    fn shell_argv_accepts_arrays_strings_and_bare_programs() {
+        assert_eq!(
+            shell_argv(&json!(["bash", "-euo", "pipefail", "-c"])).unwrap(),
+            vec!["bash", "-euo", "pipefail", "-c"]
+        );
+        assert_eq!(
+            shell_argv(&json!("python3 -c")).unwrap(),
+            vec!["python3", "-c"]
+        );
+        assert_eq!(shell_argv(&json!("zsh")).unwrap(), vec!["zsh", "-c"]);
+        assert_eq!(
+            shell_argv(&json!("pwsh")).unwrap(),
+            vec!["pwsh", "-Command"]
+        );
+        assert!(shell_argv(&json!("")).is_err());
+        assert!(shell_argv(&json!(["bash", 1])).is_err());
+        assert_eq!(
+            script_shell(&json!({ "cmd": "x", "shell": "bash" })).unwrap(),
+            Some(vec!["bash".to_string(), "-c".to_string()])
+        );
+        assert_eq!(script_shell(&json!("x")).unwrap(), None);
+        assert!(script_shell(&json!({ "cmd": "x", "shell": 3 })).is_err());
+    }
+
+    #[test]
+    fn script_block_joins_lines_and_ends_with_newline() {
+        assert_eq!(
+            script_block(&json!({ "script": ["cd ios", "pod install"] })).as_deref(),
+            Some("cd ios\npod install\n")
+        );
+        assert_eq!(
+            script_block(&json!({ "script": "cd ios\npod install\n" })).as_deref(),
+            Some("cd ios\npod install\n")
+        );
+        assert!(is_script_block(
+            &script_block(&json!({ "script": "make" })).unwrap()
+        ));
+        assert_eq!(script_block(&json!({ "cmd": "make" })), None);
+        assert!(!is_script_block("make build"));
+    }
+
+    #[test]
+    fn drops_comments_ignores_those_kept_in_script_blocks() {
+        let yaml = ConfigFormat::Yaml;
+        let old = "# Tasks\nscripts:\n  ship: |\n    # build first\n    make\n";
+        let kept = "scripts:\n  ship: |\n    # build first\n    make\n";
+        assert!(drops_comments(yaml, old, kept));
+        assert!(!drops_comments(yaml, &old.replace("# Tasks\n", ""), kept));
+    }
+
+    #[test]
+    fn drops_comments_only_counts_what_the_parser_skips() {
+        let yaml = ConfigFormat::Yaml;
+        let plain = "scripts:\n  a: \"echo #1\"\n  b: https://x/#a\n";
+        assert!(!drops_comments(yaml, plain, plain));
+        let commented = "scripts:\n  a: \"echo #1\" # the first\n  b: https://x/#a\n";
+        assert!(drops_comments(yaml, commented, plain));
+
+        let toml = ConfigFormat::Toml;
+        let plain = "[scripts]\na = \"echo #1\"\nb = '''\n# kept\nmake\n'''\n";
+        assert!(!drops_comments(toml, plain, plain));
+        assert!(drops_comments(toml, &format!("# Tasks\n{}", plain), plain));
+
+        let json = ConfigFormat::Json;
+        let plain = "{ \"scripts\": { \"a\": \"echo #1 // not a comment\" } }";
+        assert!(!drops_comments(json, plain, plain));
+        for commented in [
+            "{ // tasks\n \"scripts\": { \"a\": \"echo #1 // not a comment\" } }",
+            "{ /* tasks */ \"scripts\": { \"a\": \"echo #1 // not a comment\" } }",
+        ] {
+            assert!(drops_comments(json, commented, plain));
+        }
+    }
+
     #[test]
     fn rename_script_moves_definition_to_new_key() {
-        let mut scripts = HashMap::new();
+        let mut scripts = IndexMap::new();
         scripts.insert("old".to_string(), json!("cargo build"));
         let mut config = RobinConfig {
             schema: None,
             include: vec![],
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
             scripts,
         };
 
@@ -265,7 +961,10 @@ mod tests {
         let mut config = RobinConfig {
             schema: None,
             include: vec![],
-            scripts: HashMap::new(),
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
+            scripts: IndexMap::new(),
         };
         let err = config.rename_script("missing", "new").unwrap_err();
         assert!(err.to_string().contains("Unknown command"), "{err}");
@@ -273,12 +972,15 @@ mod tests {
 
     #[test]
     fn rename_script_refuses_to_overwrite_existing_target() {
-        let mut scripts = HashMap::new();
+        let mut scripts = IndexMap::new();
         scripts.insert("a".to_string(), json!("1"));
         scripts.insert("b".to_string(), json!("2"));
         let mut config = RobinConfig {
             schema: None,
             include: vec![],
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
             scripts,
         };
 
@@ -291,13 +993,16 @@ mod tests {
 
     #[test]
     fn migrated_wraps_strings_and_arrays_but_keeps_objects() {
-        let mut scripts = HashMap::new();
+        let mut scripts = IndexMap::new();
         scripts.insert("s".to_string(), json!("cargo build"));
         scripts.insert("a".to_string(), json!(["x", "y"]));
         scripts.insert("o".to_string(), json!({ "cmd": "already", "desc": "kept" }));
         let config = RobinConfig {
             schema: None,
             include: vec!["base.json".to_string()],
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
             scripts,
         };
 
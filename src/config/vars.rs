@@ -0,0 +1,497 @@
+use anyhow::{Context, Result, anyhow};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::utils::command_output;
+
+/// The kind of value a declared variable accepts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VarType {
+    #[default]
+    String,
+    Int,
+    Bool,
+    /// A semantic version (`1.2.3`, `v1.2.3-rc.1`).
+    Semver,
+    /// A path that must exist.
+    #[serde(alias = "path")]
+    PathExists,
+    /// One of the declared `choices`.
+    Enum,
+    /// A string matching the declared `pattern`.
+    Regex,
+}
+
+impl VarType {
+    pub fn name(self) -> &'static str {
+        match self {
+            VarType::String => "string",
+            VarType::Int => "int",
+            VarType::Bool => "bool",
+            VarType::Semver => "semver",
+            VarType::PathExists => "path-exists",
+            VarType::Enum => "enum",
+            VarType::Regex => "regex",
+        }
+    }
+}
+
+/// Where the allowed values of a variable are read from when they aren't
+/// listed in `choices`: each non-empty line a command prints, or each
+/// non-empty line of a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChoicesSource {
+    Cmd(String),
+    File(String),
+}
+
+/// How `choices_from` is written: a command, or `{ "cmd": ... }` /
+/// `{ "file": ... }`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChoicesFrom {
+    Command(String),
+    Source(ChoicesSource),
+}
+
+/// A task variable: either declared in the task's `vars` or found as a
+/// `{{placeholder}}` in its commands (then a string, or an enum for
+/// `{{name=[a, b]}}`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VarSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: VarType,
+    /// Whether the task fails without a value for it.
+    pub required: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// The value used when none is passed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// A shell command whose output is the value when none is passed. It only
+    /// runs when the value is needed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_cmd: Option<String>,
+    /// The allowed values of an enum variable.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// Where the allowed values come from when they change too often to list
+    /// them; [`VarSpec::load_choices`] reads them into `choices`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub choices_from: Option<ChoicesSource>,
+    /// A regular expression the value must match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    /// Whether the value is masked wherever robin prints it.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+}
+
+/// How a variable is written in a task's `vars`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct VarDeclaration {
+    #[serde(rename = "type", default)]
+    kind: VarType,
+    required: Option<bool>,
+    desc: Option<String>,
+    default: Option<Value>,
+    from_cmd: Option<String>,
+    #[serde(default)]
+    choices: Vec<Value>,
+    choices_from: Option<ChoicesFrom>,
+    pattern: Option<String>,
+    #[serde(default)]
+    secret: bool,
+}
+
+/// Reads the `vars` a task object declares. Errors on a malformed declaration:
+/// an unknown type or field, both a `default` and a `from_cmd`, both `choices`
+/// and `choices_from`, an `enum` without either, a `regex` without a
+/// `pattern`, or a pattern that isn't a valid regular expression.
+pub fn script_vars(entry: &Value) -> Result<Vec<VarSpec>> {
+    let Some(vars) = entry.as_object().and_then(|map| map.get("vars")) else {
+        return Ok(Vec::new());
+    };
+    let vars = vars
+        .as_object()
+        .ok_or_else(|| anyhow!("`vars` must map variable names to their declarations"))?;
+    vars.iter()
+        .map(|(name, declaration)| parse_var(name, declaration))
+        .collect()
+}
+
+/// Reads a config's top-level `vars`, in the order the config declares them.
+pub fn config_vars(vars: &IndexMap<String, Value>) -> Result<Vec<VarSpec>> {
+    vars.iter()
+        .map(|(name, declaration)| parse_var(name, declaration))
+        .collect()
+}
+
+/// Reads one declaration. A plain value is short for a declaration with just
+/// that `default`: `"registry": "ghcr.io/acme"`.
+fn parse_var(name: &str, declaration: &Value) -> Result<VarSpec> {
+    let declaration: VarDeclaration = match declaration {
+        Value::Object(_) => serde_json::from_value(declaration.clone())
+            .with_context(|| format!("Invalid declaration of variable '{}'", name))?,
+        value if is_scalar(value) => VarDeclaration {
+            default: Some(value.clone()),
+            ..VarDeclaration::default()
+        },
+        _ => return Err(anyhow!("Invalid declaration of variable '{}'", name)),
+    };
+    let spec = VarSpec {
+        name: name.to_string(),
+        kind: declaration.kind,
+        required: declaration
+            .required
+            .unwrap_or(declaration.default.is_none() && declaration.from_cmd.is_none()),
+        description: declaration.desc.filter(|d| !d.trim().is_empty()),
+        default: declaration.default.as_ref().map(scalar_string),
+        from_cmd: declaration.from_cmd.filter(|c| !c.trim().is_empty()),
+        choices: declaration.choices.iter().map(scalar_string).collect(),
+        choices_from: declaration.choices_from.map(|from| match from {
+            ChoicesFrom::Command(command) => ChoicesSource::Cmd(command),
+            ChoicesFrom::Source(source) => source,
+        }),
+        pattern: declaration.pattern,
+        secret: declaration.secret,
+    };
+    spec.check_declaration()?;
+    Ok(spec)
+}
+
+/// Reads a `--var-file`: a JSON object of variable values, such as
+/// `{ "env": "staging", "replicas": 3 }`.
+pub fn read_var_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read var file: {}", path.display()))?;
+    let values: serde_json::Map<String, Value> = serde_json::from_str(&content)
+        .with_context(|| format!("Var file {} must be a JSON object", path.display()))?;
+    values
+        .iter()
+        .map(|(name, value)| match is_scalar(value) {
+            true => Ok((name.clone(), scalar_string(value))),
+            false => Err(anyhow!(
+                "Value of {} in {} must be a string, number or boolean",
+                name,
+                path.display()
+            )),
+        })
+        .collect()
+}
+
+fn is_scalar(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+}
+
+fn scalar_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl VarSpec {
+    /// A plain string variable with nothing but a name.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            kind: VarType::String,
+            required: true,
+            description: None,
+            default: None,
+            from_cmd: None,
+            choices: Vec::new(),
+            choices_from: None,
+            pattern: None,
+            secret: false,
+        }
+    }
+
+    fn check_declaration(&self) -> Result<()> {
+        if self.default.is_some() && self.from_cmd.is_some() {
+            return Err(anyhow!(
+                "Variable '{}' declares both a default and from_cmd",
+                self.name
+            ));
+        }
+        if !self.choices.is_empty() && self.choices_from.is_some() {
+            return Err(anyhow!(
+                "Variable '{}' declares both choices and choices_from",
+                self.name
+            ));
+        }
+        if self.kind == VarType::Enum && self.choices.is_empty() && self.choices_from.is_none() {
+            return Err(anyhow!(
+                "Variable '{}' is an enum but declares no choices",
+                self.name
+            ));
+        }
+        if self.kind == VarType::Regex && self.pattern.is_none() {
+            return Err(anyhow!(
+                "Variable '{}' is a regex but declares no pattern",
+                self.name
+            ));
+        }
+        if let Some(pattern) = &self.pattern {
+            Regex::new(pattern)
+                .with_context(|| format!("Invalid pattern for variable '{}'", self.name))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the allowed values from `choices_from`, if set, running its
+    /// command or reading its file in `cwd` (the current directory when
+    /// `None`). Errors when the source fails or yields no values.
+    pub fn load_choices(&mut self, cwd: Option<&Path>) -> Result<()> {
+        let Some(source) = &self.choices_from else {
+            return Ok(());
+        };
+        let output = match source {
+            ChoicesSource::Cmd(command) => command_output(command, cwd),
+            ChoicesSource::File(file) => {
+                let path = cwd.map_or_else(|| Path::new(file).to_path_buf(), |d| d.join(file));
+                std::fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))
+            }
+        }
+        .with_context(|| format!("Could not load the choices of {}", self.name))?;
+        self.choices = output
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect();
+        if self.choices.is_empty() {
+            return Err(anyhow!("choices_from of {} produced no choices", self.name));
+        }
+        Ok(())
+    }
+
+    /// The values to offer for it, when there is a fixed set: its choices, or
+    /// `true`/`false` for a bool.
+    pub fn values(&self) -> Vec<String> {
+        match self.kind {
+            VarType::Bool if self.choices.is_empty() => vec!["true".into(), "false".into()],
+            _ => self.choices.clone(),
+        }
+    }
+
+    /// A short placeholder for its value in usage lines: `<semver>`, `<a|b>`.
+    pub fn hint(&self) -> String {
+        match self.values() {
+            values if !values.is_empty() => format!("<{}>", values.join("|")),
+            _ => format!("<{}>", self.kind.name()),
+        }
+    }
+
+    /// Checks `value` against the variable's type, choices and pattern.
+    pub fn check(&self, value: &str) -> Result<()> {
+        let valid = match self.kind {
+            VarType::String | VarType::Regex | VarType::Enum => true,
+            VarType::Int => value.parse::<i64>().is_ok(),
+            VarType::Bool => matches!(value, "true" | "false"),
+            VarType::Semver => semver_regex().is_match(value),
+            VarType::PathExists => Path::new(value).exists(),
+        };
+        if !valid {
+            let expected = match self.kind {
+                VarType::Int => "an integer",
+                VarType::Bool => "true or false",
+                VarType::Semver => "a semantic version (e.g. 1.2.3)",
+                _ => "an existing path",
+            };
+            return Err(anyhow!(
+                "Value '{}' for {} must be {}",
+                value,
+                self.name,
+                expected
+            ));
+        }
+        if !self.choices.is_empty() && !self.choices.iter().any(|c| c == value) {
+            return Err(anyhow!(
+                "Value '{}' for {} must be one of: {}",
+                value,
+                self.name,
+                self.choices.join(", ")
+            ));
+        }
+        if let Some(pattern) = &self.pattern {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid pattern for variable '{}'", self.name))?;
+            if !regex.is_match(value) {
+                return Err(anyhow!(
+                    "Value '{}' for {} must match {}",
+                    value,
+                    self.name,
+                    pattern
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn semver_regex() -> Regex {
+    Regex::new(r"^v?(0|[1-9]\d*)\.(0|[1-9]\d*)\.(0|[1-9]\d*)(-[0-9A-Za-z.-]+)?(\+[0-9A-Za-z.-]+)?$")
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn var(declaration: Value) -> VarSpec {
+        script_vars(&json!({ "cmd": "x", "vars": { "v": declaration } }))
+            .unwrap()
+            .remove(0)
+    }
+
+    #[test]
+    fn script_vars_reads_declarations() {
+        let v = var(json!({ "type": "int", "desc": "Retries", "default": 3 }));
+        assert_eq!(v.kind, VarType::Int);
+        assert_eq!(v.description.as_deref(), Some("Retries"));
+        assert_eq!(v.default.as_deref(), Some("3"));
+        assert!(!v.required);
+
+        assert!(var(json!({})).required);
+        assert!(!var(json!({ "required": false })).required);
+        let computed = var(json!({ "from_cmd": "git rev-parse --short HEAD" }));
+        assert_eq!(
+            computed.from_cmd.as_deref(),
+            Some("git rev-parse --short HEAD")
+        );
+        assert!(!computed.required);
+        assert_eq!(var(json!({ "type": "path" })).kind, VarType::PathExists);
+        assert!(script_vars(&json!("echo")).unwrap().is_empty());
+        assert!(var(json!({ "secret": true })).secret);
+        assert!(!var(json!({})).secret);
+    }
+
+    #[test]
+    fn script_vars_rejects_malformed_declarations() {
+        let err = |declaration: Value| {
+            script_vars(&json!({ "cmd": "x", "vars": { "v": declaration } }))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err(json!({ "type": "float" })).contains("Invalid declaration of variable 'v'"));
+        assert!(err(json!({ "type": "enum" })).contains("declares no choices"));
+        assert!(err(json!({ "type": "regex" })).contains("declares no pattern"));
+        assert!(err(json!({ "pattern": "(" })).contains("Invalid pattern"));
+        assert!(err(json!({ "requried": true })).contains("Invalid declaration"));
+        assert!(err(json!({ "default": "a", "from_cmd": "b" })).contains("both a default"));
+        assert!(
+            err(json!({ "choices": ["a"], "choices_from": "ls" })).contains("both choices and")
+        );
+    }
+
+    #[test]
+    fn config_vars_accept_plain_values_and_declarations() {
+        let vars: IndexMap<String, Value> = serde_json::from_value(json!({
+            "registry": "ghcr.io",
+            "replicas": 3,
+            "env": { "type": "enum", "choices": ["staging", "prod"] }
+        }))
+        .unwrap();
+        let vars = config_vars(&vars).unwrap();
+
+        let names: Vec<_> = vars.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["registry", "replicas", "env"]);
+        assert!(vars[2].required);
+        assert_eq!(vars[0].default.as_deref(), Some("ghcr.io"));
+        assert_eq!(vars[1].default.as_deref(), Some("3"));
+        assert!(!vars[1].required);
+
+        let bad: IndexMap<String, Value> = serde_json::from_value(json!({ "x": [1] })).unwrap();
+        assert!(config_vars(&bad).is_err());
+    }
+
+    #[test]
+    fn read_var_file_reads_a_json_object_of_values() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("staging.json");
+        std::fs::write(
+            &path,
+            r#"{ "env": "staging", "replicas": 3, "debug": false }"#,
+        )
+        .unwrap();
+        let mut values = read_var_file(&path).unwrap();
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                ("debug".to_string(), "false".to_string()),
+                ("env".to_string(), "staging".to_string()),
+                ("replicas".to_string(), "3".to_string()),
+            ]
+        );
+
+        std::fs::write(&path, r#"{ "env": { "nested": true } }"#).unwrap();
+        assert!(read_var_file(&path).is_err());
+        std::fs::write(&path, "[1, 2]").unwrap();
+        assert!(read_var_file(&path).is_err());
+    }
+
+    #[test]
+    fn load_choices_reads_command_output_and_files() {
+        let mut from_cmd =
+            var(json!({ "type": "enum", "choices_from": "printf 'dev\\n\\nprod\\n'" }));
+        assert!(from_cmd.values().is_empty());
+        from_cmd.load_choices(None).unwrap();
+        assert_eq!(from_cmd.values(), vec!["dev", "prod"]);
+        assert!(from_cmd.check("staging").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("flavors.txt"), "free\n  paid  \n").unwrap();
+        let mut from_file = var(json!({ "choices_from": { "file": "flavors.txt" } }));
+        from_file.load_choices(Some(dir.path())).unwrap();
+        assert_eq!(from_file.choices, vec!["free", "paid"]);
+
+        let mut empty = var(json!({ "choices_from": { "cmd": "true" } }));
+        assert_eq!(
+            empty.load_choices(None).unwrap_err().to_string(),
+            "choices_from of v produced no choices"
+        );
+    }
+
+    #[test]
+    fn check_validates_types_choices_and_patterns() {
+        let int = var(json!({ "type": "int" }));
+        assert!(int.check("42").is_ok());
+        assert_eq!(
+            int.check("4x").unwrap_err().to_string(),
+            "Value '4x' for v must be an integer"
+        );
+
+        let semver = var(json!({ "type": "semver", "pattern": "^v\\d+" }));
+        assert!(semver.check("v1.2.3").is_ok());
+        assert!(semver.check("v1.2.3-rc.1").is_ok());
+        assert!(semver.check("v1.2").is_err());
+        assert_eq!(
+            semver.check("1.2.3").unwrap_err().to_string(),
+            "Value '1.2.3' for v must match ^v\\d+"
+        );
+
+        let bool = var(json!({ "type": "bool" }));
+        assert!(bool.check("true").is_ok());
+        assert!(bool.check("yes").is_err());
+
+        let choice = var(json!({ "type": "enum", "choices": ["a", "b"] }));
+        assert!(choice.check("b").is_ok());
+        assert!(choice.check("c").is_err());
+
+        let path = var(json!({ "type": "path-exists" }));
+        assert!(path.check(".").is_ok());
+        assert!(path.check("./definitely/not/here").is_err());
+    }
+}
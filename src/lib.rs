@@ -1,4 +1,7 @@
+/// The config `robin init` creates. Robin also finds configs under the other
+/// names in [`CONFIG_FILES`].
 pub const CONFIG_FILE: &str = ".robin.json";
+#[cfg(not(feature = "test-utils"))]
 const GITHUB_TEMPLATE_BASE: &str =
     "https://raw.githubusercontent.com/cesarferreira/robin/refs/heads/main/templates";
 
@@ -8,22 +11,36 @@ pub mod makefile;
 pub mod scripts;
 pub mod tools;
 pub mod utils;
+pub mod validate;
 
-pub use cli::{Cli, Commands};
+pub use cli::{
+    Cli, Commands, CompletionShell, ListFormat, complete, complete_line, completion_script,
+};
 pub use config::{
-    RobinConfig, find_config_from, find_config_path, script_command, script_description,
+    CONFIG_FILES, ChoicesSource, ConfigFormat, LOCAL_CONFIG_FILES, PLATFORMS, RobinConfig,
+    SCHEMA_URL, TaskSources, VarSpec, VarType, config_in, config_vars, current_platform,
+    find_config_from, find_config_path, global_config_path, is_script_block, local_config_in,
+    missing_config_error, read_var_file, script_block, script_command, script_command_for,
+    script_cwd, script_deps, script_description, script_env, script_env_files, script_inputs,
+    script_outputs, script_parallel, script_platforms, script_shell, script_vars, shell_argv,
 };
 pub use makefile::{find_makefile_from, find_makefile_path, load_makefile_scripts, parse_makefile};
 pub use scripts::{
-    command_lines, interactive_mode, interactive_scripts, list_commands, list_scripts,
-    resolve_task_command, run_script, run_script_in,
+    PlannedTask, RunOptions, Step, TaskInfo, command_lines, interactive_mode, interactive_scripts,
+    list_commands, list_scripts, plan_task, print_task_help, print_task_list, resolve_task_command,
+    run_plan, run_script, run_script_in, takes_variable, task_infos, task_order, watch_task,
 };
 pub use tools::{check_environment, update_tools};
 pub use utils::{
-    check_for_update, load_env_file, replace_variables, send_notification, split_command_and_args,
+    MASK, Quoting, TemplateVariable, check_for_update, load_env_file, redact, register_secret,
+    replace_variables, replace_variables_with_env, send_notification, shell_quote,
+    split_command_and_args, split_passthrough, template_variables, uses_positional,
 };
+pub use validate::{Problem, Severity, validate_config};
 
-use anyhow::{Context, Result, anyhow};
+use anyhow::Result;
+#[cfg(not(feature = "test-utils"))]
+use anyhow::{Context, anyhow};
 
 #[cfg(not(feature = "test-utils"))]
 pub async fn fetch_template(template_name: &str) -> Result<RobinConfig> {
@@ -49,8 +66,7 @@ pub async fn fetch_template(template_name: &str) -> Result<RobinConfig> {
 
 #[cfg(feature = "test-utils")]
 pub async fn fetch_template(_template_name: &str) -> Result<RobinConfig> {
-    use std::collections::HashMap;
-    let mut scripts = HashMap::new();
+    let mut scripts = indexmap::IndexMap::new();
     scripts.insert(
         "start".to_string(),
         serde_json::Value::String("npm start".to_string()),
@@ -59,5 +75,8 @@ pub async fn fetch_template(_template_name: &str) -> Result<RobinConfig> {
         schema: None,
         scripts,
         include: vec![],
+        shell: None,
+        vars: indexmap::IndexMap::new(),
+        secret_env: vec![],
     })
 }
@@ -1,14 +1,20 @@
 use anyhow::{Context, Result, anyhow};
-use clap::Parser;
 use colored::*;
 use dialoguer::Confirm;
-use std::path::PathBuf;
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 use robin::{
-    CONFIG_FILE, Cli, Commands, RobinConfig, check_environment, check_for_update, command_lines,
-    find_config_path, find_makefile_path, interactive_mode, interactive_scripts, list_commands,
-    list_scripts, load_env_file, load_makefile_scripts, replace_variables, resolve_task_command,
-    run_script_in, script_command, send_notification, split_command_and_args, update_tools,
+    CONFIG_FILE, Cli, Commands, PLATFORMS, RobinConfig, RunOptions, SCHEMA_URL, Severity,
+    TaskSources, check_environment, check_for_update, complete_line, completion_script, config_in,
+    config_vars, find_config_path, find_makefile_path, global_config_path, interactive_mode,
+    interactive_scripts, load_env_file, load_makefile_scripts, missing_config_error, plan_task,
+    print_task_help, print_task_list, read_var_file, redact, run_plan, send_notification,
+    shell_argv, split_command_and_args, split_passthrough, takes_variable, update_tools,
+    validate_config, watch_task,
 };
 
 const GITHUB_TEMPLATE_BASE: &str =
@@ -36,26 +42,43 @@ async fn fetch_template(template_name: &str) -> Result<RobinConfig> {
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
-    let cli = Cli::parse();
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let make = args.iter().any(|a| a == "--make");
+    let cli = Cli::parse_with_tasks(args, |name| defines_task(name, make));
 
     // Run the requested command first, then surface any available update.
     let outcome = dispatch(&cli).await;
-    check_for_update().await;
-    outcome
+    // Completions run on every Tab press: keep them fast and their output clean.
+    if !matches!(
+        cli.command,
+        Some(Commands::Complete { .. } | Commands::Completions { .. })
+    ) {
+        check_for_update().await;
+    }
+    match outcome {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            // Errors can quote commands and values: mask the secrets in them
+            // as they're printed.
+            eprintln!("Error: {}", redact(&format!("{:?}", e)));
+            ExitCode::FAILURE
+        }
+    }
 }
 
 async fn dispatch(cli: &Cli) -> Result<()> {
-    // Read/edit commands locate the nearest `.robin.json` by walking up from the
-    // current directory; `init` always targets the current directory so it never
-    // overwrites a parent project's config.
+    // Read/edit commands locate the nearest config (`.robin.json`, `.robin.yaml`,
+    // `robin.toml`, ...) by walking up from the current directory; `init`
+    // always targets the current directory so it never overwrites a parent
+    // project's config.
     let config_path = find_config_path();
     let makefile_path = find_makefile_path();
 
     match &cli.command {
         Some(Commands::Init { template }) => {
             let config_path = PathBuf::from(CONFIG_FILE);
-            if config_path.exists() {
+            if config_in(Path::new(".")).is_some() {
                 let should_override = Confirm::new()
                     .with_prompt("Config file already exists. Do you want to override it?")
                     .default(false)
@@ -84,10 +107,24 @@ async fn dispatch(cli: &Cli) -> Result<()> {
             println!("{} {}", "Created".green(), config_path.display());
         }
 
-        Some(Commands::Add { name, script }) => {
+        Some(Commands::Add {
+            name,
+            script,
+            global,
+        }) => {
+            let path = edited_config_path(*global, &config_path)?;
             // Edit only the local file — never inline included scripts.
-            let mut config = if config_path.exists() {
-                RobinConfig::load_raw(&config_path)?
+            let mut config = if path.exists() {
+                RobinConfig::load_raw(&path)?
+            } else if *global {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)
+                        .with_context(|| format!("Failed to create {}", dir.display()))?;
+                }
+                RobinConfig {
+                    schema: Some(SCHEMA_URL.to_string()),
+                    ..RobinConfig::default()
+                }
             } else {
                 RobinConfig::create_template()
             };
@@ -95,28 +132,28 @@ async fn dispatch(cli: &Cli) -> Result<()> {
             config
                 .scripts
                 .insert(name.clone(), serde_json::Value::String(script.clone()));
-            config.save(&config_path)?;
+            config.save(&path)?;
             println!("{} {}", "Added command:".green(), name);
         }
 
-        Some(Commands::Remove { name }) => {
-            let mut config = RobinConfig::load_raw(&config_path)
-                .with_context(|| "No .robin.json found. Run 'robin init' first")?;
+        Some(Commands::Remove { name, global }) => {
+            let path = edited_config_path(*global, &config_path)?;
+            let mut config = load_edited(&path, *global)?;
 
-            if config.scripts.remove(name).is_some() {
-                config.save(&config_path)?;
+            if config.scripts.shift_remove(name).is_some() {
+                config.save(&path)?;
                 println!("{} {}", "Removed command:".green(), name);
             } else {
                 return Err(anyhow!("Unknown command: {}", name));
             }
         }
 
-        Some(Commands::Rename { from, to }) => {
-            let mut config = RobinConfig::load_raw(&config_path)
-                .with_context(|| "No .robin.json found. Run 'robin init' first")?;
+        Some(Commands::Rename { from, to, global }) => {
+            let path = edited_config_path(*global, &config_path)?;
+            let mut config = load_edited(&path, *global)?;
 
             config.rename_script(from, to)?;
-            config.save(&config_path)?;
+            config.save(&path)?;
             println!(
                 "{} {} {} {}",
                 "Renamed command:".green(),
@@ -127,8 +164,7 @@ async fn dispatch(cli: &Cli) -> Result<()> {
         }
 
         Some(Commands::Migrate) => {
-            let config = RobinConfig::load_raw(&config_path)
-                .with_context(|| "No .robin.json found. Run 'robin init' first")?;
+            let config = RobinConfig::load_raw(&config_path)?;
             let migrated = config.migrated();
             migrated.save(&config_path)?;
             println!(
@@ -138,9 +174,49 @@ async fn dispatch(cli: &Cli) -> Result<()> {
             );
         }
 
+        Some(Commands::Convert { to }) => {
+            let converted = RobinConfig::convert(&config_path, *to)?;
+            if converted == config_path {
+                println!("{} is already {}", config_path.display(), to.name());
+            } else {
+                println!(
+                    "{} {} {} {}",
+                    "Converted".green(),
+                    config_path.display(),
+                    "→".dimmed(),
+                    converted.display()
+                );
+            }
+        }
+
+        Some(Commands::Validate { file }) => {
+            let path = file.clone().unwrap_or_else(|| config_path.clone());
+            if !path.exists() {
+                return Err(match file {
+                    Some(file) => anyhow!("{} doesn't exist", file.display()),
+                    None => missing_config_error(),
+                });
+            }
+            let problems = validate_config(&path);
+            for problem in &problems {
+                let label = match problem.severity {
+                    Severity::Error => "error:".red().bold(),
+                    Severity::Warning => "warning:".yellow().bold(),
+                };
+                println!("{}: {} {}", problem.location(), label, problem.message);
+            }
+            let errors = problems
+                .iter()
+                .filter(|p| p.severity == Severity::Error)
+                .count();
+            if errors > 0 {
+                return Err(anyhow!("{} error(s) found in {}", errors, path.display()));
+            }
+            println!("{} {} is valid", "✓".green(), path.display());
+        }
+
         Some(Commands::Doctor) => {
-            let config = RobinConfig::load(&config_path)
-                .with_context(|| "No .robin.json found. Run 'robin init' first")?;
+            let config = RobinConfig::load(&config_path)?;
             let (success, found, missing, duration) = check_environment(&config)?;
 
             if cli.notify {
@@ -159,8 +235,7 @@ async fn dispatch(cli: &Cli) -> Result<()> {
         }
 
         Some(Commands::DoctorUpdate) => {
-            let config = RobinConfig::load(&config_path)
-                .with_context(|| "No .robin.json found. Run 'robin init' first")?;
+            let config = RobinConfig::load(&config_path)?;
             let start_time = std::time::Instant::now();
             let (success, _updated_tools) = update_tools(&config)?;
 
@@ -175,80 +250,383 @@ async fn dispatch(cli: &Cli) -> Result<()> {
             }
         }
 
-        Some(Commands::Run(args)) => {
-            let make_mode = cli.make || args.iter().any(|a| a == "--make");
-            let scripts = if make_mode {
-                load_makefile_scripts(&makefile_path)?
-            } else {
-                let config = RobinConfig::load(&config_path)
-                    .with_context(|| "No .robin.json found. Run 'robin init' first")?;
-                // Load a `.env` sitting next to the config so tasks and variable
-                // substitution can use it.
-                load_env_file(&config_path);
-                config.scripts
-            };
+        Some(Commands::Completions { shell }) => {
+            print!("{}", completion_script(*shell));
+        }
 
-            let (script_name, var_args) = split_command_and_args(args);
+        Some(Commands::Complete { line }) => {
+            for candidate in complete_line(line) {
+                println!("{}", candidate);
+            }
+        }
 
-            // Robin's own flags are also accepted after the task name (e.g.
-            // `robin build --dry-run`), since the external subcommand captures
-            // everything trailing the command into these args.
-            let dry_run = cli.dry_run || var_args.iter().any(|a| a == "--dry-run");
-            let notify = cli.notify || var_args.iter().any(|a| a == "--notify");
-            let cwd = var_args
-                .iter()
-                .find_map(|a| a.strip_prefix("--cwd=").map(PathBuf::from))
-                .or_else(|| cli.cwd.clone());
-            let var_args: Vec<String> = var_args
-                .into_iter()
-                .filter(|a| {
-                    a != "--dry-run" && a != "--notify" && a != "--make" && !a.starts_with("--cwd=")
-                })
-                .collect();
-
-            if let Some(entry) = scripts.get(&script_name) {
-                let script = script_command(entry).ok_or_else(|| {
-                    anyhow!("Command '{}' has an invalid script definition", script_name)
-                })?;
-                let resolved = resolve_task_command(script, &scripts)?;
-                let script_with_vars = replace_variables(&resolved, &var_args)?;
-
-                if dry_run {
-                    let where_ = match &cwd {
+        Some(Commands::Run(args)) => {
+            let (scripts, defaults) = load_task_scripts(cli, args, &config_path, &makefile_path)?;
+            let invocation = parse_invocation(cli, args, &scripts, defaults)?;
+
+            if scripts.contains_key(&invocation.name) && invocation.help {
+                print_task_help(&invocation.name, &scripts, &invocation.options.vars)?;
+            } else if scripts.contains_key(&invocation.name) {
+                let plan = plan_task(
+                    &invocation.name,
+                    &scripts,
+                    &invocation.var_args,
+                    &invocation.options,
+                )?;
+
+                if invocation.dry_run {
+                    let root_dir = invocation.options.root.as_deref();
+                    let mut where_ = match &invocation.options.cwd {
                         Some(dir) => format!(" (in {})", dir.display()),
                         None => String::new(),
                     };
-                    println!(
-                        "{}",
-                        format!("Would run '{}'{}:", script_name, where_).dimmed()
-                    );
-                    for line in command_lines(&script_with_vars) {
-                        println!("  {}", line);
+                    if let Some(platform) = &invocation.options.platform {
+                        where_.push_str(&format!(" on {}", platform));
+                    }
+                    for task in &plan {
+                        let mode = if task.parallel { " in parallel" } else { "" };
+                        println!(
+                            "{}",
+                            format!("Would run '{}'{}{}:", task.name, mode, where_).dimmed()
+                        );
+                        for step in &task.steps {
+                            let dir = step
+                                .cwd
+                                .as_deref()
+                                .map(|dir| format!("(in {})", display_dir(dir, root_dir)).dimmed());
+                            if step.script {
+                                // Print the script body as written, one line each.
+                                if let Some(dir) = dir {
+                                    println!("  {}", dir);
+                                }
+                                for line in redact(&step.command).lines() {
+                                    println!("  {}", line);
+                                }
+                            } else {
+                                match dir {
+                                    Some(dir) => println!("  {}  {}", redact(&step.command), dir),
+                                    None => println!("  {}", redact(&step.command)),
+                                }
+                            }
+                        }
                     }
                 } else {
-                    run_script_in(&script_with_vars, notify, cwd.as_deref())?;
+                    run_plan(&plan, &invocation.options)?;
                 }
             } else {
-                println!("{} {}", "Unknown command:".red(), script_name);
+                println!("{} {}", "Unknown command:".red(), invocation.name);
             }
         }
 
+        Some(Commands::Watch { watch, args }) => {
+            let (scripts, mut defaults) =
+                load_task_scripts(cli, args, &config_path, &makefile_path)?;
+            let root = defaults
+                .root
+                .get_or_insert_with(|| PathBuf::from("."))
+                .clone();
+
+            // `--watch GLOB` / `--watch=GLOB` is also accepted after the task
+            // name (but not after `--`, where arguments belong to the task's
+            // command).
+            let (own, passthrough) = split_passthrough(args);
+            let mut patterns = watch.clone();
+            let mut task_args = Vec::new();
+            let mut words = own.iter();
+            while let Some(arg) = words.next() {
+                if arg == "--watch" {
+                    let glob = words
+                        .next()
+                        .ok_or_else(|| anyhow!("--watch needs a glob"))?;
+                    patterns.push(glob.clone());
+                } else if let Some(glob) = arg.strip_prefix("--watch=") {
+                    patterns.push(glob.to_string());
+                } else {
+                    task_args.push(arg.clone());
+                }
+            }
+            if own.len() < args.len() {
+                task_args.push("--".to_string());
+                task_args.extend_from_slice(passthrough);
+            }
+            let invocation = parse_invocation(cli, &task_args, &scripts, defaults)?;
+
+            if !scripts.contains_key(&invocation.name) {
+                return Err(anyhow!("Unknown command: {}", invocation.name));
+            }
+            watch_task(
+                &invocation.name,
+                &scripts,
+                &invocation.var_args,
+                &patterns,
+                &root,
+                &invocation.options,
+            )?;
+        }
+
         None => {
             if cli.make {
-                let scripts = load_makefile_scripts(&makefile_path)?;
+                let mut scripts = load_makefile_scripts(&makefile_path)?;
+                if cli.sort {
+                    scripts.sort_keys();
+                }
                 if cli.list {
-                    list_scripts(&scripts)?;
+                    let sources = TaskSources {
+                        files: scripts
+                            .keys()
+                            .map(|name| (name.clone(), makefile_path.clone()))
+                            .collect(),
+                        ..TaskSources::default()
+                    };
+                    print_task_list(&scripts, &sources, &[], cli.format)?;
                 } else {
                     interactive_scripts(&scripts)?;
                 }
             } else if cli.list {
-                list_commands(&config_path)?;
+                let (mut config, sources) = RobinConfig::load_with_sources(&config_path)?;
+                if cli.sort {
+                    config.scripts.sort_keys();
+                }
+                let shared = config_vars(&config.vars).context("Invalid top-level vars")?;
+                print_task_list(&config.scripts, &sources, &shared, cli.format)?;
             } else {
                 load_env_file(&config_path);
-                interactive_mode(&config_path)?;
+                interactive_mode(&config_path, cli.sort)?;
             }
         }
     }
 
     Ok(())
 }
+
+/// Whether the nearest config (or Makefile, with `--make`) has a task `name`.
+/// A config that fails to load has none, leaving the error to the command.
+fn defines_task(name: &str, make: bool) -> bool {
+    if make {
+        return load_makefile_scripts(&find_makefile_path())
+            .is_ok_and(|scripts| scripts.contains_key(name));
+    }
+    RobinConfig::load(&find_config_path()).is_ok_and(|config| config.scripts.contains_key(name))
+}
+
+/// Shows a task's directory relative to the project root when it lies inside
+/// it, so `--dry-run` output stays short.
+fn display_dir(dir: &Path, root: Option<&Path>) -> String {
+    root.and_then(|root| dir.strip_prefix(root).ok())
+        .unwrap_or(dir)
+        .display()
+        .to_string()
+}
+
+/// The config `add`, `rm` and `rename` edit: the project's, or with `--global`
+/// the global task library.
+fn edited_config_path(global: bool, config_path: &Path) -> Result<PathBuf> {
+    if !global {
+        return Ok(config_path.to_path_buf());
+    }
+    global_config_path().ok_or_else(|| {
+        anyhow!("No global task library: ROBIN_GLOBAL_CONFIG is empty or there's no user config directory")
+    })
+}
+
+/// Reads the config `rm` and `rename` edit, without following `include`.
+fn load_edited(path: &Path, global: bool) -> Result<RobinConfig> {
+    if global && !path.exists() {
+        return Err(anyhow!(
+            "No global tasks yet: {} doesn't exist. Add one with 'robin add --global'",
+            path.display()
+        ));
+    }
+    RobinConfig::load_raw(path)
+}
+
+/// Loads the tasks for `robin <task>` / `robin watch <task>`: Makefile targets in
+/// `--make` mode, otherwise the merged `.robin.json` (loading the `.env` next to
+/// it). Also returns the run options the config implies: the project root (the
+/// directory of the config or Makefile) used for fingerprints and file
+/// watching, and the config's default `shell` and top-level `vars`.
+fn load_task_scripts(
+    cli: &Cli,
+    args: &[String],
+    config_path: &Path,
+    makefile_path: &Path,
+) -> Result<(IndexMap<String, Value>, RunOptions)> {
+    let make_mode = cli.make || args.iter().any(|a| a == "--make");
+    if make_mode {
+        let defaults = RunOptions {
+            root: makefile_path.parent().map(Path::to_path_buf),
+            ..RunOptions::default()
+        };
+        return Ok((load_makefile_scripts(makefile_path)?, defaults));
+    }
+
+    let config = RobinConfig::load(config_path)?;
+    // Load a `.env` sitting next to the config so tasks and variable
+    // substitution can use it.
+    load_env_file(config_path);
+    let defaults = RunOptions {
+        root: config_path.parent().map(Path::to_path_buf),
+        shell: config
+            .shell
+            .as_ref()
+            .map(shell_argv)
+            .transpose()
+            .context("Invalid top-level shell")?,
+        vars: config_vars(&config.vars).context("Invalid top-level vars")?,
+        secret_env: config.secret_env.clone(),
+        ..RunOptions::default()
+    };
+    Ok((config.scripts, defaults))
+}
+
+/// A task name plus everything that follows it on the command line, split into
+/// robin's own flags and the `--var=value` arguments for the task.
+struct Invocation {
+    name: String,
+    var_args: Vec<String>,
+    dry_run: bool,
+    /// `robin <task> --help`: describe the task instead of running it.
+    help: bool,
+    options: RunOptions,
+}
+
+fn parse_jobs(n: &str) -> Result<usize> {
+    n.parse::<usize>()
+        .map_err(|_| anyhow!("Invalid value for --jobs: {}", n))
+}
+
+fn parse_invocation(
+    cli: &Cli,
+    args: &[String],
+    scripts: &IndexMap<String, Value>,
+    defaults: RunOptions,
+) -> Result<Invocation> {
+    let (mut name, mut var_args) = split_command_and_args(args);
+    // Everything after a bare `--` is passed through to the task's command,
+    // never read as robin's own flags.
+    let mut passthrough = match var_args.iter().position(|a| a == "--") {
+        Some(i) => var_args.split_off(i),
+        None => Vec::new(),
+    };
+    // Words after the task's name are its positional arguments, as if they
+    // came after `--` (`robin greet Alice`). The longest run of leading words
+    // that names a task wins, so names with spaces still work.
+    let mut positional = Vec::new();
+    if !scripts.contains_key(&name) {
+        let words: Vec<&str> = name.split(' ').collect();
+        if let Some(len) = (1..words.len())
+            .rev()
+            .find(|&len| scripts.contains_key(&words[..len].join(" ")))
+        {
+            positional = words[len..].iter().map(|w| w.to_string()).collect();
+            name = words[..len].join(" ");
+        }
+    }
+
+    // Robin's own flags are also accepted after the task name (e.g.
+    // `robin build --dry-run` or `robin build -j 4`), since the external
+    // subcommand captures everything trailing the command into these args.
+    let mut dry_run = cli.dry_run;
+    let mut notify = cli.notify;
+    let mut force = cli.force;
+    let mut no_input = cli.no_input;
+    let mut help = false;
+    let mut cwd = None;
+    let mut var_files = cli.var_file.clone();
+    let mut jobs = None;
+    let mut platform = cli.platform.clone();
+    // After the task name `--platform` is robin's, unless the task takes a
+    // `platform` variable of its own (as in `{{platform=[ios,android]}}`).
+    let own_platform = !takes_variable(&name, scripts, "platform");
+    let mut task_args = Vec::new();
+    let mut words = var_args.into_iter();
+    while let Some(arg) = words.next() {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            "--notify" => notify = true,
+            "--force" => force = true,
+            "--no-input" => no_input = true,
+            "--help" => help = true,
+            "--make" => {}
+            "-j" | "--jobs" => {
+                let n = words
+                    .next()
+                    .ok_or_else(|| anyhow!("{} needs a number of jobs", arg))?;
+                jobs = Some(parse_jobs(&n)?);
+            }
+            "--var-file" => {
+                let file = words
+                    .next()
+                    .ok_or_else(|| anyhow!("--var-file needs a file"))?;
+                var_files.push(PathBuf::from(file));
+            }
+            "--platform" => {
+                let os = words
+                    .next()
+                    .ok_or_else(|| anyhow!("--platform needs a platform"))?;
+                match own_platform {
+                    true => platform = Some(os),
+                    false => task_args.push(format!("--platform={}", os)),
+                }
+            }
+            _ => {
+                if let Some(dir) = arg.strip_prefix("--cwd=") {
+                    cwd = Some(PathBuf::from(dir));
+                } else if let Some(n) = arg.strip_prefix("--jobs=") {
+                    jobs = Some(parse_jobs(n)?);
+                } else if let Some(file) = arg.strip_prefix("--var-file=") {
+                    var_files.push(PathBuf::from(file));
+                } else if let Some(os) = arg.strip_prefix("--platform=").filter(|_| own_platform) {
+                    platform = Some(os.to_string());
+                } else if arg.starts_with('-') {
+                    task_args.push(arg);
+                } else {
+                    positional.push(arg);
+                }
+            }
+        }
+    }
+    let cwd = cwd.or_else(|| cli.cwd.clone());
+    let jobs = jobs.or(cli.jobs);
+    let mut var_values = Vec::new();
+    for file in &var_files {
+        var_values.extend(read_var_file(file)?);
+    }
+    if let Some(platform) = &platform {
+        if !PLATFORMS.contains(&platform.as_str()) {
+            return Err(anyhow!(
+                "Unknown platform '{}' (expected one of: {})",
+                platform,
+                PLATFORMS.join(", ")
+            ));
+        }
+        if !dry_run {
+            return Err(anyhow!("--platform can only be used with --dry-run"));
+        }
+    }
+    if !positional.is_empty() {
+        if passthrough.is_empty() {
+            passthrough.push("--".to_string());
+        }
+        passthrough.splice(1..1, positional);
+    }
+    let mut var_args = task_args;
+    var_args.extend(passthrough);
+
+    Ok(Invocation {
+        name,
+        var_args,
+        dry_run,
+        help,
+        options: RunOptions {
+            notify,
+            cwd,
+            jobs,
+            force,
+            platform,
+            prompt: !no_input,
+            var_values,
+            ..defaults
+        },
+    })
+}
@@ -1,10 +1,13 @@
 use anyhow::{Context, Result, anyhow};
+use indexmap::IndexMap;
 use regex::Regex;
 use serde_json::{Value, json};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+use crate::utils::shell_quote;
+
 const MAKEFILE_NAMES: &[&str] = &["Makefile", "makefile", "GNUmakefile"];
 
 /// Walks up from `start` (inclusive) looking for a Makefile.
@@ -38,14 +41,14 @@ struct MakefileTarget {
 /// Parses a Makefile and returns robin-compatible script entries keyed by target
 /// name. Each entry runs the target via `make` so dependencies and variables are
 /// handled correctly.
-pub fn parse_makefile(content: &str, makefile_dir: &Path) -> Result<HashMap<String, Value>> {
+pub fn parse_makefile(content: &str, makefile_dir: &Path) -> Result<IndexMap<String, Value>> {
     let targets = parse_targets(content)?;
     let dir = makefile_dir
         .canonicalize()
         .unwrap_or_else(|_| makefile_dir.to_path_buf());
     let make_cmd = format!("make -C {}", shell_quote(&dir.display().to_string()));
 
-    let mut scripts = HashMap::new();
+    let mut scripts = IndexMap::new();
     for target in targets {
         let cmd = format!("{} {}", make_cmd, target.name);
         let entry = if let Some(desc) = target.description.filter(|s| !s.is_empty()) {
@@ -59,7 +62,7 @@ pub fn parse_makefile(content: &str, makefile_dir: &Path) -> Result<HashMap<Stri
     Ok(scripts)
 }
 
-pub fn load_makefile_scripts(path: &Path) -> Result<HashMap<String, Value>> {
+pub fn load_makefile_scripts(path: &Path) -> Result<IndexMap<String, Value>> {
     if !path.exists() {
         return Err(anyhow!(
             "No Makefile found. Looked for Makefile, makefile, or GNUmakefile"
@@ -73,16 +76,6 @@ pub fn load_makefile_scripts(path: &Path) -> Result<HashMap<String, Value>> {
     parse_makefile(&content, dir)
 }
 
-fn shell_quote(s: &str) -> String {
-    if s.chars()
-        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.'))
-    {
-        s.to_string()
-    } else {
-        format!("'{}'", s.replace('\'', "'\\''"))
-    }
-}
-
 fn parse_targets(content: &str) -> Result<Vec<MakefileTarget>> {
     let target_line = Regex::new(
         r"^([A-Za-z0-9_.][A-Za-z0-9_.$-]*(?:\s+[A-Za-z0-9_.][A-Za-z0-9_.$-]*)*)\s*:([^=].*)?$",
@@ -0,0 +1,215 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::globs::matching_files;
+use super::script_runner::command_lines;
+use super::task_graph::PlannedTask;
+
+/// Where fingerprints are stored, relative to the project root.
+const CACHE_FILE: &str = ".robin/fingerprints.json";
+
+/// Remembers, per task, the fingerprint of its last successful run so tasks
+/// whose inputs haven't changed can be skipped. Stored as a small JSON map under
+/// the project root; a missing or unreadable file simply means "nothing cached".
+#[derive(Debug)]
+pub(crate) struct FingerprintCache {
+    root: PathBuf,
+    entries: HashMap<String, String>,
+}
+
+impl FingerprintCache {
+    pub fn load(root: &Path) -> Self {
+        let entries = fs::read_to_string(root.join(CACHE_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self {
+            root: root.to_path_buf(),
+            entries,
+        }
+    }
+
+    /// A task is up to date when it declares `inputs`, its current fingerprint
+    /// (`current`) matches the one recorded after its last successful run, and
+    /// every declared output still exists.
+    pub fn is_up_to_date(&self, task: &PlannedTask, current: &str) -> bool {
+        if task.inputs.is_empty() {
+            return false;
+        }
+        let Some(recorded) = self.entries.get(&task.name) else {
+            return false;
+        };
+        task.outputs.iter().all(|o| self.root.join(o).exists()) && recorded == current
+    }
+
+    /// Records `hash` as the fingerprint of the task `name` once it has run
+    /// successfully. `hash` must be taken before the task starts: an input
+    /// edited while it runs then still makes the next run rerun it.
+    pub fn record(&mut self, name: &str, hash: &str) -> Result<()> {
+        self.entries.insert(name.to_string(), hash.to_string());
+
+        let path = self.root.join(CACHE_FILE);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(&self.entries)?)
+            .with_context(|| format!("Failed to write fingerprint cache: {}", path.display()))?;
+        Ok(())
+    }
+}
+
+/// Hashes everything that decides a task's result: its fully-resolved command
+/// lines with the directories and shells they run in, the path and contents of
+/// every file matched by its `inputs` globs (relative to `root`), its declared
+/// `outputs`, its own `env`, and the values of environment variables the
+/// commands reference.
+pub(crate) fn fingerprint(task: &PlannedTask, root: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    let lines = command_lines(&task.script());
+
+    for line in &lines {
+        field(&mut hasher, "cmd", line.as_bytes());
+    }
+    for dir in task.steps.iter().filter_map(|s| s.cwd.as_deref()) {
+        let relative = dir.strip_prefix(root).unwrap_or(dir);
+        field(&mut hasher, "cwd", relative.to_string_lossy().as_bytes());
+    }
+    for shell in task.steps.iter().filter_map(|s| s.shell.as_ref()) {
+        field(&mut hasher, "shell", shell.join(" ").as_bytes());
+    }
+    for output in &task.outputs {
+        field(&mut hasher, "out", output.as_bytes());
+    }
+    let task_env: BTreeSet<&(String, String)> =
+        task.steps.iter().flat_map(|s| s.env.iter()).collect();
+    for (name, value) in task_env {
+        field(
+            &mut hasher,
+            "task-env",
+            format!("{}={}", name, value).as_bytes(),
+        );
+    }
+    for name in referenced_env_vars(&lines) {
+        let value = std::env::var(&name).unwrap_or_default();
+        field(&mut hasher, "env", format!("{}={}", name, value).as_bytes());
+    }
+    for file in matching_files(&task.inputs, root)? {
+        let relative = file.strip_prefix(root).unwrap_or(&file);
+        field(&mut hasher, "path", relative.to_string_lossy().as_bytes());
+        let contents = fs::read(&file)
+            .with_context(|| format!("Failed to read input file: {}", file.display()))?;
+        field(&mut hasher, "data", &contents);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Feeds one length-prefixed, tagged field into the hash so that adjacent
+/// fields can never run together into the same byte stream.
+fn field(hasher: &mut Sha256, tag: &str, bytes: &[u8]) {
+    hasher.update(tag.as_bytes());
+    hasher.update((bytes.len() as u64).to_le_bytes());
+    hasher.update(bytes);
+}
+
+/// Names of the environment variables (`$VAR` or `${VAR...}`) the commands use.
+fn referenced_env_vars(lines: &[String]) -> BTreeSet<String> {
+    let env_regex = Regex::new(r"\$\{?([A-Za-z_][A-Za-z0-9_]*)").unwrap();
+    lines
+        .iter()
+        .flat_map(|line| env_regex.captures_iter(line))
+        .map(|c| c[1].to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::script_runner::Step;
+
+    fn task(inputs: &[&str], outputs: &[&str]) -> PlannedTask {
+        PlannedTask {
+            name: "gen".to_string(),
+            steps: vec![Step::new("protoc --out=gen $PROTO_FLAGS")],
+            sequence: false,
+            deps: vec![],
+            parallel: false,
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            outputs: outputs.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn fingerprint_changes_with_input_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("proto")).unwrap();
+        fs::write(dir.path().join("proto/a.proto"), "v1").unwrap();
+        let task = task(&["proto/*.proto"], &[]);
+
+        let before = fingerprint(&task, dir.path()).unwrap();
+        assert_eq!(before, fingerprint(&task, dir.path()).unwrap());
+
+        fs::write(dir.path().join("proto/a.proto"), "v2").unwrap();
+        assert_ne!(before, fingerprint(&task, dir.path()).unwrap());
+    }
+
+    #[test]
+    fn fingerprint_changes_with_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = task(&["*.txt"], &[]);
+        let mut b = a.clone();
+        b.steps = vec![Step::new("protoc --out=other")];
+        assert_ne!(
+            fingerprint(&a, dir.path()).unwrap(),
+            fingerprint(&b, dir.path()).unwrap()
+        );
+    }
+
+    #[test]
+    fn cache_reports_up_to_date_only_after_recording_and_with_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("in.txt"), "x").unwrap();
+        let task = task(&["in.txt"], &["out.txt"]);
+        let current = fingerprint(&task, dir.path()).unwrap();
+
+        let mut cache = FingerprintCache::load(dir.path());
+        assert!(!cache.is_up_to_date(&task, &current));
+
+        cache.record(&task.name, &current).unwrap();
+        // The declared output is missing, so the task must still run.
+        assert!(!cache.is_up_to_date(&task, &current));
+
+        fs::write(dir.path().join("out.txt"), "built").unwrap();
+        let reloaded = FingerprintCache::load(dir.path());
+        assert!(reloaded.is_up_to_date(&task, &current));
+    }
+
+    #[test]
+    fn an_input_edited_during_the_run_is_not_recorded_as_up_to_date() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("in.txt"), "v1").unwrap();
+        let task = task(&["in.txt"], &[]);
+
+        // Taken when the task starts; the input changes while it runs.
+        let started = fingerprint(&task, dir.path()).unwrap();
+        fs::write(dir.path().join("in.txt"), "v2").unwrap();
+        let mut cache = FingerprintCache::load(dir.path());
+        cache.record(&task.name, &started).unwrap();
+
+        let now = fingerprint(&task, dir.path()).unwrap();
+        assert!(!cache.is_up_to_date(&task, &now));
+    }
+
+    #[test]
+    fn tasks_without_inputs_are_never_up_to_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let task = task(&[], &[]);
+        let current = fingerprint(&task, dir.path()).unwrap();
+        let cache = FingerprintCache::load(dir.path());
+        assert!(!cache.is_up_to_date(&task, &current));
+    }
+}
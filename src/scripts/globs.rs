@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Builds the matcher for `inputs` and `--watch` globs. They're matched against
+/// paths relative to the project root, and `*` stays within one path segment
+/// (`**` crosses them), so both agree on what a pattern covers.
+pub(crate) fn glob_set(patterns: &[String]) -> Result<GlobSet> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid glob: {}", pattern))?;
+        globs.add(glob);
+    }
+    Ok(globs.build()?)
+}
+
+/// The files under `root` matched by `patterns`, sorted and de-duplicated.
+/// Only the directories a pattern can reach are walked: `src/**/*.rs` starts at
+/// `src`, so a large `target/` or `node_modules/` is never visited.
+pub(crate) fn matching_files(patterns: &[String], root: &Path) -> Result<BTreeSet<PathBuf>> {
+    let globs = glob_set(patterns)?;
+    let bases: BTreeSet<PathBuf> = patterns.iter().map(|p| literal_prefix(p)).collect();
+
+    let mut files = BTreeSet::new();
+    for base in &bases {
+        // A base inside another one is already covered by its walk.
+        if bases
+            .iter()
+            .any(|other| other != base && base.starts_with(other))
+        {
+            continue;
+        }
+        let start = root.join(base);
+        if !start.exists() {
+            continue;
+        }
+        for entry in WalkBuilder::new(&start).standard_filters(false).build() {
+            let entry = entry?;
+            if !entry.file_type().is_some_and(|t| t.is_file()) {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            if globs.is_match(relative) {
+                files.insert(entry.into_path());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// The leading directories of `pattern` that contain no glob syntax.
+fn literal_prefix(pattern: &str) -> PathBuf {
+    let mut segments: Vec<&str> = pattern.split('/').collect();
+    // The last segment names files, never a directory to start from.
+    segments.pop();
+    segments
+        .into_iter()
+        .take_while(|s| !s.contains(['*', '?', '[', '{', '\\']))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn star_stays_within_a_segment_and_double_star_crosses_them() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("src/a")).unwrap();
+        fs::write(dir.path().join("src/main.rs"), "").unwrap();
+        fs::write(dir.path().join("src/a/b.rs"), "").unwrap();
+        fs::write(dir.path().join("top.rs"), "").unwrap();
+
+        let shallow = matching_files(&["src/*.rs".to_string()], dir.path()).unwrap();
+        assert_eq!(shallow, BTreeSet::from([dir.path().join("src/main.rs")]));
+
+        let deep = matching_files(&["**/*.rs".to_string()], dir.path()).unwrap();
+        assert_eq!(deep.len(), 3);
+    }
+
+    #[test]
+    fn literal_prefix_stops_at_the_first_glob_segment() {
+        assert_eq!(literal_prefix("src/**/*.rs"), PathBuf::from("src"));
+        assert_eq!(
+            literal_prefix("proto/v1/*.proto"),
+            PathBuf::from("proto/v1")
+        );
+        assert_eq!(literal_prefix("*.txt"), PathBuf::new());
+        assert_eq!(literal_prefix("a/{b,c}/d.txt"), PathBuf::from("a"));
+    }
+}
@@ -1,6 +1,20 @@
+mod fingerprint;
+mod globs;
+mod parallel;
+mod prompt;
 mod script_runner;
+mod task_graph;
+mod task_info;
+mod variables;
+mod watch;
 
 pub use script_runner::{
-    command_lines, interactive_mode, interactive_scripts, list_commands, list_scripts,
+    Step, command_lines, interactive_mode, interactive_scripts, list_commands, list_scripts,
     resolve_task_command, run_script, run_script_in,
 };
+pub(crate) use script_runner::{resolve_lines, task_command};
+pub use task_graph::{PlannedTask, RunOptions, plan_task, run_plan, task_order};
+pub use task_info::{TaskInfo, print_task_help, print_task_list, task_infos};
+pub use variables::takes_variable;
+pub(crate) use variables::task_variables;
+pub use watch::watch_task;
@@ -0,0 +1,242 @@
+use anyhow::{Context, Result, anyhow};
+use colored::*;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+use super::script_runner::{Step, shell_command, wait_cancellable};
+
+/// Colors cycled through for step prefixes so interleaved output stays readable.
+const PREFIX_COLORS: &[Color] = &[
+    Color::Cyan,
+    Color::Magenta,
+    Color::Yellow,
+    Color::Green,
+    Color::Blue,
+];
+
+/// A unit of work for the parallel runner: steps run one after another
+/// once every node listed in `deps` (by index) has succeeded.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Node {
+    pub label: String,
+    pub commands: Vec<Step>,
+    pub deps: Vec<usize>,
+}
+
+/// Runs a dependency graph of nodes with at most `limit` of them at once.
+///
+/// Each line a step prints is forwarded as soon as it is complete, prefixed with
+/// the node's label. The first failure cancels everything still running (the
+/// child processes are killed) and nothing new is started; that failure is the
+/// error returned. Raising `cancel` from outside stops the run the same way,
+/// but a failure never raises it, so the caller can tell the two apart.
+/// `on_success` is called with the index of every node that completes
+/// successfully.
+pub(crate) fn run_graph(
+    nodes: &[Node],
+    limit: usize,
+    cwd: Option<&Path>,
+    cancel: &AtomicBool,
+    on_success: &mut dyn FnMut(usize),
+) -> Result<()> {
+    let limit = limit.max(1);
+    let width = nodes.iter().map(|n| n.label.len()).max().unwrap_or(0);
+
+    let mut remaining: Vec<usize> = nodes.iter().map(|n| n.deps.len()).collect();
+    let mut dependents = vec![Vec::new(); nodes.len()];
+    for (i, node) in nodes.iter().enumerate() {
+        for &dep in &node.deps {
+            dependents[dep].push(i);
+        }
+    }
+    let mut ready: VecDeque<usize> = (0..nodes.len()).filter(|&i| remaining[i] == 0).collect();
+
+    let (tx, rx) = mpsc::channel::<(usize, Result<()>)>();
+    let mut first_error = None;
+    let abort = AtomicBool::new(false);
+    let stopped = || cancel.load(Ordering::SeqCst) || abort.load(Ordering::SeqCst);
+
+    thread::scope(|scope| {
+        let mut running = 0;
+        loop {
+            while first_error.is_none() && !stopped() && running < limit {
+                let Some(i) = ready.pop_front() else { break };
+                let node = &nodes[i];
+                let prefix = format!("[{:<width$}]", node.label, width = width)
+                    .color(PREFIX_COLORS[i % PREFIX_COLORS.len()])
+                    .to_string();
+                let tx = tx.clone();
+                let stopped = &stopped;
+                scope.spawn(move || {
+                    let result = run_node(node, &prefix, cwd, stopped);
+                    let _ = tx.send((i, result));
+                });
+                running += 1;
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let Ok((i, result)) = rx.recv() else { break };
+            running -= 1;
+            match result {
+                Ok(()) => {
+                    on_success(i);
+                    for &d in &dependents[i] {
+                        remaining[d] -= 1;
+                        if remaining[d] == 0 {
+                            ready.push_back(d);
+                        }
+                    }
+                }
+                Err(e) => {
+                    if first_error.is_none() {
+                        abort.store(true, Ordering::SeqCst);
+                        first_error = Some(e);
+                    }
+                }
+            }
+        }
+    });
+
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn run_node(
+    node: &Node,
+    prefix: &str,
+    cwd: Option<&Path>,
+    stopped: &(dyn Fn() -> bool + Sync),
+) -> Result<()> {
+    for step in &node.commands {
+        let cmd = &step.summary();
+        if stopped() {
+            return Err(anyhow!("Cancelled: {}", cmd));
+        }
+        println!("{} {} {}", prefix, "▶".cyan().bold(), cmd);
+
+        let (mut command, _script) = shell_command(step, cwd)?;
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute script: {}", cmd))?;
+
+        let stdout = child.stdout.take().map(|out| forward(out, prefix, false));
+        let stderr = child.stderr.take().map(|err| forward(err, prefix, true));
+
+        // On cancellation, don't wait for the output forwarders: a grandchild
+        // may still hold the pipes open after the shell itself is gone.
+        let status = wait_cancellable(&mut child, cmd, stopped)?;
+
+        for forwarder in [stdout, stderr].into_iter().flatten() {
+            let _ = forwarder.join();
+        }
+
+        if !status.success() {
+            println!("{} {}", prefix, format!("Script failed: {}", cmd).red());
+            return Err(anyhow!("Script failed: {}", cmd));
+        }
+    }
+    Ok(())
+}
+
+/// Copies a child's output to ours line by line, each line tagged with `prefix`.
+/// Lines that aren't valid UTF-8 are printed lossily rather than ending the
+/// copy, so the child never writes to a closed pipe.
+fn forward(
+    stream: impl Read + Send + 'static,
+    prefix: &str,
+    to_stderr: bool,
+) -> thread::JoinHandle<()> {
+    let prefix = prefix.to_string();
+    thread::spawn(move || {
+        for chunk in BufReader::new(stream).split(b'\n').map_while(Result::ok) {
+            let line = String::from_utf8_lossy(&chunk);
+            let line = line.strip_suffix('\r').unwrap_or(&line);
+            if to_stderr {
+                eprintln!("{} {}", prefix, line);
+            } else {
+                println!("{} {}", prefix, line);
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    fn node(label: &str, commands: &[&str], deps: &[usize]) -> Node {
+        Node {
+            label: label.to_string(),
+            commands: commands.iter().map(|c| Step::new(*c)).collect(),
+            deps: deps.to_vec(),
+        }
+    }
+
+    #[test]
+    fn independent_nodes_run_concurrently() {
+        let nodes = vec![
+            node("a", &["sleep 0.3"], &[]),
+            node("b", &["sleep 0.3"], &[]),
+            node("c", &["sleep 0.3"], &[]),
+        ];
+        let start = Instant::now();
+        run_graph(&nodes, 3, None, &AtomicBool::new(false), &mut |_| {}).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(800));
+    }
+
+    #[test]
+    fn dependencies_finish_before_dependents_start() {
+        let dir = tempfile::tempdir().unwrap();
+        let nodes = vec![
+            node("first", &["sleep 0.1", "touch done"], &[]),
+            node("second", &["test -f done"], &[0]),
+        ];
+        run_graph(
+            &nodes,
+            2,
+            Some(dir.path()),
+            &AtomicBool::new(false),
+            &mut |_| {},
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn output_that_is_not_utf8_is_forwarded_to_the_end() {
+        // Stopping at the bad line would close the pipe and kill `seq`.
+        let nodes = vec![
+            node("bytes", &["printf 'bad \\377\\n'; seq 1 20000"], &[]),
+            node("other", &["true"], &[]),
+        ];
+        run_graph(&nodes, 2, None, &AtomicBool::new(false), &mut |_| {}).unwrap();
+    }
+
+    #[test]
+    fn first_failure_cancels_siblings() {
+        let nodes = vec![
+            node("fails", &["false"], &[]),
+            node("slow", &["sleep 5"], &[]),
+            node("after", &["echo never"], &[0]),
+        ];
+        let start = Instant::now();
+        let cancel = AtomicBool::new(false);
+        let err = run_graph(&nodes, 2, None, &cancel, &mut |_| {}).unwrap_err();
+        assert!(err.to_string().contains("Script failed: false"), "{err}");
+        assert!(start.elapsed() < Duration::from_secs(3));
+        // The caller's flag means "stopped from outside"; a failure leaves it.
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+}
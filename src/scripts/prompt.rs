@@ -0,0 +1,99 @@
+use anyhow::Result;
+use inquire::validator::Validation;
+use inquire::{Password, PasswordDisplayMode, Select, Text};
+
+use crate::config::VarSpec;
+use crate::utils::{MASK, split_passthrough};
+
+/// The required variables in `variables` that have no default (static or
+/// computed) and no `--name=` in `args`.
+pub(crate) fn missing_variables<'a>(variables: &'a [VarSpec], args: &[String]) -> Vec<&'a VarSpec> {
+    let (args, _) = split_passthrough(args);
+    variables
+        .iter()
+        .filter(|v| v.required && v.default.is_none() && v.from_cmd.is_none())
+        .filter(|v| {
+            let flag = format!("--{}=", v.name);
+            !args.iter().any(|a| a.starts_with(&flag))
+        })
+        .collect()
+}
+
+/// Asks on the terminal for a value of each variable `task` is missing: a list
+/// to pick from for enum and bool variables, a text input checked against the
+/// variable's type and pattern otherwise, masked for `secret` variables.
+/// Returns the answers as `(name, value)` pairs.
+pub(crate) fn prompt_for_variables(
+    task: &str,
+    variables: &[&VarSpec],
+) -> Result<Vec<(String, String)>> {
+    variables
+        .iter()
+        .map(|var| {
+            let label = format!("{}:", var.name);
+            let help = match &var.description {
+                Some(desc) => desc.clone(),
+                None => format!("Required by '{}'", task),
+            };
+            let values = var.values();
+            let spec = (*var).clone();
+            let validator = move |input: &str| {
+                if input.is_empty() {
+                    return Ok(Validation::Invalid("A value is required".into()));
+                }
+                Ok(match spec.check(input) {
+                    Ok(()) => Validation::Valid,
+                    Err(e) if spec.secret => {
+                        Validation::Invalid(e.to_string().replace(input, MASK).into())
+                    }
+                    Err(e) => Validation::Invalid(e.to_string().into()),
+                })
+            };
+            let value = if var.secret && values.is_empty() {
+                Password::new(&label)
+                    .with_help_message(&help)
+                    .with_display_mode(PasswordDisplayMode::Masked)
+                    .without_confirmation()
+                    .with_validator(validator)
+                    .prompt()?
+            } else if values.is_empty() {
+                Text::new(&label)
+                    .with_help_message(&help)
+                    .with_validator(validator)
+                    .prompt()?
+            } else {
+                Select::new(&label, values)
+                    .with_help_message(&help)
+                    .prompt()?
+            };
+            Ok((var.name.clone(), value))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_variables_skips_defaults_optional_and_passed_values() {
+        let vars = vec![
+            VarSpec::new("target"),
+            VarSpec {
+                default: Some("eu".to_string()),
+                required: false,
+                ..VarSpec::new("region")
+            },
+            VarSpec {
+                required: false,
+                ..VarSpec::new("notes")
+            },
+            VarSpec::new("tag"),
+        ];
+
+        let missing = missing_variables(&vars, &["--tag=v1".to_string()]);
+
+        let names: Vec<_> = missing.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["target"]);
+    }
+}
@@ -2,16 +2,26 @@ use anyhow::{Context, Result, anyhow};
 use colored::*;
 use fuzzy_matcher::FuzzyMatcher;
 use fuzzy_matcher::skim::SkimMatcherV2;
+use indexmap::IndexMap;
 use inquire::Select;
 use serde_json;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::borrow::Cow;
 use std::fmt;
-use std::path::Path;
-use std::process::Command;
-
-use crate::config::{RobinConfig, script_command, script_description};
-use crate::utils::send_notification;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tempfile::TempPath;
+
+use super::task_graph::{RunOptions, plan_task, run_plan};
+use crate::config::{
+    RobinConfig, TaskSources, config_vars, current_platform, is_script_block, script_block,
+    script_command, script_command_for, script_description, script_platforms, script_vars,
+    shell_argv,
+};
+use crate::utils::{POSIX_SHELLS, redact, send_notification, shell_program};
 
 /// Expands a task's command, resolving any `@task` references into the commands
 /// of the referenced task (recursively), and returns a flattened command ready
@@ -21,7 +31,7 @@ use crate::utils::send_notification;
 /// tasks keep their single-command behaviour (including notifications). Anything
 /// that contains a reference, or is an array, flattens to an array of shell
 /// commands. Variable substitution is applied later, by the caller.
-pub fn resolve_task_command(cmd: &Value, scripts: &HashMap<String, Value>) -> Result<Value> {
+pub fn resolve_task_command(cmd: &Value, scripts: &IndexMap<String, Value>) -> Result<Value> {
     // Fast path: a plain command with no reference needs no expansion.
     if let Value::String(s) = cmd {
         if !s.trim_start().starts_with('@') {
@@ -29,38 +39,63 @@ pub fn resolve_task_command(cmd: &Value, scripts: &HashMap<String, Value>) -> Re
         }
     }
 
-    let mut out = Vec::new();
-    let mut stack = Vec::new();
-    resolve_into(cmd, scripts, &mut stack, &mut out)?;
-    Ok(Value::Array(out.into_iter().map(Value::String).collect()))
+    let lines = resolve_lines(cmd, scripts, current_platform())?;
+    Ok(Value::Array(
+        lines
+            .into_iter()
+            .map(|line| Value::String(line.command))
+            .collect(),
+    ))
 }
 
 /// Flattens a resolved command into the individual shell command lines it will
-/// run: a single string yields one line, an array yields one line per element.
-/// Used by `--dry-run` to preview exactly what would execute.
+/// run: a single string yields one line, an array yields one line per element
+/// (nested arrays, as produced for the steps of a parallel task, are flattened
+/// too). Used by `--dry-run` to preview exactly what would execute.
 pub fn command_lines(script: &Value) -> Vec<String> {
     match script {
         Value::String(s) => vec![s.clone()],
-        Value::Array(items) => items
-            .iter()
-            .filter_map(|c| c.as_str().map(str::to_string))
-            .collect(),
+        Value::Array(items) => items.iter().flat_map(command_lines).collect(),
         _ => Vec::new(),
     }
 }
 
+/// A command line produced by expanding `@task` references, along with the
+/// chain of tasks it was reached through (outermost first; empty for the task's
+/// own commands) so each line can run with the settings of the task that
+/// declared it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ResolvedLine {
+    pub command: String,
+    pub via: Vec<String>,
+}
+
+/// Expands `@task` references in `cmd` into the command lines they stand for,
+/// using each referenced task's command for `platform`.
+pub(crate) fn resolve_lines(
+    cmd: &Value,
+    scripts: &IndexMap<String, Value>,
+    platform: &str,
+) -> Result<Vec<ResolvedLine>> {
+    let mut out = Vec::new();
+    let mut stack = Vec::new();
+    resolve_into(cmd, scripts, platform, &mut stack, &mut out)?;
+    Ok(out)
+}
+
 fn resolve_into(
     cmd: &Value,
-    scripts: &HashMap<String, Value>,
+    scripts: &IndexMap<String, Value>,
+    platform: &str,
     stack: &mut Vec<String>,
-    out: &mut Vec<String>,
+    out: &mut Vec<ResolvedLine>,
 ) -> Result<()> {
     match cmd {
-        Value::String(s) => resolve_command_str(s, scripts, stack, out),
+        Value::String(s) => resolve_command_str(s, scripts, platform, stack, out),
         Value::Array(items) => {
             for item in items {
                 if let Some(s) = item.as_str() {
-                    resolve_command_str(s, scripts, stack, out)?;
+                    resolve_command_str(s, scripts, platform, stack, out)?;
                 }
             }
             Ok(())
@@ -73,58 +108,246 @@ fn resolve_into(
 
 fn resolve_command_str(
     s: &str,
-    scripts: &HashMap<String, Value>,
+    scripts: &IndexMap<String, Value>,
+    platform: &str,
     stack: &mut Vec<String>,
-    out: &mut Vec<String>,
+    out: &mut Vec<ResolvedLine>,
 ) -> Result<()> {
-    match s.trim_start().strip_prefix('@') {
+    // A script block is never a reference, even if its first line starts with `@`.
+    let reference = match is_script_block(s) {
+        true => None,
+        false => s.trim_start().strip_prefix('@'),
+    };
+    match reference {
         Some(reference) => {
             let name = reference.trim();
-            if stack.iter().any(|n| n == name) {
-                stack.push(name.to_string());
-                return Err(anyhow!(
-                    "Cycle detected in task references: {}",
-                    stack.join(" -> ")
-                ));
-            }
+            check_cycle(stack, name)?;
             let entry = scripts
                 .get(name)
                 .ok_or_else(|| anyhow!("Referenced task '{}' not found", name))?;
-            let referenced = script_command(entry).ok_or_else(|| {
-                anyhow!(
-                    "Referenced task '{}' has an invalid script definition",
-                    name
-                )
-            })?;
+            let referenced = task_command(entry, platform)
+                .ok_or_else(|| missing_command("Referenced task", name, entry, platform))?;
 
             stack.push(name.to_string());
-            resolve_into(referenced, scripts, stack, out)?;
+            resolve_into(&referenced, scripts, platform, stack, out)?;
             stack.pop();
             Ok(())
         }
         None => {
-            out.push(s.to_string());
+            out.push(ResolvedLine {
+                command: s.to_string(),
+                via: stack.clone(),
+            });
             Ok(())
         }
     }
 }
 
-/// Builds the shell command used to run a single script line, optionally in a
-/// specific working directory.
-fn shell_command(cmd: &str, cwd: Option<&Path>) -> Command {
-    let mut command = if cfg!(target_os = "windows") {
-        let mut c = Command::new("cmd");
-        c.args(["/C", cmd]);
-        c
+/// The command a task runs on `platform`: its `script` block when it has one
+/// (a single multi-line command), otherwise its `cmd`.
+pub(crate) fn task_command<'a>(entry: &'a Value, platform: &str) -> Option<Cow<'a, Value>> {
+    match script_block(entry) {
+        Some(body) => Some(Cow::Owned(Value::String(body))),
+        None => script_command_for(entry, platform).map(Cow::Borrowed),
+    }
+}
+
+/// The error for a task whose command can't be found: either it only has
+/// variants for other platforms, or its definition is malformed (`kind` names
+/// the task in that message: "Command", "Referenced task").
+pub(crate) fn missing_command(
+    kind: &str,
+    name: &str,
+    entry: &Value,
+    platform: &str,
+) -> anyhow::Error {
+    let platforms = script_platforms(entry);
+    if platforms.is_empty() {
+        anyhow!("{} '{}' has an invalid script definition", kind, name)
+    } else {
+        anyhow!(
+            "Task '{}' has no command for {} (available: {})",
+            name,
+            platform,
+            platforms.join(", ")
+        )
+    }
+}
+
+/// Errors when `name` is already on the `stack` of tasks being expanded, i.e.
+/// visiting it again would loop forever. The message spells out the whole chain
+/// so the offending references are easy to find.
+pub(crate) fn check_cycle(stack: &[String], name: &str) -> Result<()> {
+    if stack.iter().any(|n| n == name) {
+        let mut chain = stack.to_vec();
+        chain.push(name.to_string());
+        return Err(anyhow!(
+            "Cycle detected in task references: {}",
+            chain.join(" -> ")
+        ));
+    }
+    Ok(())
+}
+
+/// A single shell command ready to run, with the environment of the task that
+/// declared it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Step {
+    pub command: String,
+    /// Whether the command is a script block, run from a temporary file as one
+    /// process. Decided from the task's definition rather than the command, so
+    /// a variable value with a newline in it can't change how it runs.
+    pub script: bool,
+    /// For a `parallel` task, which entry of its sequence this command belongs
+    /// to; commands sharing a group run in order, groups run concurrently.
+    pub group: usize,
+    /// Extra environment variables (from the task's `env` and `env_file`) set
+    /// for this command only.
+    pub env: Vec<(String, String)>,
+    /// The task's own working directory (its `cwd`), which takes precedence
+    /// over the one the whole run was given.
+    pub cwd: Option<PathBuf>,
+    /// Program and arguments the command is appended to (the task's `shell`);
+    /// `sh -c` (`cmd /C` on Windows) when unset.
+    pub shell: Option<Vec<String>>,
+}
+
+impl Step {
+    pub fn new(command: impl Into<String>) -> Self {
+        let command = command.into();
+        Self {
+            script: is_script_block(&command),
+            command,
+            ..Self::default()
+        }
+    }
+
+    /// The command as shown in progress and error messages: script blocks are
+    /// abbreviated to their first line, and secrets are masked.
+    pub fn summary(&self) -> String {
+        if !self.script {
+            return redact(&self.command).into_owned();
+        }
+        let first = self.command.lines().find(|l| !l.trim().is_empty());
+        redact(&format!("{} …", first.unwrap_or_default().trim())).into_owned()
+    }
+}
+
+/// Builds the shell command used to run a single step, in the step's own
+/// directory or else `cwd` when provided.
+///
+/// A script block is written to a temporary file run by the shell in one
+/// process (see [`script_file_command`]); the returned path keeps that file
+/// alive and must be held until the command has finished.
+pub(crate) fn shell_command(
+    step: &Step,
+    cwd: Option<&Path>,
+) -> Result<(Command, Option<TempPath>)> {
+    let shell = match step.shell.as_deref() {
+        Some(shell) if !shell.is_empty() => shell.to_vec(),
+        _ if cfg!(target_os = "windows") => vec!["cmd".to_string(), "/C".to_string()],
+        _ => vec!["sh".to_string(), "-c".to_string()],
+    };
+
+    let (mut command, script) = if step.script {
+        let (command, path) = script_file_command(&shell, &step.command)?;
+        (command, Some(path))
     } else {
-        let mut c = Command::new("sh");
-        c.arg("-c").arg(cmd);
-        c
+        let mut c = Command::new(&shell[0]);
+        c.args(&shell[1..]).arg(&step.command);
+        (c, None)
     };
-    if let Some(dir) = cwd {
+    command.envs(step.env.iter().map(|(k, v)| (k, v)));
+    if let Some(dir) = step.cwd.as_deref().or(cwd) {
         command.current_dir(dir);
     }
-    command
+    Ok((command, script))
+}
+
+/// Writes a script block to a temporary file and builds the command running it
+/// with `shell`. The shell's trailing "run this string" flag (`-c`, `-Command`,
+/// `/C`) is replaced with what runs a file instead, and POSIX shells get `-e`
+/// so the script stops at the first failing line.
+fn script_file_command(shell: &[String], body: &str) -> Result<(Command, TempPath)> {
+    let program = shell_program(&shell[0]);
+    let mut args: Vec<&str> = shell[1..].iter().map(String::as_str).collect();
+    if args
+        .last()
+        .is_some_and(|a| matches!(a.to_lowercase().as_str(), "-c" | "-command" | "/c"))
+    {
+        args.pop();
+    }
+
+    let extension = match program.as_str() {
+        "pwsh" | "powershell" => {
+            args.push("-File");
+            ".ps1"
+        }
+        "cmd" => {
+            args.push("/C");
+            ".cmd"
+        }
+        p if POSIX_SHELLS.contains(&p) => {
+            args.insert(0, "-e");
+            ".sh"
+        }
+        _ => "",
+    };
+
+    let mut file = tempfile::Builder::new()
+        .prefix("robin-")
+        .suffix(extension)
+        .tempfile()
+        .context("Failed to create a temporary script file")?;
+    file.write_all(body.as_bytes())
+        .and_then(|_| file.flush())
+        .context("Failed to write the temporary script file")?;
+    let path = file.into_temp_path();
+
+    let mut command = Command::new(&shell[0]);
+    command.args(args).arg(&path);
+    Ok((command, path))
+}
+
+/// How often a running command checks whether it has been cancelled.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Waits for `child` to exit. When `cancelled` returns true first, the child is
+/// killed and a "Cancelled" error is returned instead.
+pub(crate) fn wait_cancellable(
+    child: &mut Child,
+    cmd: &str,
+    cancelled: &dyn Fn() -> bool,
+) -> Result<ExitStatus> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if cancelled() {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(anyhow!("Cancelled: {}", cmd));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Runs one step to completion, stopping it early if `cancel` is raised while
+/// it runs.
+fn run_command(step: &Step, cwd: Option<&Path>, cancel: Option<&AtomicBool>) -> Result<ExitStatus> {
+    let cmd = step.summary();
+    let (mut command, _script) = shell_command(step, cwd)?;
+    match cancel {
+        None => command
+            .status()
+            .with_context(|| format!("Failed to execute script: {}", cmd)),
+        Some(cancel) => {
+            let mut child = command
+                .spawn()
+                .with_context(|| format!("Failed to execute script: {}", cmd))?;
+            wait_cancellable(&mut child, &cmd, &|| cancel.load(Ordering::SeqCst))
+        }
+    }
 }
 
 pub fn run_script(script: &serde_json::Value, notify: bool) -> Result<()> {
@@ -134,13 +357,42 @@ pub fn run_script(script: &serde_json::Value, notify: bool) -> Result<()> {
 /// Runs a script, executing each command in `cwd` when provided (otherwise in
 /// the process's current directory).
 pub fn run_script_in(script: &serde_json::Value, notify: bool, cwd: Option<&Path>) -> Result<()> {
+    let (steps, sequence) = match script {
+        serde_json::Value::String(cmd) => (vec![Step::new(cmd)], false),
+        serde_json::Value::Array(commands) => (
+            commands
+                .iter()
+                .filter_map(|cmd| cmd.as_str())
+                .map(Step::new)
+                .collect(),
+            true,
+        ),
+        _ => {
+            return Err(anyhow!(
+                "Invalid script type: must be string or array of strings"
+            ));
+        }
+    };
+    run_steps(&steps, sequence, notify, cwd, None)
+}
+
+/// Runs steps one after another, stopping at the first failure. A `sequence`
+/// echoes each command before running it and is reported as a whole in the
+/// notification; otherwise the single step is reported by name. Raising
+/// `cancel` kills the running command and skips the rest.
+pub(crate) fn run_steps(
+    steps: &[Step],
+    sequence: bool,
+    notify: bool,
+    cwd: Option<&Path>,
+    cancel: Option<&AtomicBool>,
+) -> Result<()> {
     let start_time = std::time::Instant::now();
 
-    match script {
-        serde_json::Value::String(cmd) => {
-            let status = shell_command(cmd, cwd)
-                .status()
-                .with_context(|| format!("Failed to execute script: {}", cmd))?;
+    match steps {
+        [step] if !sequence => {
+            let cmd = &step.summary();
+            let status = run_command(step, cwd, cancel)?;
 
             if notify {
                 let duration = start_time.elapsed();
@@ -168,21 +420,18 @@ pub fn run_script_in(script: &serde_json::Value, notify: bool, cwd: Option<&Path
             }
             Ok(())
         }
-        serde_json::Value::Array(commands) => {
-            for cmd in commands {
-                if let Some(cmd_str) = cmd.as_str() {
-                    // Echo each step so the user can follow a multi-command
-                    // sequence and see exactly which command is running.
-                    println!("{} {}", "▶".cyan().bold(), cmd_str);
-
-                    let status = shell_command(cmd_str, cwd)
-                        .status()
-                        .with_context(|| format!("Failed to execute script: {}", cmd_str))?;
-
-                    if !status.success() {
-                        println!("{}", format!("Script failed: {}", cmd_str).red());
-                        return Err(anyhow!("Script failed: {}", cmd_str));
-                    }
+        _ => {
+            for step in steps {
+                // Echo each step so the user can follow a multi-command
+                // sequence and see exactly which command is running.
+                let cmd = step.summary();
+                println!("{} {}", "▶".cyan().bold(), cmd);
+
+                let status = run_command(step, cwd, cancel)?;
+
+                if !status.success() {
+                    println!("{}", format!("Script failed: {}", cmd).red());
+                    return Err(anyhow!("Script failed: {}", cmd));
                 }
             }
 
@@ -199,19 +448,25 @@ pub fn run_script_in(script: &serde_json::Value, notify: bool, cwd: Option<&Path
             }
             Ok(())
         }
-        _ => Err(anyhow!(
-            "Invalid script type: must be string or array of strings"
-        )),
     }
 }
 
 pub fn list_commands(config_path: &Path) -> Result<()> {
-    let config = RobinConfig::load(config_path)
-        .with_context(|| "No .robin.json found. Run 'robin init' first")?;
-    list_scripts(&config.scripts)
+    let (config, sources) = RobinConfig::load_with_sources(config_path)?;
+    print_scripts(&config.scripts, &sources)
+}
+
+pub fn list_scripts(scripts: &IndexMap<String, Value>) -> Result<()> {
+    print_scripts(scripts, &TaskSources::default())
 }
 
-pub fn list_scripts(scripts: &HashMap<String, Value>) -> Result<()> {
+/// Prints the tasks for `--list`: the project's, then those of the global task
+/// library in a section of their own. Tasks the local overrides file replaces
+/// are marked.
+pub(crate) fn print_scripts(
+    scripts: &IndexMap<String, Value>,
+    sources: &TaskSources,
+) -> Result<()> {
     if scripts.is_empty() {
         return Err(anyhow!("No commands available"));
     }
@@ -219,36 +474,81 @@ pub fn list_scripts(scripts: &HashMap<String, Value>) -> Result<()> {
     // Find the longest command name for padding
     let max_len = scripts.keys().map(|name| name.len()).max().unwrap_or(0);
 
-    // Convert to sorted vec for alphabetical ordering
-    let mut commands: Vec<_> = scripts.iter().collect();
-    commands.sort_by(|a, b| a.0.cmp(b.0));
+    let (project, global): (Vec<_>, Vec<_>) = scripts
+        .iter()
+        .partition(|(name, _)| !sources.global.contains(*name));
+    for (name, script) in project {
+        print_script(name, script, sources, max_len);
+    }
+    if let Some((first, _)) = global.first() {
+        let label = match sources.files.get(*first) {
+            Some(file) => format!("Global tasks ({}):", file.display()),
+            None => "Global tasks:".to_string(),
+        };
+        println!("\n{}", label.bold());
+        for (name, script) in global {
+            print_script(name, script, sources, max_len);
+        }
+    }
+
+    Ok(())
+}
 
-    for (name, script) in commands {
-        if let Some(desc) = script_description(script) {
-            println!("    {:<width$}   {}", "", desc.dimmed(), width = max_len);
+/// Prints one task of [`print_scripts`]: its description, platforms and
+/// variables, then its command.
+fn print_script(name: &str, script: &Value, sources: &TaskSources, max_len: usize) {
+    if let Some(desc) = script_description(script) {
+        println!("    {:<width$}   {}", "", desc.dimmed(), width = max_len);
+    }
+    let platforms = script_platforms(script);
+    if !platforms.is_empty() {
+        let label = format!("platforms: {}", platforms.join(", "));
+        println!("    {:<width$}   {}", "", label.dimmed(), width = max_len);
+    }
+    if let Some(replaced) = sources.overridden.get(name) {
+        let file = replaced.file_name().unwrap_or_default().to_string_lossy();
+        let label = format!("local override of {}", file);
+        println!("    {:<width$}   {}", "", label.dimmed(), width = max_len);
+    }
+    for var in script_vars(script).unwrap_or_default() {
+        let label = match &var.description {
+            Some(desc) => format!("--{}={}  {}", var.name, var.hint(), desc),
+            None => format!("--{}={}", var.name, var.hint()),
+        };
+        println!("    {:<width$}   {}", "", label.dimmed(), width = max_len);
+    }
+    if let Some(body) = script_block(script) {
+        println!("==> {:<width$} # script:", name.blue(), width = max_len);
+        for line in body.lines() {
+            println!("       {}", line);
         }
-        match script_command(script) {
-            Some(serde_json::Value::String(cmd)) => {
-                println!("==> {:<width$} # {}", name.blue(), cmd, width = max_len);
-            }
-            Some(serde_json::Value::Array(commands)) => {
-                println!("==> {:<width$} # [", name.blue(), width = max_len);
-                for cmd in commands {
-                    if let Some(cmd_str) = cmd.as_str() {
-                        println!("       {}", cmd_str);
-                    }
+        return;
+    }
+    match script_command(script) {
+        Some(serde_json::Value::String(cmd)) => {
+            println!("==> {:<width$} # {}", name.blue(), cmd, width = max_len);
+        }
+        Some(serde_json::Value::Array(commands)) => {
+            println!("==> {:<width$} # [", name.blue(), width = max_len);
+            for cmd in commands {
+                if let Some(cmd_str) = cmd.as_str() {
+                    println!("       {}", cmd_str);
                 }
-                println!("     ]");
             }
-            _ => println!(
-                "==> {:<width$} # <invalid script type>",
-                name.blue(),
-                width = max_len
-            ),
+            println!("     ]");
         }
+        None if !platforms.is_empty() => println!(
+            "==> {:<width$} # <not available on {}>",
+            name.blue(),
+            current_platform(),
+            width = max_len
+        ),
+        _ => println!(
+            "==> {:<width$} # <invalid script type>",
+            name.blue(),
+            width = max_len
+        ),
     }
-
-    Ok(())
 }
 
 /// One selectable row in the interactive picker.
@@ -268,21 +568,14 @@ impl fmt::Display for CommandChoice {
     }
 }
 
-/// Builds the aligned, sorted list of picker rows from the config's scripts.
-/// Names are sorted alphabetically (HashMap order isn't stable) and padded to a
-/// common width so descriptions line up in one column.
-fn command_choices(scripts: &HashMap<String, Value>) -> Vec<CommandChoice> {
-    let mut entries: Vec<(&String, &Value)> = scripts.iter().collect();
-    entries.sort_by(|a, b| a.0.cmp(b.0));
+/// Builds the aligned list of picker rows from the config's scripts, in the
+/// order they are declared. Names are padded to a common width so
+/// descriptions line up in one column.
+fn command_choices(scripts: &IndexMap<String, Value>) -> Vec<CommandChoice> {
+    let max_len = scripts.keys().map(|name| name.len()).max().unwrap_or(0);
 
-    let max_len = entries
+    scripts
         .iter()
-        .map(|(name, _)| name.len())
-        .max()
-        .unwrap_or(0);
-
-    entries
-        .into_iter()
         .map(|(name, script)| match script_description(script) {
             Some(desc) => CommandChoice {
                 name: name.clone(),
@@ -298,13 +591,40 @@ fn command_choices(scripts: &HashMap<String, Value>) -> Vec<CommandChoice> {
         .collect()
 }
 
-pub fn interactive_mode(config_path: &Path) -> Result<()> {
-    let config = RobinConfig::load(config_path)
-        .with_context(|| "No .robin.json found. Run 'robin init' first")?;
-    interactive_scripts(&config.scripts)
+/// Shows the picker over the config's tasks, in the order the config declares
+/// them or, with `sort`, by name.
+pub fn interactive_mode(config_path: &Path, sort: bool) -> Result<()> {
+    let mut config = RobinConfig::load(config_path)?;
+    if sort {
+        config.scripts.sort_keys();
+    }
+    let options = RunOptions {
+        root: config_path.parent().map(Path::to_path_buf),
+        shell: config
+            .shell
+            .as_ref()
+            .map(shell_argv)
+            .transpose()
+            .context("Invalid top-level shell")?,
+        vars: config_vars(&config.vars).context("Invalid top-level vars")?,
+        secret_env: config.secret_env.clone(),
+        prompt: true,
+        ..RunOptions::default()
+    };
+    pick_and_run(&config.scripts, &options)
+}
+
+pub fn interactive_scripts(scripts: &IndexMap<String, Value>) -> Result<()> {
+    let options = RunOptions {
+        prompt: true,
+        ..RunOptions::default()
+    };
+    pick_and_run(scripts, &options)
 }
 
-pub fn interactive_scripts(scripts: &HashMap<String, Value>) -> Result<()> {
+/// Shows the fuzzy picker over `scripts` and runs the selected task (with its
+/// dependencies) using `options`.
+fn pick_and_run(scripts: &IndexMap<String, Value>, options: &RunOptions) -> Result<()> {
     if scripts.is_empty() {
         println!("{}", "No commands available".red());
         return Ok(());
@@ -326,14 +646,8 @@ pub fn interactive_scripts(scripts: &HashMap<String, Value>) -> Result<()> {
         .with_scorer(&scorer)
         .prompt()?;
 
-    if let Some(script) = scripts.get(&selection.name) {
-        if let Some(cmd) = script_command(script) {
-            let resolved = resolve_task_command(cmd, scripts)?;
-            run_script(&resolved, false)?;
-        }
-    }
-
-    Ok(())
+    let plan = plan_task(&selection.name, scripts, &[], options)?;
+    run_plan(&plan, options)
 }
 
 #[cfg(test)]
@@ -347,8 +661,8 @@ mod tests {
     }
 
     #[test]
-    fn choices_are_sorted_and_description_column_is_aligned() {
-        let mut scripts = HashMap::new();
+    fn choices_keep_declaration_order_and_description_column_is_aligned() {
+        let mut scripts = IndexMap::new();
         scripts.insert(
             "build".to_string(),
             json!({ "cmd": "cargo build", "desc": "Compile" }),
@@ -361,27 +675,27 @@ mod tests {
 
         let choices = command_choices(&scripts);
 
-        // Alphabetical order, independent of HashMap iteration order.
+        // The order the tasks are declared in.
         assert_eq!(
             choices.iter().map(|c| c.name.as_str()).collect::<Vec<_>>(),
-            vec!["build", "clean", "deploy"]
+            vec!["build", "deploy", "clean"]
         );
 
         // The description column starts at the same offset for every row that
         // has a description (name padded to the longest name, "deploy" = 6).
         let build = strip_ansi(&choices[0].label);
-        let deploy = strip_ansi(&choices[2].label);
+        let deploy = strip_ansi(&choices[1].label);
         assert_eq!(build.find("Compile"), deploy.find("Release it"));
         assert_eq!(build, "build    Compile");
         assert_eq!(deploy, "deploy   Release it");
 
         // A task without a description renders as just its (unpadded) name.
-        assert_eq!(choices[1].label, "clean");
+        assert_eq!(choices[2].label, "clean");
     }
 
     #[test]
     fn search_text_is_plain_name_plus_description() {
-        let mut scripts = HashMap::new();
+        let mut scripts = IndexMap::new();
         scripts.insert(
             "build".to_string(),
             json!({ "cmd": "x", "desc": "Compile the app" }),
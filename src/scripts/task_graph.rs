@@ -0,0 +1,688 @@
+use anyhow::{Context, Result, anyhow};
+use colored::*;
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use super::fingerprint::{FingerprintCache, fingerprint};
+use super::parallel::{Node, run_graph};
+use super::prompt::{missing_variables, prompt_for_variables};
+use super::script_runner::{
+    Step, check_cycle, missing_command, resolve_lines, run_steps, task_command,
+};
+use super::variables::{bind_variables, task_variables, with_values};
+use crate::config::{
+    VarSpec, current_platform, is_script_block, script_cwd, script_deps, script_env,
+    script_env_files, script_inputs, script_outputs, script_parallel, script_shell,
+};
+use crate::utils::{
+    Quoting, expand_env, read_env_file, register_secret, replace_variables_with_env,
+    send_notification, split_passthrough, uses_positional,
+};
+
+/// One step of an execution plan: a task name plus its fully-resolved commands
+/// (`@task` references expanded and variables substituted), ready to run or to
+/// preview with `--dry-run`.
+///
+/// For a `parallel` task, each entry of the task's sequence gets its own
+/// [`Step::group`], resolved on its own so an `@task` entry stays a single
+/// (internally ordered) group.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedTask {
+    pub name: String,
+    pub steps: Vec<Step>,
+    /// Whether the command was written (or expanded) as a sequence, which is
+    /// echoed step by step when run, rather than as a single command.
+    pub sequence: bool,
+    pub deps: Vec<String>,
+    pub parallel: bool,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+}
+
+impl PlannedTask {
+    /// The task's command lines in the shape they were declared: a single
+    /// string, an array for a sequence, or for a `parallel` task an array with
+    /// one element (string or array) per group.
+    pub fn script(&self) -> Value {
+        let lines = |steps: &[&Step]| -> Value {
+            Value::Array(
+                steps
+                    .iter()
+                    .map(|s| Value::String(s.command.clone()))
+                    .collect(),
+            )
+        };
+        if self.parallel {
+            return Value::Array(
+                self.groups()
+                    .into_iter()
+                    .map(|group| match group.as_slice() {
+                        [step] => Value::String(step.command.clone()),
+                        steps => lines(steps),
+                    })
+                    .collect(),
+            );
+        }
+        match self.steps.as_slice() {
+            [step] if !self.sequence => Value::String(step.command.clone()),
+            steps => lines(&steps.iter().collect::<Vec<_>>()),
+        }
+    }
+
+    /// The steps split by [`Step::group`], in order.
+    pub(crate) fn groups(&self) -> Vec<Vec<&Step>> {
+        let mut groups: Vec<Vec<&Step>> = Vec::new();
+        for step in &self.steps {
+            match groups.last_mut() {
+                Some(group) if group[0].group == step.group => group.push(step),
+                _ => groups.push(vec![step]),
+            }
+        }
+        groups
+    }
+}
+
+/// How a plan is built and executed.
+#[derive(Debug, Clone, Default)]
+pub struct RunOptions {
+    /// Send a desktop notification when the run finishes.
+    pub notify: bool,
+    /// Run commands in this directory instead of the current one.
+    pub cwd: Option<PathBuf>,
+    /// Maximum number of commands running at once (`-j/--jobs`). When set,
+    /// independent tasks of the dependency graph run concurrently; when unset,
+    /// tasks run one after another and only `parallel` tasks fan out.
+    pub jobs: Option<usize>,
+    /// Project root holding the fingerprint cache. Tasks that declare `inputs`
+    /// are skipped while up to date; without a root nothing is cached.
+    pub root: Option<PathBuf>,
+    /// Run every task even when its fingerprint says it is up to date.
+    pub force: bool,
+    /// Raised from another thread to stop the run: running commands are killed
+    /// and nothing further starts.
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Plan for this platform's `cmd` variants instead of the current one's
+    /// (`--platform`, for previewing with `--dry-run`).
+    pub platform: Option<String>,
+    /// Shell for tasks that don't pick their own (the config's top-level
+    /// `shell`); `sh -c` when unset.
+    pub shell: Option<Vec<String>>,
+    /// When stdin is a terminal, ask for required variables that weren't
+    /// passed instead of failing (turned off by `--no-input`).
+    pub prompt: bool,
+    /// Variables declared once for every task (the config's top-level `vars`).
+    /// A task's own declarations and inline defaults take precedence.
+    pub vars: Vec<VarSpec>,
+    /// Values from `--var-file`s: they win over any default, and lose to
+    /// `--name=value` on the command line.
+    pub var_values: Vec<(String, String)>,
+    /// Environment variables whose values are masked in everything robin
+    /// prints (the config's top-level `secret_env`).
+    pub secret_env: Vec<String>,
+}
+
+/// Returns the order in which `name` and everything it (transitively) depends
+/// on through `deps` must run: prerequisites first, `name` last.
+///
+/// The dependency graph is walked depth-first in declaration order, so the
+/// result is a topological order in which every task appears exactly once —
+/// a prerequisite shared by several tasks runs a single time. Cycles are
+/// reported the same way as `@task` reference cycles.
+pub fn task_order(name: &str, scripts: &IndexMap<String, Value>) -> Result<Vec<String>> {
+    let mut order = Vec::new();
+    let mut visited = HashSet::new();
+    let mut stack = Vec::new();
+    visit(name, None, scripts, &mut stack, &mut visited, &mut order)?;
+    Ok(order)
+}
+
+fn visit(
+    name: &str,
+    parent: Option<&str>,
+    scripts: &IndexMap<String, Value>,
+    stack: &mut Vec<String>,
+    visited: &mut HashSet<String>,
+    order: &mut Vec<String>,
+) -> Result<()> {
+    check_cycle(stack, name)?;
+    if visited.contains(name) {
+        return Ok(());
+    }
+
+    let entry = scripts.get(name).ok_or_else(|| match parent {
+        Some(parent) => anyhow!("Dependency '{}' of task '{}' not found", name, parent),
+        None => anyhow!("Unknown command: {}", name),
+    })?;
+
+    stack.push(name.to_string());
+    for dep in script_deps(entry) {
+        visit(dep, Some(name), scripts, stack, visited, order)?;
+    }
+    stack.pop();
+
+    visited.insert(name.to_string());
+    order.push(name.to_string());
+    Ok(())
+}
+
+/// Builds the execution plan for `name`: every task from [`task_order`] with its
+/// command resolved and its `{{variables}}` filled from `args`. All tasks share
+/// the same arguments, so `--env=staging` reaches the prerequisites as well.
+///
+/// Each task's `env_file`s and `env` map are loaded into its steps, and its
+/// `cwd` becomes their working directory (paths relative to `options.root`, or
+/// the current directory) and its `shell` runs them, else `options.shell`.
+/// Commands are picked for `options.platform`, by default the current one. A
+/// step reached through `@other` gets `other`'s environment on top and runs in
+/// `other`'s directory and shell when it declares them. An invalid `shell` is
+/// an error naming the task.
+pub fn plan_task(
+    name: &str,
+    scripts: &IndexMap<String, Value>,
+    args: &[String],
+    options: &RunOptions,
+) -> Result<Vec<PlannedTask>> {
+    let root = options.root.as_deref();
+    let platform = options.platform.as_deref().unwrap_or(current_platform());
+    let order = task_order(name, scripts)?;
+    for name in &options.secret_env {
+        if let Ok(value) = std::env::var(name) {
+            register_secret(&value);
+        }
+    }
+
+    let mut variables = task_variables(&order, scripts, platform, &options.vars)?;
+    let mut args = with_values(args, &options.var_values);
+    let prompting = options.prompt && std::io::stdin().is_terminal();
+    let missing: Vec<String> = match prompting {
+        true => missing_variables(&variables, &args)
+            .into_iter()
+            .map(|v| v.name.clone())
+            .collect(),
+        false => Vec::new(),
+    };
+    // Only the variables whose value gets checked or asked for need their
+    // choices, so a slow `choices_from` doesn't run on every invocation. It
+    // runs in the config's directory, like the rest of the config's paths and
+    // the computed defaults.
+    let (named, _) = split_passthrough(&args);
+    for var in &mut variables {
+        let flag = format!("--{}=", var.name);
+        let passed = named.iter().any(|a| a.starts_with(&flag));
+        if passed || var.from_cmd.is_some() || missing.contains(&var.name) {
+            var.load_choices(root)?;
+        }
+    }
+
+    if prompting {
+        let missing: Vec<&VarSpec> = variables
+            .iter()
+            .filter(|v| missing.contains(&v.name))
+            .collect();
+        let answers = prompt_for_variables(name, &missing)?;
+        args = with_values(&args, &answers);
+    }
+    let (args, defaults) = bind_variables(&variables, &args, root)?;
+    let (_, passthrough) = split_passthrough(&args);
+    let mut takes_positional = false;
+
+    let mut settings: HashMap<String, TaskSettings> = HashMap::new();
+    let mut settings_of = |task: &str| -> Result<TaskSettings> {
+        if let Some(found) = settings.get(task) {
+            return Ok(found.clone());
+        }
+        let found = TaskSettings::load(task, &scripts[task], root)?;
+        settings.insert(task.to_string(), found.clone());
+        Ok(found)
+    };
+
+    let mut plan = order
+        .into_iter()
+        .map(|task| {
+            let entry = &scripts[&task];
+            let cmd = task_command(entry, platform)
+                .ok_or_else(|| missing_command("Command", &task, entry, platform))?;
+            let cmd = cmd.as_ref();
+            let parallel = script_parallel(entry);
+            let groups: Vec<&Value> = match cmd {
+                Value::Array(items) if parallel => items.iter().collect(),
+                _ => vec![cmd],
+            };
+
+            let mut steps = Vec::new();
+            for (group, cmd) in groups.into_iter().enumerate() {
+                for line in resolve_lines(cmd, scripts, platform)? {
+                    // Each task the line was reached through layers its own
+                    // environment on top, and the innermost directory and
+                    // shell win.
+                    let TaskSettings {
+                        mut env,
+                        mut cwd,
+                        mut shell,
+                    } = settings_of(&task)?;
+                    for via in &line.via {
+                        let referenced = settings_of(via)?;
+                        env.extend(referenced.env);
+                        cwd = referenced.cwd.or(cwd);
+                        shell = referenced.shell.or(shell);
+                    }
+                    let shell = shell.or_else(|| options.shell.clone());
+                    for (key, value) in &env {
+                        if options.secret_env.contains(key) {
+                            register_secret(value);
+                        }
+                    }
+                    takes_positional |= uses_positional(&line.command);
+                    let script = is_script_block(&line.command);
+                    let command = replace_variables_with_env(
+                        &Value::String(line.command),
+                        &args,
+                        &defaults,
+                        &env,
+                        Quoting::for_shell(shell.as_deref()),
+                    )?;
+                    steps.push(Step {
+                        command: command.as_str().unwrap_or_default().to_string(),
+                        script,
+                        group,
+                        env,
+                        cwd,
+                        shell,
+                    });
+                }
+            }
+
+            Ok(PlannedTask {
+                steps,
+                sequence: cmd.is_array()
+                    || cmd
+                        .as_str()
+                        .is_some_and(|s| !is_script_block(s) && s.trim_start().starts_with('@')),
+                deps: owned(script_deps(entry)),
+                parallel,
+                inputs: owned(script_inputs(entry)),
+                outputs: owned(script_outputs(entry)),
+                name: task,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Arguments after `--` that no `{{1}}`/`{{@}}` asked for go to the end of
+    // the task's last command.
+    if !passthrough.is_empty() && !takes_positional {
+        if let Some(step) = plan.last_mut().and_then(|t| t.steps.last_mut()) {
+            append_arguments(step, passthrough);
+        }
+    }
+    Ok(plan)
+}
+
+fn append_arguments(step: &mut Step, args: &[String]) {
+    let quoting = Quoting::for_shell(step.shell.as_deref());
+    let quoted: Vec<String> = args.iter().map(|a| quoting.quote(a)).collect();
+    let block = step.script;
+    let mut command = step.command.trim_end_matches('\n').to_string();
+    command.push(' ');
+    command.push_str(&quoted.join(" "));
+    if block {
+        command.push('\n');
+    }
+    step.command = command;
+}
+
+/// The per-task settings a step inherits from the task that declared it.
+#[derive(Debug, Clone, Default)]
+struct TaskSettings {
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    shell: Option<Vec<String>>,
+}
+
+impl TaskSettings {
+    /// Reads a task's settings, resolving its paths against `root` (or the
+    /// current directory).
+    ///
+    /// The environment is built from the task's `env_file`s in order, then its
+    /// `env` map, later entries overriding earlier ones. `${VAR}` in an `env`
+    /// value is expanded against what has been loaded so far and then the
+    /// current process environment.
+    fn load(name: &str, entry: &Value, root: Option<&Path>) -> Result<Self> {
+        let root = root.unwrap_or(Path::new("."));
+
+        let mut env: Vec<(String, String)> = Vec::new();
+        for file in script_env_files(entry) {
+            env.extend(
+                read_env_file(&root.join(file))
+                    .with_context(|| format!("Task '{}' env_file", name))?,
+            );
+        }
+        for (key, value) in script_env(entry) {
+            let value = expand_env(&value, |var| {
+                env.iter()
+                    .rev()
+                    .find(|(k, _)| k == var)
+                    .map(|(_, v)| v.clone())
+                    .or_else(|| std::env::var(var).ok())
+            });
+            env.push((key, value));
+        }
+
+        let cwd = match script_cwd(entry) {
+            Some(dir) => {
+                let dir = root.join(dir);
+                if !dir.is_dir() {
+                    return Err(anyhow!(
+                        "Working directory of task '{}' not found: {}",
+                        name,
+                        dir.display()
+                    ));
+                }
+                Some(dir)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            env,
+            cwd,
+            shell: script_shell(entry)
+                .with_context(|| format!("Invalid shell for task '{}'", name))?,
+        })
+    }
+}
+
+fn owned(items: Vec<&str>) -> Vec<String> {
+    items.into_iter().map(str::to_string).collect()
+}
+
+/// Runs a plan, stopping at the first failing task. When nothing can run
+/// concurrently the tasks simply run in order, each prerequisite announced
+/// before it starts, and the notification (if requested) is sent only for the
+/// final, requested task. Otherwise the plan is handed to the parallel runner,
+/// which prefixes every output line with the step it came from.
+///
+/// Tasks that are up to date (see [`up_to_date_tasks`]) are skipped, and every
+/// task with `inputs` has its fingerprint recorded once it succeeds. The
+/// fingerprints are taken before anything runs, so an input edited while its
+/// task runs makes the next run rerun it.
+pub fn run_plan(plan: &[PlannedTask], options: &RunOptions) -> Result<()> {
+    let root = options
+        .root
+        .as_deref()
+        .filter(|_| plan.iter().any(|t| !t.inputs.is_empty()));
+    let mut cache = root.map(FingerprintCache::load);
+    let mut fingerprints = HashMap::new();
+    if let Some(root) = root {
+        for task in plan.iter().filter(|t| !t.inputs.is_empty()) {
+            fingerprints.insert(task.name.clone(), fingerprint(task, root)?);
+        }
+    }
+    let skipped = match (&cache, options.force) {
+        (Some(cache), false) => up_to_date_tasks(plan, cache, &fingerprints),
+        _ => HashSet::new(),
+    };
+    for task in plan.iter().filter(|t| skipped.contains(&t.name)) {
+        println!(
+            "{}",
+            format!("Skipping '{}' (up to date)", task.name).dimmed()
+        );
+    }
+
+    if options.jobs.is_none() && !plan.iter().any(|t| t.parallel) {
+        let last = plan.len().saturating_sub(1);
+        for (i, task) in plan.iter().enumerate() {
+            if skipped.contains(&task.name) {
+                continue;
+            }
+            if plan.len() > 1 {
+                println!("{}", format!("Running '{}'", task.name).dimmed());
+            }
+            run_steps(
+                &task.steps,
+                task.sequence,
+                options.notify && i == last,
+                options.cwd.as_deref(),
+                options.cancel.as_deref(),
+            )?;
+            record_fingerprint(&mut cache, &fingerprints, task);
+        }
+        return Ok(());
+    }
+
+    let start_time = std::time::Instant::now();
+    let (nodes, node_tasks) = plan_nodes(plan, options.jobs.is_some(), &skipped);
+    let limit = options.jobs.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    });
+
+    // Record a task's fingerprint as soon as its last node has succeeded.
+    let mut pending = vec![0usize; plan.len()];
+    for &t in &node_tasks {
+        pending[t] += 1;
+    }
+    let mut on_success = |node: usize| {
+        let t = node_tasks[node];
+        pending[t] -= 1;
+        if pending[t] == 0 {
+            record_fingerprint(&mut cache, &fingerprints, &plan[t]);
+        }
+    };
+    let cancel = options.cancel.clone().unwrap_or_default();
+    let result = run_graph(
+        &nodes,
+        limit,
+        options.cwd.as_deref(),
+        &cancel,
+        &mut on_success,
+    );
+
+    if options.notify {
+        let message = match &result {
+            Ok(()) => format!(
+                "Tasks completed in {:.1}s",
+                start_time.elapsed().as_secs_f32()
+            ),
+            Err(_) => "Tasks failed".to_string(),
+        };
+        send_notification("Robin", &message, result.is_ok())?;
+    }
+    result
+}
+
+/// Returns the names of the tasks in `plan` that can be skipped: those whose
+/// fingerprint is unchanged since their last successful run and none of whose
+/// dependencies will run (a prerequisite that reruns may change their inputs).
+fn up_to_date_tasks(
+    plan: &[PlannedTask],
+    cache: &FingerprintCache,
+    fingerprints: &HashMap<String, String>,
+) -> HashSet<String> {
+    let mut skipped = HashSet::new();
+    for task in plan {
+        let Some(current) = fingerprints.get(&task.name) else {
+            continue;
+        };
+        if task.deps.iter().all(|d| skipped.contains(d)) && cache.is_up_to_date(task, current) {
+            skipped.insert(task.name.clone());
+        }
+    }
+    skipped
+}
+
+/// Records the fingerprint `task` had before the run, now that it succeeded.
+/// A cache that can't be written only costs a rerun, so that's a warning.
+fn record_fingerprint(
+    cache: &mut Option<FingerprintCache>,
+    fingerprints: &HashMap<String, String>,
+    task: &PlannedTask,
+) {
+    let (Some(cache), Some(hash)) = (cache.as_mut(), fingerprints.get(&task.name)) else {
+        return;
+    };
+    if let Err(e) = cache.record(&task.name, hash) {
+        eprintln!("{} {:#}", "warning:".yellow().bold(), e);
+    }
+}
+
+/// Turns a plan into nodes for the parallel runner, along with the index of the
+/// plan task each node belongs to. A regular task becomes one node running its
+/// commands in order; a `parallel` task becomes one node per step, all sharing
+/// the task's prerequisites. Tasks in `skipped` get no nodes at all.
+///
+/// With `dag` set, a task waits only for its declared `deps`; otherwise it waits
+/// for the task before it in the plan, preserving the sequential order.
+fn plan_nodes(
+    plan: &[PlannedTask],
+    dag: bool,
+    skipped: &HashSet<String>,
+) -> (Vec<Node>, Vec<usize>) {
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut node_tasks = Vec::new();
+    // The nodes that must all finish before a task counts as done.
+    let mut exits: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut previous: Vec<usize> = Vec::new();
+
+    for (t, task) in plan.iter().enumerate() {
+        let deps: Vec<usize> = if dag {
+            task.deps
+                .iter()
+                .flat_map(|d| exits.get(d.as_str()).cloned().unwrap_or_default())
+                .collect()
+        } else {
+            previous.clone()
+        };
+
+        let steps: Vec<(String, Vec<Step>)> = if skipped.contains(&task.name) {
+            Vec::new()
+        } else if task.parallel {
+            task.groups()
+                .into_iter()
+                .enumerate()
+                .map(|(i, group)| {
+                    let commands = group.into_iter().cloned().collect();
+                    (format!("{}:{}", task.name, i + 1), commands)
+                })
+                .collect()
+        } else {
+            vec![(task.name.clone(), task.steps.clone())]
+        };
+
+        let task_exits = if steps.is_empty() {
+            deps.clone()
+        } else {
+            steps
+                .into_iter()
+                .map(|(label, commands)| {
+                    nodes.push(Node {
+                        label,
+                        commands,
+                        deps: deps.clone(),
+                    });
+                    node_tasks.push(t);
+                    nodes.len() - 1
+                })
+                .collect()
+        };
+        previous = task_exits.clone();
+        exits.insert(task.name.as_str(), task_exits);
+    }
+
+    (nodes, node_tasks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::script_runner::command_lines;
+    use serde_json::json;
+
+    fn planned(name: &str, script: Value, deps: &[&str], parallel: bool) -> PlannedTask {
+        let groups = match &script {
+            Value::Array(items) if parallel => items.clone(),
+            _ => vec![script.clone()],
+        };
+        let steps = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(group, cmd)| {
+                command_lines(cmd).into_iter().map(move |line| Step {
+                    group,
+                    ..Step::new(line)
+                })
+            })
+            .collect();
+        PlannedTask {
+            name: name.to_string(),
+            steps,
+            sequence: script.is_array(),
+            deps: deps.iter().map(|d| d.to_string()).collect(),
+            parallel,
+            inputs: vec![],
+            outputs: vec![],
+        }
+    }
+
+    #[test]
+    fn parallel_task_fans_out_into_one_node_per_step() {
+        let plan = vec![
+            planned("install", json!("npm ci"), &[], false),
+            planned(
+                "check",
+                json!(["eslint .", ["tsc", "echo typed"], "jest"]),
+                &["install"],
+                true,
+            ),
+        ];
+        let (nodes, _) = plan_nodes(&plan, true, &HashSet::new());
+
+        let labels: Vec<_> = nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, vec!["install", "check:1", "check:2", "check:3"]);
+        let commands: Vec<_> = nodes[2]
+            .commands
+            .iter()
+            .map(|s| s.command.as_str())
+            .collect();
+        assert_eq!(commands, vec!["tsc", "echo typed"]);
+        assert!(nodes[1..].iter().all(|n| n.deps == vec![0]));
+    }
+
+    #[test]
+    fn dag_mode_only_waits_for_declared_deps() {
+        let plan = vec![
+            planned("a", json!("a"), &[], false),
+            planned("b", json!("b"), &[], false),
+            planned("c", json!("c"), &["a", "b"], false),
+        ];
+
+        let (dag, _) = plan_nodes(&plan, true, &HashSet::new());
+        assert!(dag[1].deps.is_empty());
+        assert_eq!(dag[2].deps, vec![0, 1]);
+
+        // Without -j the plan stays a chain.
+        let (chain, _) = plan_nodes(&plan, false, &HashSet::new());
+        assert_eq!(chain[1].deps, vec![0]);
+        assert_eq!(chain[2].deps, vec![1]);
+    }
+
+    #[test]
+    fn skipped_tasks_pass_their_prerequisites_through() {
+        let plan = vec![
+            planned("a", json!("a"), &[], false),
+            planned("b", json!("b"), &["a"], false),
+            planned("c", json!("c"), &["b"], false),
+        ];
+        let skipped = HashSet::from(["b".to_string()]);
+        let (nodes, node_tasks) = plan_nodes(&plan, true, &skipped);
+
+        assert_eq!(node_tasks, vec![0, 2]);
+        assert_eq!(nodes[1].label, "c");
+        assert_eq!(nodes[1].deps, vec![0]);
+    }
+}
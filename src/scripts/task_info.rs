@@ -0,0 +1,256 @@
+use anyhow::{Result, anyhow};
+use colored::*;
+use indexmap::IndexMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::PathBuf;
+
+use super::script_runner::{missing_command, print_scripts, resolve_lines, task_command};
+use super::variables::task_variables;
+use crate::cli::ListFormat;
+use crate::config::{ChoicesSource, TaskSources, VarSpec, current_platform, script_description};
+use crate::utils::MASK;
+
+/// Everything `--list --format json|yaml` reports about one task.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaskInfo {
+    pub name: String,
+    pub description: Option<String>,
+    /// The command as declared: the `cmd` (string, sequence or per-platform
+    /// variants) or the `script` block.
+    pub command: Value,
+    /// The command lines it runs on this platform, with `@task` references
+    /// expanded. Empty when they can't be resolved (see `error`).
+    pub expanded: Vec<String>,
+    /// The variables it accepts: those declared in its `vars` and any other
+    /// `{{placeholder}}`, including those of referenced tasks.
+    pub variables: Vec<VarSpec>,
+    /// The file that declares it: the config, an included config or a Makefile.
+    pub source: Option<PathBuf>,
+    /// The file declaring the task this one replaces, when it comes from the
+    /// local overrides file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overrides: Option<PathBuf>,
+    /// Whether it comes from the global task library rather than the project.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub global: bool,
+    /// Why the command can't be expanded (unknown reference, cycle, no
+    /// variant for this platform) or its `vars` are malformed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Describes every task in `scripts`, in their order. `sources` tells the file
+/// each task came from, and `shared` holds the config's top-level `vars`.
+pub fn task_infos(
+    scripts: &IndexMap<String, Value>,
+    sources: &TaskSources,
+    shared: &[VarSpec],
+) -> Vec<TaskInfo> {
+    scripts
+        .iter()
+        .map(|(name, entry)| {
+            let command = match entry {
+                Value::Object(map) => map
+                    .get("script")
+                    .or_else(|| map.get("cmd"))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                other => other.clone(),
+            };
+
+            let resolved = task_command(entry, current_platform())
+                .ok_or_else(|| missing_command("Task", name, entry, current_platform()))
+                .and_then(|cmd| resolve_lines(&cmd, scripts, current_platform()));
+            let (expanded, mut error) = match resolved {
+                Ok(lines) => (lines.into_iter().map(|l| l.command).collect(), None),
+                Err(e) => (Vec::new(), Some(e.to_string())),
+            };
+            let variables = match task_variables(
+                std::slice::from_ref(name),
+                scripts,
+                current_platform(),
+                shared,
+            ) {
+                Ok(variables) => variables.into_iter().map(masked).collect(),
+                Err(e) => {
+                    error.get_or_insert_with(|| format!("{:#}", e));
+                    Vec::new()
+                }
+            };
+
+            TaskInfo {
+                name: name.clone(),
+                description: script_description(entry).map(str::to_string),
+                command,
+                expanded,
+                variables,
+                source: sources.files.get(name).cloned(),
+                overrides: sources.overridden.get(name).cloned(),
+                global: sources.global.contains(name),
+                error,
+            }
+        })
+        .collect()
+}
+
+/// Prints the tasks for `--list` in the requested format.
+pub fn print_task_list(
+    scripts: &IndexMap<String, Value>,
+    sources: &TaskSources,
+    shared: &[VarSpec],
+    format: ListFormat,
+) -> Result<()> {
+    match format {
+        ListFormat::Text => print_scripts(scripts, sources),
+        ListFormat::Json => {
+            let infos = task_infos(scripts, sources, shared);
+            println!("{}", serde_json::to_string_pretty(&infos)?);
+            Ok(())
+        }
+        ListFormat::Yaml => {
+            let infos = task_infos(scripts, sources, shared);
+            print!("{}", serde_norway::to_string(&infos)?);
+            Ok(())
+        }
+    }
+}
+
+/// Prints `robin <task> --help`: the task's description, a usage line and the
+/// variables it takes with their types, descriptions and defaults.
+pub fn print_task_help(
+    name: &str,
+    scripts: &IndexMap<String, Value>,
+    shared: &[VarSpec],
+) -> Result<()> {
+    let entry = scripts
+        .get(name)
+        .ok_or_else(|| anyhow!("Unknown command: {}", name))?;
+    let variables: Vec<VarSpec> =
+        task_variables(&[name.to_string()], scripts, current_platform(), shared)?
+            .into_iter()
+            .map(masked)
+            .collect();
+
+    if let Some(desc) = script_description(entry) {
+        println!("{}\n", desc);
+    }
+    let usage: Vec<String> = variables
+        .iter()
+        .map(|var| match var.required && var.default.is_none() {
+            true => format!("--{}={}", var.name, var.hint()),
+            false => format!("[--{}={}]", var.name, var.hint()),
+        })
+        .collect();
+    println!("{} robin {} {}", "Usage:".bold(), name, usage.join(" "));
+
+    if variables.is_empty() {
+        return Ok(());
+    }
+    println!("\n{}", "Variables:".bold());
+    let flags: Vec<String> = variables
+        .iter()
+        .map(|var| format!("--{}={}", var.name, var.hint()))
+        .collect();
+    let width = flags.iter().map(|f| f.len()).max().unwrap_or(0);
+    for (var, flag) in variables.iter().zip(&flags) {
+        let mut notes = Vec::new();
+        if let Some(desc) = &var.description {
+            notes.push(desc.clone());
+        }
+        if let Some(default) = &var.default {
+            notes.push(format!("[default: {}]", default));
+        } else if let Some(command) = &var.from_cmd {
+            notes.push(format!("[default: $({})]", command));
+        } else if var.required {
+            notes.push("[required]".to_string());
+        }
+        match &var.choices_from {
+            Some(ChoicesSource::Cmd(command)) => {
+                notes.push(format!("[choices from: $({})]", command))
+            }
+            Some(ChoicesSource::File(file)) => notes.push(format!("[choices from: {}]", file)),
+            None => {}
+        }
+        if let Some(pattern) = &var.pattern {
+            notes.push(format!("[pattern: {}]", pattern));
+        }
+        if var.secret {
+            notes.push("[secret]".to_string());
+        }
+        println!("  {:<width$}  {}", flag, notes.join(" "), width = width);
+    }
+    Ok(())
+}
+
+/// `var` with the default of a secret variable masked, for display.
+fn masked(mut var: VarSpec) -> VarSpec {
+    if var.secret && var.default.is_some() {
+        var.default = Some(MASK.to_string());
+    }
+    var
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::{HashMap, HashSet};
+
+    fn scripts(entries: Value) -> IndexMap<String, Value> {
+        serde_json::from_value(entries).unwrap()
+    }
+
+    #[test]
+    fn task_infos_expand_references_and_collect_their_variables() {
+        let scripts = scripts(json!({
+            "build": { "cmd": "cargo build --profile={{profile=dev}}", "desc": "Build it" },
+            "ship": ["@build", "deploy --env={{env=[staging, prod]}}"],
+        }));
+        let sources = TaskSources {
+            files: HashMap::from([("build".to_string(), PathBuf::from("/p/.robin.local.json"))]),
+            overridden: HashMap::from([("build".to_string(), PathBuf::from("/p/.robin.json"))]),
+            global: HashSet::from(["ship".to_string()]),
+        };
+        let infos = task_infos(&scripts, &sources, &[]);
+
+        assert_eq!(infos[0].name, "build");
+        assert_eq!(infos[0].description.as_deref(), Some("Build it"));
+        assert_eq!(infos[0].source, Some(PathBuf::from("/p/.robin.local.json")));
+        assert_eq!(infos[0].overrides, Some(PathBuf::from("/p/.robin.json")));
+
+        let ship = &infos[1];
+        assert_eq!(
+            ship.command,
+            json!(["@build", "deploy --env={{env=[staging, prod]}}"])
+        );
+        assert_eq!(
+            ship.expanded,
+            vec![
+                "cargo build --profile={{profile=dev}}",
+                "deploy --env={{env=[staging, prod]}}"
+            ]
+        );
+        let names: Vec<_> = ship.variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["profile", "env"]);
+        assert_eq!(ship.variables[1].choices, vec!["staging", "prod"]);
+        assert!(ship.variables[1].required);
+        assert_eq!(ship.source, None);
+        assert_eq!(ship.overrides, None);
+        assert!(ship.global);
+        assert!(!infos[0].global);
+    }
+
+    #[test]
+    fn task_infos_report_unresolvable_references() {
+        let scripts = scripts(json!({ "broken": ["@missing", "echo {{x}}"] }));
+        let info = &task_infos(&scripts, &TaskSources::default(), &[])[0];
+
+        assert!(info.expanded.is_empty());
+        assert_eq!(
+            info.error.as_deref(),
+            Some("Referenced task 'missing' not found")
+        );
+        assert_eq!(info.variables[0].name, "x");
+    }
+}
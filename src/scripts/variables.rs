@@ -0,0 +1,352 @@
+use anyhow::{Context, Result, anyhow};
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::path::Path;
+
+use super::script_runner::{command_lines, resolve_lines, task_command};
+use super::task_graph::task_order;
+use crate::config::{VarSpec, VarType, current_platform, script_vars};
+use crate::utils::{
+    command_output, computed_default, register_secret, split_passthrough, template_variables,
+};
+
+/// Whether the task `name`, or a task it runs through `deps` or `@task`
+/// references, takes the variable `var`.
+pub fn takes_variable(name: &str, scripts: &IndexMap<String, Value>, var: &str) -> bool {
+    let tasks = task_order(name, scripts).unwrap_or_else(|_| vec![name.to_string()]);
+    task_variables(&tasks, scripts, current_platform(), &[])
+        .is_ok_and(|vars| vars.iter().any(|v| v.name == var))
+}
+
+/// The variables `tasks` take: those declared in their `vars` (and in the
+/// `vars` of tasks they reach through `@task` references), then any other
+/// `{{placeholder}}` their commands use. A placeholder's inline default or
+/// choices fill in what its declaration leaves out, and the `shared`
+/// declarations (the config's top-level `vars`) whatever is still missing.
+pub(crate) fn task_variables(
+    tasks: &[String],
+    scripts: &IndexMap<String, Value>,
+    platform: &str,
+    shared: &[VarSpec],
+) -> Result<Vec<VarSpec>> {
+    let mut declaring: Vec<&str> = Vec::new();
+    let mut lines = Vec::new();
+    for task in tasks {
+        let Some(entry) = scripts.get(task) else {
+            continue;
+        };
+        push_unique(&mut declaring, task);
+        let Some(cmd) = task_command(entry, platform) else {
+            continue;
+        };
+        match resolve_lines(&cmd, scripts, platform) {
+            Ok(resolved) => {
+                for line in resolved {
+                    for via in &line.via {
+                        if let Some((name, _)) = scripts.get_key_value(via) {
+                            push_unique(&mut declaring, name);
+                        }
+                    }
+                    lines.push(line.command);
+                }
+            }
+            // Planning reports the broken reference; still show what we can.
+            Err(_) => lines.extend(command_lines(&cmd)),
+        }
+    }
+
+    let mut variables: Vec<VarSpec> = Vec::new();
+    for task in declaring {
+        let declared =
+            script_vars(&scripts[task]).with_context(|| format!("Task '{}' vars", task))?;
+        for var in declared {
+            if !variables.iter().any(|v| v.name == var.name) {
+                variables.push(var);
+            }
+        }
+    }
+
+    for placeholder in template_variables(&lines) {
+        // A `{{name=$(command)}}` default is computed when it's needed.
+        let (default, from_cmd) = match placeholder.default {
+            Some(default) => match computed_default(&default) {
+                Some(command) => (None, Some(command.to_string())),
+                None => (Some(default), None),
+            },
+            None => (None, None),
+        };
+        match variables.iter_mut().find(|v| v.name == placeholder.name) {
+            Some(declared) => {
+                if declared.default.is_none() && declared.from_cmd.is_none() {
+                    declared.default = default;
+                    declared.from_cmd = from_cmd;
+                }
+                if declared.choices.is_empty()
+                    && declared.choices_from.is_none()
+                    && !placeholder.choices.is_empty()
+                {
+                    declared.choices = placeholder.choices;
+                }
+            }
+            None => variables.push(VarSpec {
+                kind: match placeholder.choices.is_empty() {
+                    true => VarType::String,
+                    false => VarType::Enum,
+                },
+                required: placeholder.required,
+                default,
+                from_cmd,
+                choices: placeholder.choices,
+                ..VarSpec::new(placeholder.name)
+            }),
+        }
+    }
+
+    for var in &mut variables {
+        if let Some(shared) = shared.iter().find(|s| s.name == var.name) {
+            inherit(var, shared);
+        }
+    }
+    Ok(variables)
+}
+
+/// Fills in what `var` leaves out from the shared declaration of the same name.
+fn inherit(var: &mut VarSpec, shared: &VarSpec) {
+    if var.kind == VarType::String {
+        var.kind = shared.kind;
+    }
+    var.required &= shared.required;
+    if var.description.is_none() {
+        var.description = shared.description.clone();
+    }
+    if var.default.is_none() && var.from_cmd.is_none() {
+        var.default = shared.default.clone();
+        var.from_cmd = shared.from_cmd.clone();
+    }
+    if var.choices.is_empty() && var.choices_from.is_none() {
+        var.choices = shared.choices.clone();
+        var.choices_from = shared.choices_from.clone();
+    }
+    if var.pattern.is_none() {
+        var.pattern = shared.pattern.clone();
+    }
+    var.secret |= shared.secret;
+}
+
+/// Adds the `values` (from `--var-file`s) to `args` as `--name=value`, for the
+/// names `args` doesn't already pass. A later value for a name wins over an
+/// earlier one.
+pub(crate) fn with_values(args: &[String], values: &[(String, String)]) -> Vec<String> {
+    let (named, positional) = split_passthrough(args);
+    let mut combined = named.to_vec();
+    for (name, value) in values.iter().rev() {
+        let flag = format!("--{}=", name);
+        if !combined.iter().any(|a| a.starts_with(&flag)) {
+            combined.push(format!("{}{}", flag, value));
+        }
+    }
+    if named.len() < args.len() {
+        combined.push("--".to_string());
+        combined.extend_from_slice(positional);
+    }
+    combined
+}
+
+fn push_unique<'a>(names: &mut Vec<&'a str>, name: &'a str) {
+    if !names.contains(&name) {
+        names.push(name);
+    }
+}
+
+/// The defaults written in the config for variables that weren't passed, by
+/// name.
+type Defaults = Vec<(String, String)>;
+
+/// Checks the `--name=value` arguments against `variables` before anything
+/// runs, and returns them completed with the computed defaults of the
+/// variables that weren't passed (an empty value for optional ones without a
+/// default), along with the defaults written in the config, which go into the
+/// commands unquoted. Defaults are checked like passed values, so a config
+/// can't slip in an `int` of `abc` or a choice it doesn't list. A computed
+/// default (`from_cmd` or an inline `$(...)`) runs in `root`, the config's
+/// directory, only here, when its variable wasn't passed. The value of a
+/// `secret` variable is registered for masking before it is checked, so errors
+/// don't reveal it either. The positional arguments after `--` are kept as they
+/// are.
+pub(crate) fn bind_variables(
+    variables: &[VarSpec],
+    args: &[String],
+    root: Option<&Path>,
+) -> Result<(Vec<String>, Defaults)> {
+    let (named, positional) = split_passthrough(args);
+    let mut bound = named.to_vec();
+    let mut defaults = Vec::new();
+    for var in variables {
+        let flag = format!("--{}=", var.name);
+        match named.iter().find_map(|a| a.strip_prefix(&flag)) {
+            Some(value) => {
+                mask_if_secret(var, value);
+                var.check(value)?
+            }
+            None => match (&var.default, &var.from_cmd) {
+                (Some(default), _) => {
+                    mask_if_secret(var, default);
+                    var.check(default)
+                        .with_context(|| format!("Invalid default of {}", var.name))?;
+                    defaults.push((var.name.clone(), default.clone()))
+                }
+                (None, Some(command)) => {
+                    let value = command_output(command, root).with_context(|| {
+                        format!("Could not compute the default of {}", var.name)
+                    })?;
+                    mask_if_secret(var, &value);
+                    var.check(&value)?;
+                    bound.push(format!("{}{}", flag, value));
+                }
+                (None, None) if var.required => {
+                    return Err(anyhow!("Missing required variable: {}", var.name));
+                }
+                (None, None) => bound.push(flag),
+            },
+        }
+    }
+    if named.len() < args.len() {
+        bound.push("--".to_string());
+        bound.extend_from_slice(positional);
+    }
+    Ok((bound, defaults))
+}
+
+fn mask_if_secret(var: &VarSpec, value: &str) {
+    if var.secret {
+        register_secret(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn scripts() -> IndexMap<String, Value> {
+        serde_json::from_value(json!({
+            "build": "cargo build --target={{target}}",
+            "release": {
+                "cmd": ["@build", "git tag {{version}} && deploy {{env=[staging, prod]}} {{notes}}"],
+                "vars": {
+                    "version": { "type": "semver", "desc": "Release version" },
+                    "notes": { "required": false }
+                }
+            },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn takes_variable_follows_references() {
+        assert!(takes_variable("release", &scripts(), "target"));
+        assert!(takes_variable("release", &scripts(), "version"));
+        assert!(!takes_variable("build", &scripts(), "platform"));
+        assert!(!takes_variable("missing", &scripts(), "target"));
+    }
+
+    #[test]
+    fn task_variables_merge_declarations_with_placeholders() {
+        let vars = task_variables(&["release".to_string()], &scripts(), "linux", &[]).unwrap();
+
+        let names: Vec<_> = vars.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["version", "notes", "target", "env"]);
+        assert_eq!(vars[0].kind, VarType::Semver);
+        assert_eq!(vars[0].description.as_deref(), Some("Release version"));
+        assert_eq!(vars[3].kind, VarType::Enum);
+        assert_eq!(vars[3].choices, vec!["staging", "prod"]);
+    }
+
+    #[test]
+    fn bind_variables_validates_and_fills_in_defaults() {
+        let vars = task_variables(&["release".to_string()], &scripts(), "linux", &[]).unwrap();
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+        let (bound, _) = bind_variables(
+            &vars,
+            &args(&["--version=1.2.3", "--target=x86", "--env=prod"]),
+            None,
+        )
+        .unwrap();
+        assert!(bound.contains(&"--notes=".to_string()));
+
+        let err = bind_variables(
+            &vars,
+            &args(&["--version=1.2", "--target=x86", "--env=prod"]),
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Value '1.2' for version must be a semantic version (e.g. 1.2.3)"
+        );
+
+        let err =
+            bind_variables(&vars, &args(&["--version=1.2.3", "--env=prod"]), None).unwrap_err();
+        assert_eq!(err.to_string(), "Missing required variable: target");
+    }
+
+    #[test]
+    fn defaults_from_the_config_are_checked_too() {
+        let scripts: IndexMap<String, Value> = serde_json::from_value(json!({
+            "scale": {
+                "cmd": "scale {{replicas}} {{env}}",
+                "vars": {
+                    "replicas": { "type": "int", "default": "abc" },
+                    "env": { "type": "enum", "choices": ["staging", "prod"], "default": "dev" }
+                }
+            },
+        }))
+        .unwrap();
+        let vars = task_variables(&["scale".to_string()], &scripts, "linux", &[]).unwrap();
+
+        let err = bind_variables(&vars, &["--env=prod".to_string()], None).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Invalid default of replicas: Value 'abc' for replicas must be an integer"
+        );
+        let err = bind_variables(&vars, &["--replicas=2".to_string()], None).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            "Invalid default of env: Value 'dev' for env must be one of: staging, prod"
+        );
+        // A passed value replaces the default, which then goes unchecked.
+        let passed = ["--replicas=2".to_string(), "--env=prod".to_string()];
+        assert!(bind_variables(&vars, &passed, None).is_ok());
+    }
+
+    #[test]
+    fn computed_defaults_only_run_when_the_variable_is_not_passed() {
+        let scripts: IndexMap<String, Value> = serde_json::from_value(json!({
+            "tag": {
+                "cmd": "git tag {{version}}-{{branch=$(echo main)}}",
+                "vars": { "version": { "type": "int", "from_cmd": "echo 7" } }
+            },
+        }))
+        .unwrap();
+        let vars = task_variables(&["tag".to_string()], &scripts, "linux", &[]).unwrap();
+        assert_eq!(vars[1].from_cmd.as_deref(), Some("echo main"));
+        assert!(!vars[1].required);
+
+        let (bound, _) = bind_variables(&vars, &["--branch=dev".to_string()], None).unwrap();
+        assert_eq!(bound, vec!["--branch=dev", "--version=7"]);
+
+        let failing = VarSpec {
+            from_cmd: Some("exit 3".to_string()),
+            ..VarSpec::new("sha")
+        };
+        let passed = bind_variables(
+            std::slice::from_ref(&failing),
+            &["--sha=abc".to_string()],
+            None,
+        );
+        assert!(passed.is_ok(), "passing a value skips the command");
+        let err = bind_variables(&[failing], &[], None).unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Could not compute the default of sha"));
+    }
+}
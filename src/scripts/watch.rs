@@ -0,0 +1,276 @@
+use anyhow::{Context, Result, anyhow};
+use colored::*;
+use globset::GlobSet;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use indexmap::IndexMap;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::globs::glob_set;
+use super::task_graph::{PlannedTask, RunOptions, plan_task, run_plan};
+use crate::utils::redact;
+
+/// How long the file system must stay quiet before a burst of changes (an
+/// editor's save, a `git checkout`) triggers a single rerun.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Directories whose churn never triggers a rerun, on top of `.gitignore`.
+const ALWAYS_IGNORED: &[&str] = &[".git", ".robin"];
+
+/// Decides which changed paths should trigger a rerun: those under `root` that
+/// match one of the watched globs and aren't excluded by `.gitignore`.
+pub(crate) struct ChangeFilter {
+    root: PathBuf,
+    globs: GlobSet,
+    gitignore: Gitignore,
+}
+
+impl ChangeFilter {
+    pub fn new(root: &Path, patterns: &[String]) -> Result<Self> {
+        let mut gitignore = GitignoreBuilder::new(root);
+        // A missing or unreadable .gitignore just means nothing extra is ignored.
+        let _ = gitignore.add(root.join(".gitignore"));
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            globs: glob_set(patterns)?,
+            gitignore: gitignore.build()?,
+        })
+    }
+
+    pub fn is_relevant(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative
+            .components()
+            .any(|c| ALWAYS_IGNORED.iter().any(|dir| c.as_os_str() == *dir))
+        {
+            return false;
+        }
+        if self
+            .gitignore
+            .matched_path_or_any_parents(relative, path.is_dir())
+            .is_ignore()
+        {
+            return false;
+        }
+        self.globs.is_match(relative)
+    }
+}
+
+/// Runs `name` and reruns it whenever a watched file under `root` changes.
+///
+/// The files watched are those matched by the `inputs` of every task in the
+/// plan plus any extra `patterns` (globs relative to `root`). Bursts of
+/// changes are debounced into one rerun, and a run still in progress when a
+/// change arrives is cancelled (its commands killed) before starting over.
+/// Runs forever; failures are reported and the watch carries on.
+pub fn watch_task(
+    name: &str,
+    scripts: &IndexMap<String, Value>,
+    args: &[String],
+    patterns: &[String],
+    root: &Path,
+    options: &RunOptions,
+) -> Result<()> {
+    let plan = plan_task(name, scripts, args, options)?;
+    let mut globs: Vec<String> = patterns.to_vec();
+    globs.extend(plan.iter().flat_map(|t| t.inputs.iter().cloned()));
+    if globs.is_empty() {
+        return Err(anyhow!(
+            "Task '{}' declares no inputs to watch; pass --watch <GLOB>",
+            name
+        ));
+    }
+
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let filter = ChangeFilter::new(&root, &globs)?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher
+        .watch(&root, RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+    println!(
+        "{}",
+        format!(
+            "Watching {} for changes ({})",
+            root.display(),
+            globs.join(", ")
+        )
+        .dimmed()
+    );
+
+    loop {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let run = spawn_run(&plan, options, &cancel);
+
+        let changed = wait_for_change(&rx, &filter)?;
+        if !run.is_finished() {
+            cancel.store(true, Ordering::SeqCst);
+        }
+        let _ = run.join();
+        settle(&rx, &filter)?;
+
+        println!(
+            "\n{}",
+            format!(
+                "{} changed, rerunning '{}'",
+                changed.strip_prefix(&root).unwrap_or(&changed).display(),
+                name
+            )
+            .yellow()
+        );
+    }
+}
+
+/// Runs the plan on its own thread. The thread's result is the run's error,
+/// unless the run was stopped because `cancel` was raised: that run is about to
+/// restart and its error isn't worth reporting.
+fn spawn_run(
+    plan: &[PlannedTask],
+    options: &RunOptions,
+    cancel: &Arc<AtomicBool>,
+) -> thread::JoinHandle<Option<anyhow::Error>> {
+    let plan = plan.to_vec();
+    let cancel = Arc::clone(cancel);
+    let options = RunOptions {
+        cancel: Some(Arc::clone(&cancel)),
+        // A change is exactly what makes the task worth rerunning.
+        force: true,
+        ..options.clone()
+    };
+    thread::spawn(move || match run_plan(&plan, &options) {
+        Ok(()) => {
+            println!("{}", "Done. Waiting for changes...".dimmed());
+            None
+        }
+        Err(_) if cancel.load(Ordering::SeqCst) => None,
+        Err(e) => {
+            println!("{} {}", "Error:".red(), redact(&e.to_string()));
+            println!("{}", "Waiting for changes...".dimmed());
+            Some(e)
+        }
+    })
+}
+
+/// Blocks until a relevant path changes and returns it.
+fn wait_for_change(rx: &Receiver<notify::Result<Event>>, filter: &ChangeFilter) -> Result<PathBuf> {
+    loop {
+        let event = rx.recv().context("File watcher stopped unexpectedly")?;
+        if let Some(path) = relevant_path(event, filter) {
+            return Ok(path);
+        }
+    }
+}
+
+/// Waits until no relevant path has changed for [`DEBOUNCE`], so a burst of
+/// changes makes one rerun. Other events (a build writing to `target/`) are
+/// drained without restarting the wait.
+fn settle(rx: &Receiver<notify::Result<Event>>, filter: &ChangeFilter) -> Result<()> {
+    let mut deadline = Instant::now() + DEBOUNCE;
+    loop {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(event) => {
+                if relevant_path(event, filter).is_some() {
+                    deadline = Instant::now() + DEBOUNCE;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => return Ok(()),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("File watcher stopped unexpectedly"));
+            }
+        }
+    }
+}
+
+fn relevant_path(event: notify::Result<Event>, filter: &ChangeFilter) -> Option<PathBuf> {
+    let event = event.ok()?;
+    if matches!(event.kind, EventKind::Access(_)) {
+        return None;
+    }
+    event.paths.into_iter().find(|p| filter.is_relevant(p))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scripts::script_runner::Step;
+    use std::fs;
+
+    #[test]
+    fn change_filter_matches_globs_relative_to_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = ChangeFilter::new(dir.path(), &["src/**/*.rs".to_string()]).unwrap();
+
+        assert!(filter.is_relevant(&dir.path().join("src/main.rs")));
+        assert!(filter.is_relevant(&dir.path().join("src/a/b.rs")));
+        assert!(!filter.is_relevant(&dir.path().join("README.md")));
+        assert!(!filter.is_relevant(Path::new("/elsewhere/src/main.rs")));
+    }
+
+    #[test]
+    fn change_filter_respects_gitignore_and_internal_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n*.log\n").unwrap();
+        let filter = ChangeFilter::new(dir.path(), &["**/*".to_string()]).unwrap();
+
+        assert!(filter.is_relevant(&dir.path().join("src/lib.rs")));
+        assert!(!filter.is_relevant(&dir.path().join("target/debug/out.rs")));
+        assert!(!filter.is_relevant(&dir.path().join("build.log")));
+        assert!(!filter.is_relevant(&dir.path().join(".git/index")));
+        assert!(!filter.is_relevant(&dir.path().join(".robin/fingerprints.json")));
+    }
+
+    #[test]
+    fn a_failing_parallel_step_is_reported_not_taken_for_a_restart() {
+        let task = PlannedTask {
+            name: "check".to_string(),
+            steps: vec![Step::new("false"), Step::new("sleep 5")],
+            sequence: false,
+            deps: vec![],
+            parallel: true,
+            inputs: vec![],
+            outputs: vec![],
+        };
+        let cancel = Arc::new(AtomicBool::new(false));
+        let failure = spawn_run(&[task], &RunOptions::default(), &cancel)
+            .join()
+            .unwrap();
+        assert!(failure.is_some());
+        assert!(!cancel.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn settle_ignores_irrelevant_churn() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = ChangeFilter::new(dir.path(), &["src/**".to_string()]).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let churn = dir.path().join("target/debug/out.o");
+        let writer = thread::spawn(move || {
+            // A build that keeps writing output for well past the debounce.
+            for _ in 0..20 {
+                let event = Event::new(EventKind::Modify(notify::event::ModifyKind::Any))
+                    .add_path(churn.clone());
+                if tx.send(Ok(event)).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let start = Instant::now();
+        settle(&rx, &filter).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(600));
+        drop(rx);
+        writer.join().unwrap();
+    }
+}
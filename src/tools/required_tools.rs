@@ -98,6 +98,11 @@ pub const KNOWN_TOOLS: &[RequiredTool] = &[
 ];
 
 fn check_script_contains(script: &serde_json::Value, pattern: &str) -> bool {
+    if let Some(body) = crate::config::script_block(script) {
+        return body
+            .lines()
+            .any(|line| command_uses(line.trim_start(), pattern));
+    }
     match crate::config::script_command(script) {
         Some(serde_json::Value::String(cmd)) => command_uses(cmd, pattern),
         Some(serde_json::Value::Array(commands)) => commands
@@ -233,44 +238,36 @@ pub fn update_tools(config: &RobinConfig) -> Result<(bool, Vec<String>)> {
 
     for tool in required_tools {
         match tool.name {
-            "Node.js" => {
-                if Command::new("npm").arg("--version").output().is_ok() {
-                    println!("Updating npm packages...");
-                    if !run_update_command("npm", &["update", "-g"])? {
-                        all_success = false;
-                    } else {
-                        updated_tools.push("npm packages".to_string());
-                    }
+            "Node.js" if Command::new("npm").arg("--version").output().is_ok() => {
+                println!("Updating npm packages...");
+                if !run_update_command("npm", &["update", "-g"])? {
+                    all_success = false;
+                } else {
+                    updated_tools.push("npm packages".to_string());
                 }
             }
-            "Ruby" | "Fastlane" => {
-                if Command::new("gem").arg("--version").output().is_ok() {
-                    println!("Updating Fastlane...");
-                    if !run_update_command("gem", &["update", "fastlane"])? {
-                        all_success = false;
-                    } else {
-                        updated_tools.push("Fastlane".to_string());
-                    }
+            "Ruby" | "Fastlane" if Command::new("gem").arg("--version").output().is_ok() => {
+                println!("Updating Fastlane...");
+                if !run_update_command("gem", &["update", "fastlane"])? {
+                    all_success = false;
+                } else {
+                    updated_tools.push("Fastlane".to_string());
                 }
             }
-            "Flutter" => {
-                if Command::new("flutter").arg("--version").output().is_ok() {
-                    println!("Updating Flutter...");
-                    if !run_update_command("flutter", &["upgrade"])? {
-                        all_success = false;
-                    } else {
-                        updated_tools.push("Flutter".to_string());
-                    }
+            "Flutter" if Command::new("flutter").arg("--version").output().is_ok() => {
+                println!("Updating Flutter...");
+                if !run_update_command("flutter", &["upgrade"])? {
+                    all_success = false;
+                } else {
+                    updated_tools.push("Flutter".to_string());
                 }
             }
-            "Cargo" => {
-                if Command::new("rustup").arg("--version").output().is_ok() {
-                    println!("Updating Rust toolchain...");
-                    if !run_update_command("rustup", &["update"])? {
-                        all_success = false;
-                    } else {
-                        updated_tools.push("Rust".to_string());
-                    }
+            "Cargo" if Command::new("rustup").arg("--version").output().is_ok() => {
+                println!("Updating Rust toolchain...");
+                if !run_update_command("rustup", &["update"])? {
+                    all_success = false;
+                } else {
+                    updated_tools.push("Rust".to_string());
                 }
             }
             "CocoaPods"
@@ -312,17 +309,20 @@ fn run_update_command(cmd: &str, args: &[&str]) -> Result<bool> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use indexmap::IndexMap;
     use serde_json::{Value, json};
-    use std::collections::HashMap;
 
     fn config_with(scripts: &[(&str, Value)]) -> RobinConfig {
-        let mut map = HashMap::new();
+        let mut map = IndexMap::new();
         for (name, script) in scripts {
             map.insert((*name).to_string(), script.clone());
         }
         RobinConfig {
             schema: None,
             include: vec![],
+            shell: None,
+            vars: IndexMap::new(),
+            secret_env: vec![],
             scripts: map,
         }
     }
@@ -349,6 +349,13 @@ mod tests {
         assert!(!check_script_contains(&script, "gradle "));
     }
 
+    #[test]
+    fn check_script_contains_reads_script_blocks() {
+        let script = json!({ "script": ["cd ios", "  pod install"] });
+        assert!(check_script_contains(&script, "pod "));
+        assert!(!check_script_contains(&script, "cargo "));
+    }
+
     #[test]
     fn check_script_contains_ignores_non_string_types() {
         assert!(!check_script_contains(&json!(42), "cargo "));
@@ -1,6 +1,407 @@
 use anyhow::{Result, anyhow};
-use regex::Regex;
+use regex::{Captures, Regex};
+use serde::Serialize;
 use serde_json;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Matches a `{{name}}`, `{{name=default}}` or `{{name=[a, b]}}` placeholder,
+/// followed by any filters.
+const VARIABLE_PATTERN: &str = r"\{\{(\w+)(?:=([^}]+?))?((?:\|\w+(?:\([^)}]*\))?)*)\}\}";
+
+/// Matches a positional placeholder: `{{1}}` (optionally `{{1=default}}`), or
+/// `{{@}}` / `{{args}}` for all of them, followed by any filters.
+const POSITIONAL_PATTERN: &str = r"\{\{(@|args|\d+)(?:=([^}]+?))?((?:\|\w+(?:\([^)}]*\))?)*)\}\}";
+
+/// Matches any placeholder, named or positional, followed by any filters.
+const PLACEHOLDER_PATTERN: &str = r"\{\{(@|\w+)(?:=([^}]+?))?((?:\|\w+(?:\([^)}]*\))?)*)\}\}";
+
+/// Matches one filter of a placeholder: `|upper`, `|default(main)`.
+const FILTER_PATTERN: &str = r"\|(\w+)(?:\(([^)}]*)\))?";
+
+/// A transformation of a placeholder's value, written after it:
+/// `{{name|slug|default(main)}}`.
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    /// Insert the value without quoting it for the shell.
+    Raw,
+    Upper,
+    Lower,
+    /// Lowercase, with every run of other characters than letters and digits
+    /// turned into a single `-`: `Feature/Add Login` becomes `feature-add-login`.
+    Slug,
+    /// The value to use when it is empty or not passed.
+    Default(String),
+}
+
+/// Reads the filters written after a placeholder, like `|upper|default(x)`.
+fn parse_filters(chain: &str) -> Result<Vec<Filter>> {
+    Regex::new(FILTER_PATTERN)
+        .unwrap()
+        .captures_iter(chain)
+        .map(
+            |capture| match (&capture[1], capture.get(2).map(|m| m.as_str())) {
+                ("raw", None) => Ok(Filter::Raw),
+                ("upper", None) => Ok(Filter::Upper),
+                ("lower", None) => Ok(Filter::Lower),
+                ("slug", None) => Ok(Filter::Slug),
+                ("default", Some(default)) => Ok(Filter::Default(default.to_string())),
+                _ => Err(anyhow!(
+                    "Unknown filter '{}' (expected raw, upper, lower, slug or default(...))",
+                    &capture[0]
+                )),
+            },
+        )
+        .collect()
+}
+
+/// The problems with the `{{...}}` placeholders in `text`: those robin would
+/// leave as they are because they don't parse, and those with an unknown
+/// filter. Braces that don't start with a name or `@` (`{{.State}}`,
+/// `{{ json . }}`) belong to other tools' templates and are skipped.
+pub(crate) fn placeholder_problems(text: &str) -> Vec<String> {
+    let variable = Regex::new(&format!("^{}$", VARIABLE_PATTERN)).unwrap();
+    let positional = Regex::new(&format!("^{}$", POSITIONAL_PATTERN)).unwrap();
+    Regex::new(r"\{\{[\w@][^{}]*\}\}")
+        .unwrap()
+        .find_iter(text)
+        .filter_map(|found| {
+            let placeholder = found.as_str();
+            let Some(captures) = positional
+                .captures(placeholder)
+                .or_else(|| variable.captures(placeholder))
+            else {
+                return Some(format!("Malformed placeholder '{}'", placeholder));
+            };
+            let filters = captures.get(3).map_or("", |m| m.as_str());
+            parse_filters(filters)
+                .err()
+                .map(|e| format!("{} in '{}'", e, placeholder))
+        })
+        .collect()
+}
+
+/// The value of the `|default(...)` filter in a placeholder's filter chain.
+fn default_filter(chain: &str) -> Result<Option<String>> {
+    Ok(parse_filters(chain)?.into_iter().find_map(|f| match f {
+        Filter::Default(default) => Some(default),
+        _ => None,
+    }))
+}
+
+/// Passes `value` through `filters`, and tells whether `|default(...)` filled
+/// it in.
+fn apply_filters(value: &str, filters: &[Filter]) -> (String, bool) {
+    filters.iter().fold(
+        (value.to_string(), false),
+        |(value, defaulted), filter| match filter {
+            Filter::Upper => (value.to_uppercase(), defaulted),
+            Filter::Lower => (value.to_lowercase(), defaulted),
+            Filter::Slug => (slug(&value), defaulted),
+            Filter::Default(default) if value.is_empty() => (default.clone(), true),
+            Filter::Default(_) | Filter::Raw => (value, defaulted),
+        },
+    )
+}
+
+fn slug(value: &str) -> String {
+    let mut slug = String::with_capacity(value.len());
+    for c in value.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// The command of a computed default, `$(git rev-parse --short HEAD)`.
+pub(crate) fn computed_default(default: &str) -> Option<&str> {
+    default.strip_prefix("$(")?.strip_suffix(')').map(str::trim)
+}
+
+/// Runs `command` with the default shell (`sh`, or `cmd` on Windows) in `cwd`
+/// and returns what it printed, without the trailing newline. This is how a
+/// computed variable default gets its value.
+pub(crate) fn command_output(command: &str, cwd: Option<&Path>) -> Result<String> {
+    let mut process = if cfg!(target_os = "windows") {
+        let mut c = Command::new("cmd");
+        c.arg("/C");
+        c
+    } else {
+        let mut c = Command::new("sh");
+        c.arg("-c");
+        c
+    };
+    process
+        .arg(command)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit());
+    if let Some(dir) = cwd {
+        process.current_dir(dir);
+    }
+    let output = process
+        .output()
+        .map_err(|e| anyhow!("Failed to run `{}`: {}", command, e))?;
+    if !output.status.success() {
+        return Err(anyhow!("`{}` failed ({})", command, output.status));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim_end_matches(['\n', '\r'])
+        .to_string())
+}
+
+/// Shells that quote like `sh` and understand `-e` (exit on the first failing
+/// command).
+pub(crate) const POSIX_SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "ash"];
+
+/// The name of a shell program without its directory or extension, lowercased:
+/// `pwsh` for `/usr/bin/pwsh` or `pwsh.exe`.
+pub(crate) fn shell_program(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// Splits a task's arguments at the first bare `--`: the `--name=value`
+/// variables before it, and the positional arguments after it (passed through
+/// to the command).
+pub fn split_passthrough(args: &[String]) -> (&[String], &[String]) {
+    match args.iter().position(|a| a == "--") {
+        Some(i) => (&args[..i], &args[i + 1..]),
+        None => (args, &[]),
+    }
+}
+
+/// Whether `command` takes positional arguments through `{{1}}`, `{{@}}` or
+/// `{{args}}`. Commands that don't get them appended instead.
+pub fn uses_positional(command: &str) -> bool {
+    Regex::new(POSITIONAL_PATTERN).unwrap().is_match(command)
+}
+
+/// Quotes `s` as one word for a POSIX shell, leaving it as-is when it only has
+/// characters the shell doesn't treat specially.
+pub fn shell_quote(s: &str) -> String {
+    Quoting::Posix.quote(s)
+}
+
+/// How values substituted into a command are escaped, which depends on the
+/// shell that runs it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// `sh` and other POSIX shells: single quotes, with `'\''` for a quote.
+    Posix,
+    /// PowerShell: single quotes, with `''` for a quote.
+    PowerShell,
+    /// `cmd`: double quotes, with `""` for a quote. `cmd` still expands
+    /// `%VAR%` inside them.
+    Cmd,
+    /// Inserted as-is: for interpreters robin doesn't know how to quote for,
+    /// like `python3 -c`.
+    Raw,
+}
+
+/// Whether a point in a command is outside quotes, or inside single or double
+/// quotes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum QuoteState {
+    Unquoted,
+    Single,
+    Double,
+}
+
+impl Default for Quoting {
+    /// The quoting of the shell commands run under when a task sets none:
+    /// `cmd` on Windows, `sh` elsewhere.
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            Quoting::Cmd
+        } else {
+            Quoting::Posix
+        }
+    }
+}
+
+impl Quoting {
+    /// The quoting for commands run by `shell`, a task's shell argv, or by the
+    /// default shell when `None`.
+    pub fn for_shell(shell: Option<&[String]>) -> Self {
+        let Some(program) = shell.and_then(|s| s.first()) else {
+            return Quoting::default();
+        };
+        match shell_program(program).as_str() {
+            "pwsh" | "powershell" => Quoting::PowerShell,
+            "cmd" => Quoting::Cmd,
+            p if POSIX_SHELLS.contains(&p) || p == "fish" => Quoting::Posix,
+            _ => Quoting::Raw,
+        }
+    }
+
+    /// Quotes `value` as one word, leaving it as-is when it only has characters
+    /// the shell doesn't treat specially.
+    pub fn quote(self, value: &str) -> String {
+        self.quote_in(value, QuoteState::Unquoted)
+    }
+
+    /// Escapes `value` for a place in a command that is in `state`: outside
+    /// quotes it becomes one quoted word, inside quotes its characters are
+    /// escaped so it can't close them.
+    fn quote_in(self, value: &str, state: QuoteState) -> String {
+        match (self, state) {
+            (Quoting::Raw, _) => value.to_string(),
+            (_, QuoteState::Unquoted) if self.is_plain(value) => value.to_string(),
+            (Quoting::Posix, QuoteState::Unquoted) => {
+                format!("'{}'", value.replace('\'', "'\\''"))
+            }
+            (Quoting::Posix, QuoteState::Single) => value.replace('\'', "'\\''"),
+            (Quoting::Posix, QuoteState::Double) => escape(value, '\\', &['\\', '"', '$', '`']),
+            (Quoting::PowerShell, QuoteState::Unquoted) => {
+                format!("'{}'", value.replace('\'', "''"))
+            }
+            (Quoting::PowerShell, QuoteState::Single) => value.replace('\'', "''"),
+            (Quoting::PowerShell, QuoteState::Double) => escape(value, '`', &['`', '"', '$']),
+            (Quoting::Cmd, QuoteState::Unquoted) => format!("\"{}\"", value.replace('"', "\"\"")),
+            (Quoting::Cmd, _) => value.replace('"', "\"\""),
+        }
+    }
+
+    fn is_plain(self, value: &str) -> bool {
+        !value.is_empty()
+            && value.chars().all(|c| {
+                c.is_ascii_alphanumeric()
+                    || matches!(c, '/' | '-' | '_' | '.' | ':')
+                    || match self {
+                        Quoting::Posix => matches!(c, '=' | ',' | '@' | '+'),
+                        _ => c == '\\',
+                    }
+            })
+    }
+
+    /// Whether the end of `text` is inside quotes. Escaped quotes and, for
+    /// POSIX shells and PowerShell, `#` comments are skipped.
+    fn state_after(self, text: &str) -> QuoteState {
+        let escape = match self {
+            Quoting::PowerShell => '`',
+            Quoting::Cmd => '^',
+            _ => '\\',
+        };
+        let mut state = QuoteState::Unquoted;
+        let mut previous = ' ';
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match state {
+                QuoteState::Single if c == '\'' => state = QuoteState::Unquoted,
+                QuoteState::Single => {}
+                QuoteState::Double if c == '"' => state = QuoteState::Unquoted,
+                QuoteState::Double if c == escape && self != Quoting::Cmd => {
+                    chars.next();
+                }
+                QuoteState::Double => {}
+                QuoteState::Unquoted if c == escape => {
+                    chars.next();
+                }
+                QuoteState::Unquoted if c == '"' => state = QuoteState::Double,
+                QuoteState::Unquoted if c == '\'' && self != Quoting::Cmd => {
+                    state = QuoteState::Single
+                }
+                QuoteState::Unquoted
+                    if c == '#' && previous.is_whitespace() && self != Quoting::Cmd =>
+                {
+                    chars.by_ref().take_while(|&c| c != '\n').for_each(drop);
+                }
+                QuoteState::Unquoted => {}
+            }
+            previous = c;
+        }
+        state
+    }
+}
+
+/// Every form `value` can take once substituted into a command: as-is, and
+/// quoted or escaped for each shell and quoting context.
+pub(crate) fn quoted_forms(value: &str) -> Vec<String> {
+    let mut forms = vec![value.to_string()];
+    for quoting in [Quoting::Posix, Quoting::PowerShell, Quoting::Cmd] {
+        for state in [QuoteState::Unquoted, QuoteState::Single, QuoteState::Double] {
+            let form = quoting.quote_in(value, state);
+            if !forms.contains(&form) {
+                forms.push(form);
+            }
+        }
+    }
+    forms
+}
+
+fn escape(value: &str, escape: char, special: &[char]) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(&c) {
+            escaped.push(escape);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// A `{{variable}}` placeholder declared in a command, as passed on the command
+/// line with `--name=value`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TemplateVariable {
+    pub name: String,
+    /// The value used when none is passed (`{{name=default}}` or
+    /// `{{name|default(value)}}`), or the `$(command)` that computes it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    /// The allowed values of an enum variable (`{{name=[a, b]}}`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// Whether the task fails without a value for it.
+    pub required: bool,
+}
+
+/// The variables used in `commands`, in order of first appearance. A variable
+/// used more than once is reported as declared the first time.
+pub fn template_variables<S: AsRef<str>>(commands: &[S]) -> Vec<TemplateVariable> {
+    let var_regex = Regex::new(VARIABLE_PATTERN).unwrap();
+    let mut variables: Vec<TemplateVariable> = Vec::new();
+
+    for capture in commands
+        .iter()
+        .flat_map(|c| var_regex.captures_iter(c.as_ref()))
+    {
+        let name = &capture[1];
+        // Positional placeholders are filled from the arguments after `--`.
+        if is_positional(name) {
+            continue;
+        }
+        if variables.iter().any(|v| v.name == name) {
+            continue;
+        }
+        let spec = capture.get(2).map(|m| m.as_str());
+        let (default, choices) = match spec {
+            Some(list) if list.starts_with('[') && list.ends_with(']') => (
+                None,
+                list[1..list.len() - 1]
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .collect(),
+            ),
+            Some(default) => (Some(default.to_string()), Vec::new()),
+            None => (None, Vec::new()),
+        };
+        let default = default.or_else(|| default_filter(&capture[3]).ok().flatten());
+        variables.push(TemplateVariable {
+            name: name.to_string(),
+            required: default.is_none(),
+            default,
+            choices,
+        });
+    }
+
+    variables
+}
 
 pub fn split_command_and_args(args: &[String]) -> (String, Vec<String>) {
     if args.is_empty() {
@@ -12,7 +413,7 @@ pub fn split_command_and_args(args: &[String]) -> (String, Vec<String>) {
     let mut found_args = false;
 
     for arg in args {
-        if arg.starts_with("--") {
+        if arg.starts_with('-') {
             found_args = true;
             var_args.push(arg.clone());
         } else if !found_args {
@@ -25,17 +426,35 @@ pub fn split_command_and_args(args: &[String]) -> (String, Vec<String>) {
     (command_parts.join(" "), var_args)
 }
 
+/// Fills a command's placeholders, quoting values for the default shell (see
+/// [`replace_variables_with_env`]).
 pub fn replace_variables(script: &serde_json::Value, args: &[String]) -> Result<serde_json::Value> {
+    replace_variables_with_env(script, args, &[], &[], Quoting::default())
+}
+
+/// [`replace_variables`] for a task with its own environment and shell:
+/// `${VAR:-default}` looks `VAR` up in `env` before the process environment, so
+/// the defaults see the same values the task's commands will, and values are
+/// quoted with `quoting`. `defaults` are the config's values for variables that
+/// weren't passed; like a `{{name=default}}`, they are inserted unquoted.
+pub fn replace_variables_with_env(
+    script: &serde_json::Value,
+    args: &[String],
+    defaults: &[(String, String)],
+    env: &[(String, String)],
+    quoting: Quoting,
+) -> Result<serde_json::Value> {
     match script {
         serde_json::Value::String(cmd) => {
-            let replaced = replace_variables_in_string(cmd, args)?;
+            let replaced = replace_variables_in_string(cmd, args, defaults, env, quoting)?;
             Ok(serde_json::Value::String(replaced))
         }
         serde_json::Value::Array(commands) => {
             let mut replaced_commands = Vec::new();
             for cmd in commands {
                 if let Some(cmd_str) = cmd.as_str() {
-                    let replaced = replace_variables_in_string(cmd_str, args)?;
+                    let replaced =
+                        replace_variables_in_string(cmd_str, args, defaults, env, quoting)?;
                     replaced_commands.push(serde_json::Value::String(replaced));
                 } else {
                     replaced_commands.push(cmd.clone());
@@ -55,7 +474,7 @@ pub fn replace_variables(script: &serde_json::Value, args: &[String]) -> Result<
 ///
 /// Bare `${VAR}` (no operator) is intentionally left untouched so it is still
 /// expanded by the shell at run time.
-fn replace_env_variables_in_string(script: &str) -> String {
+fn replace_env_variables_in_string(script: &str, env: &[(String, String)]) -> String {
     let env_regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(:?-)([^}]*)\}").unwrap();
     let mut result = script.to_string();
 
@@ -65,7 +484,12 @@ fn replace_env_variables_in_string(script: &str) -> String {
         let operator = &capture[2];
         let default = &capture[3];
 
-        let env_value = std::env::var(var_name).ok();
+        let env_value = env
+            .iter()
+            .rev()
+            .find(|(key, _)| key == var_name)
+            .map(|(_, value)| value.clone())
+            .or_else(|| std::env::var(var_name).ok());
         let value = match operator {
             ":-" => match env_value {
                 Some(v) if !v.is_empty() => v,
@@ -81,54 +505,169 @@ fn replace_env_variables_in_string(script: &str) -> String {
     result
 }
 
-fn replace_variables_in_string(script: &str, args: &[String]) -> Result<String> {
-    let script = replace_env_variables_in_string(script);
-    let var_regex = Regex::new(r"\{\{(\w+)(?:=([^}]+|\[[^\]]+\]))?\}\}").unwrap();
-    let mut result = script.clone();
+fn replace_variables_in_string(
+    script: &str,
+    args: &[String],
+    defaults: &[(String, String)],
+    env: &[(String, String)],
+    quoting: Quoting,
+) -> Result<String> {
+    let (args, positional) = split_passthrough(args);
+    let script = replace_env_variables_in_string(script, env);
+    // Named and positional placeholders are filled in one pass over the
+    // command, so a value that contains `{{name}}` is never expanded itself.
+    let placeholder_regex = Regex::new(PLACEHOLDER_PATTERN).unwrap();
+    substitute(
+        &script,
+        &placeholder_regex,
+        quoting,
+        |capture| match is_positional(&capture[1]) {
+            true => positional_value(capture, positional),
+            false => named_value(capture, args, defaults),
+        },
+    )
+}
 
-    for capture in var_regex.captures_iter(&script) {
-        let full_match = &capture[0];
-        let var_name = &capture[1];
-        let default_or_enum = capture.get(2).map(|m| m.as_str()).unwrap_or("");
-        let var_pattern = format!("--{}=", var_name);
-
-        if default_or_enum.starts_with('[') && default_or_enum.ends_with(']') {
-            let allowed_values: Vec<&str> = default_or_enum[1..default_or_enum.len() - 1]
-                .split(',')
-                .map(|s| s.trim())
-                .collect();
-
-            let value = args
-                .iter()
-                .find(|arg| arg.starts_with(&var_pattern))
-                .map(|arg| arg.trim_start_matches(&var_pattern))
-                .ok_or_else(|| anyhow!("Missing required variable: {}", var_name))?;
-
-            if !allowed_values.contains(&value) {
-                return Err(anyhow!(
-                    "Value '{}' for {} must be one of: {}",
-                    value,
-                    var_name,
-                    allowed_values.join(", ")
-                ));
-            }
+/// Whether a placeholder's name refers to the arguments after `--`: `{{1}}`,
+/// `{{2}}`, ..., `{{@}}` or `{{args}}`.
+fn is_positional(name: &str) -> bool {
+    name == "@" || name == "args" || name.chars().all(|c| c.is_ascii_digit())
+}
 
-            result = result.replace(full_match, value);
-        } else {
-            let value = args
-                .iter()
-                .find(|arg| arg.starts_with(&var_pattern))
-                .map(|arg| arg.trim_start_matches(&var_pattern))
-                .or(if default_or_enum.is_empty() {
-                    None
-                } else {
-                    Some(default_or_enum)
-                })
-                .ok_or_else(|| anyhow!("Missing required variable: {}", var_name))?;
+/// The value of a `{{name}}` placeholder: the `--name=value` argument, or the
+/// default from the config or written in the placeholder.
+fn named_value(capture: &Captures, args: &[String], defaults: &[(String, String)]) -> Result<Fill> {
+    let var_name = &capture[1];
+    let default_or_enum = capture.get(2).map(|m| m.as_str()).unwrap_or("");
+    let var_pattern = format!("--{}=", var_name);
+    let passed = args
+        .iter()
+        .find(|arg| arg.starts_with(&var_pattern))
+        .map(|arg| arg.trim_start_matches(&var_pattern));
+    let configured = defaults
+        .iter()
+        .find(|(name, _)| name == var_name)
+        .map(|(_, value)| value.as_str());
 
-            result = result.replace(full_match, value);
+    if default_or_enum.starts_with('[') && default_or_enum.ends_with(']') {
+        let allowed_values: Vec<&str> = default_or_enum[1..default_or_enum.len() - 1]
+            .split(',')
+            .map(|s| s.trim())
+            .collect();
+        let value = passed
+            .or(configured)
+            .ok_or_else(|| anyhow!("Missing required variable: {}", var_name))?;
+        if !allowed_values.contains(&value) {
+            return Err(anyhow!(
+                "Value '{}' for {} must be one of: {}",
+                value,
+                var_name,
+                allowed_values.join(", ")
+            ));
         }
+        return Ok(match passed {
+            Some(value) => Fill::Values(vec![value.to_string()]),
+            None => Fill::Literal(value.to_string()),
+        });
+    }
+    match (passed, configured) {
+        (Some(value), _) => Ok(Fill::Values(vec![value.to_string()])),
+        (None, Some(default)) => Ok(Fill::Literal(default.to_string())),
+        // A planned task has computed this already, in the config's directory;
+        // a command substituted on its own has no config and uses the current one.
+        (None, None) if !default_or_enum.is_empty() => match computed_default(default_or_enum) {
+            Some(command) => command_output(command, None)
+                .map(|value| Fill::Values(vec![value]))
+                .map_err(|e| anyhow!("Could not compute the default of {}: {}", var_name, e)),
+            None => Ok(Fill::Literal(default_or_enum.to_string())),
+        },
+        // `|default(...)` fills it in.
+        (None, None) if default_filter(&capture[3])?.is_some() => Ok(Fill::Values(Vec::new())),
+        (None, None) => Err(anyhow!("Missing required variable: {}", var_name)),
     }
+}
 
+/// The value of a positional placeholder: `{{1}}`, `{{2}}`, ... take one of
+/// the arguments after `--`, and `{{@}}` / `{{args}}` take all of them, each
+/// quoted as one word.
+fn positional_value(capture: &Captures, positional: &[String]) -> Result<Fill> {
+    let default = capture.get(2).map(|m| m.as_str());
+    match &capture[1] {
+        "@" | "args" => Ok(Fill::Values(positional.to_vec())),
+        index => {
+            let value = index
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| i.checked_sub(1))
+                .and_then(|i| positional.get(i));
+            match (value, default) {
+                (Some(value), _) => Ok(Fill::Values(vec![value.clone()])),
+                (None, Some(default)) => Ok(Fill::Literal(default.to_string())),
+                (None, None) if default_filter(&capture[3])?.is_some() => {
+                    Ok(Fill::Values(Vec::new()))
+                }
+                (None, None) => Err(anyhow!(
+                    "Missing positional argument {} (pass it after --)",
+                    index
+                )),
+            }
+        }
+    }
+}
+
+/// What a placeholder is replaced with.
+enum Fill {
+    /// Values passed in or computed, quoted so each stays one word.
+    Values(Vec<String>),
+    /// A default written in the config, inserted as it is like the rest of
+    /// the command.
+    Literal(String),
+}
+
+/// Replaces each match of `regex` in `text` with what `value_of` returns for
+/// it, passed through the placeholder's filters and separated by spaces.
+/// Values are quoted for `quoting` according to the quotes the placeholder
+/// sits in, unless it has the `|raw` filter; defaults written in the config,
+/// including `|default(...)`, are inserted unquoted. An empty value outside
+/// quotes inserts nothing, so an optional variable left unset drops out of the
+/// command.
+fn substitute(
+    text: &str,
+    regex: &Regex,
+    quoting: Quoting,
+    mut value_of: impl FnMut(&Captures) -> Result<Fill>,
+) -> Result<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut last = 0;
+    for capture in regex.captures_iter(text) {
+        let placeholder = capture.get(0).unwrap();
+        result.push_str(&text[last..placeholder.start()]);
+        last = placeholder.end();
+
+        let filters = parse_filters(&capture[3])?;
+        let raw = filters.contains(&Filter::Raw);
+        let mut values = match value_of(&capture)? {
+            Fill::Literal(value) => {
+                result.push_str(&apply_filters(&value, &filters).0);
+                continue;
+            }
+            Fill::Values(values) => values,
+        };
+        if values.is_empty() {
+            values.push(String::new());
+        }
+        let state = quoting.state_after(&text[..placeholder.start()]);
+        let words: Vec<String> = values
+            .iter()
+            .map(|v| apply_filters(v, &filters))
+            .filter(|(v, _)| raw || !v.is_empty() || state != QuoteState::Unquoted)
+            .map(|(v, defaulted)| match raw || defaulted {
+                true => v,
+                false => quoting.quote_in(&v, state),
+            })
+            .collect();
+        result.push_str(&words.join(" "));
+    }
+    result.push_str(&text[last..]);
     Ok(result)
 }
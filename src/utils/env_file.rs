@@ -1,3 +1,5 @@
+use anyhow::{Context, Result};
+use regex::Regex;
 use std::path::Path;
 
 /// Loads a `.env` file living next to the given config file into the process
@@ -18,3 +20,33 @@ pub fn load_env_file(config_path: &Path) {
         let _ = dotenvy::from_path(&env_path);
     }
 }
+
+/// Reads the `KEY=value` pairs of an env file without touching the process
+/// environment. Unlike the project `.env`, a file a task explicitly asks for
+/// must exist and parse.
+pub fn read_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let entries = dotenvy::from_path_iter(path)
+        .with_context(|| format!("Failed to read env file: {}", path.display()))?;
+    entries
+        .map(|entry| entry.with_context(|| format!("Failed to parse env file: {}", path.display())))
+        .collect()
+}
+
+/// Expands `${VAR}` references in an env value using `lookup`. The
+/// `${VAR:-default}` and `${VAR-default}` forms behave as in the shell, and an
+/// unset variable without a default expands to an empty string.
+pub fn expand_env(value: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let env_regex = Regex::new(r"\$\{([A-Za-z_][A-Za-z0-9_]*)(?:(:?-)([^}]*))?\}").unwrap();
+    env_regex
+        .replace_all(value, |capture: &regex::Captures| {
+            let current = lookup(&capture[1]);
+            match capture.get(2).map(|m| m.as_str()) {
+                Some(":-") => current
+                    .filter(|v| !v.is_empty())
+                    .unwrap_or_else(|| capture[3].to_string()),
+                Some(_) => current.unwrap_or_else(|| capture[3].to_string()),
+                None => current.unwrap_or_default(),
+            }
+        })
+        .into_owned()
+}
@@ -1,9 +1,17 @@
 mod command_utils;
 mod env_file;
 mod notifications;
+mod secrets;
 mod update_check;
 
-pub use command_utils::{replace_variables, split_command_and_args};
-pub use env_file::load_env_file;
+pub(crate) use command_utils::{
+    POSIX_SHELLS, command_output, computed_default, placeholder_problems, shell_program,
+};
+pub use command_utils::{
+    Quoting, TemplateVariable, replace_variables, replace_variables_with_env, shell_quote,
+    split_command_and_args, split_passthrough, template_variables, uses_positional,
+};
+pub use env_file::{expand_env, load_env_file, read_env_file};
 pub use notifications::send_notification;
+pub use secrets::{MASK, redact, register_secret};
 pub use update_check::check_for_update;
@@ -2,7 +2,10 @@ use anyhow::Result;
 use notify_rust::Notification;
 use std::process::Command;
 
+use super::redact;
+
 pub fn send_notification(title: &str, message: &str, success: bool) -> Result<()> {
+    let message = redact(message);
     if cfg!(target_os = "windows") {
         let icon = if success { "✅" } else { "❌" };
         let script = format!(
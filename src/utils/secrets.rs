@@ -0,0 +1,66 @@
+use std::borrow::Cow;
+use std::sync::Mutex;
+
+use super::command_utils::quoted_forms;
+
+/// What a secret is replaced with wherever robin prints it.
+pub const MASK: &str = "****";
+
+/// The values to hide, longest first so that a secret containing another is
+/// masked whole.
+static SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The shortest value worth masking: replacing every `1` or `on` in the output
+/// would hide far more than the secret.
+const MIN_SECRET_LEN: usize = 4;
+
+/// Marks `value` as secret for the rest of the process, along with the forms it
+/// takes once quoted into a command. Values shorter than [`MIN_SECRET_LEN`]
+/// characters are ignored.
+pub fn register_secret(value: &str) {
+    if value.chars().count() < MIN_SECRET_LEN {
+        return;
+    }
+    let mut secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    for form in quoted_forms(value) {
+        if !secrets.contains(&form) {
+            secrets.push(form);
+        }
+    }
+    secrets.sort_by_key(|s| std::cmp::Reverse(s.len()));
+}
+
+/// `text` with every registered secret replaced by [`MASK`].
+pub fn redact(text: &str) -> Cow<'_, str> {
+    let secrets = SECRETS.lock().unwrap_or_else(|e| e.into_inner());
+    if !secrets.iter().any(|s| text.contains(s.as_str())) {
+        return Cow::Borrowed(text);
+    }
+    let mut redacted = text.to_string();
+    for secret in secrets.iter() {
+        redacted = redacted.replace(secret.as_str(), MASK);
+    }
+    Cow::Owned(redacted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_masks_secrets_and_their_quoted_forms() {
+        register_secret("s3cr3t-t0ken");
+        register_secret("it's a secret");
+        register_secret("");
+        register_secret("1");
+
+        assert_eq!(
+            redact("curl -H 'Authorization: s3cr3t-t0ken' api"),
+            "curl -H 'Authorization: ****' api"
+        );
+        assert_eq!(redact(r"echo 'it'\''s a secret'"), "echo ****");
+        assert_eq!(redact(r#"echo "so it's a secret""#), r#"echo "so ****""#);
+        assert!(matches!(redact("nothing to hide"), Cow::Borrowed(_)));
+        assert!(matches!(redact("exit code 1"), Cow::Borrowed(_)));
+    }
+}
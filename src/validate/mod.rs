@@ -0,0 +1,399 @@
+mod schema;
+
+use indexmap::IndexMap;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::{
+    ConfigFormat, PLATFORMS, RobinConfig, config_vars, current_platform, duplicate_keys,
+    line_column, local_config_in, locate, script_platforms,
+};
+use crate::scripts::{resolve_lines, task_command, task_order, task_variables};
+use crate::utils::placeholder_problems;
+use schema::check_schema;
+
+/// How serious a [`Problem`] is: errors make `robin validate` fail, warnings
+/// only point at something that is likely a mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Something `robin validate` found in a config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// The 1-based line and column the problem is at, when known.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Problem {
+    /// `file:line:column`, or just the file when the position isn't known.
+    pub fn location(&self) -> String {
+        match self.position {
+            Some((line, column)) => format!("{}:{}:{}", self.file.display(), line, column),
+            None => self.file.display().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location(), self.message)
+    }
+}
+
+/// Checks the config at `path` and every config it includes, and returns what
+/// is wrong with them, in file order.
+///
+/// Each file must parse and match the bundled JSON Schema, a JSON file must
+/// not repeat a key, and each include must exist without including itself
+/// back. Then, across the merged tasks:
+/// `@task` references and `deps` must name existing tasks without cycles,
+/// variable declarations must be valid, and `{{...}}` placeholders must parse.
+/// A task declared by several included files is an error (the first include
+/// silently wins); one overridden by the including file is a warning. The
+/// local overrides file next to the config is checked too, and its tasks and
+/// variables replace the shared ones before the merged tasks are checked.
+pub fn validate_config(path: &Path) -> Vec<Problem> {
+    let mut walk = Walk::default();
+    walk.visit(path, None);
+    if let Some(local_path) = local_config_in(path.parent().unwrap_or_else(|| Path::new("."))) {
+        let mut local = Walk::default();
+        local.visit(&local_path, None);
+        walk.overlay(local);
+    }
+    walk.check_tasks(path);
+    walk.problems
+}
+
+#[derive(Default)]
+struct Walk {
+    problems: Vec<Problem>,
+    /// Whether a file couldn't be read, parsed or matched to the schema, so its
+    /// tasks are missing from `scripts`.
+    incomplete: bool,
+    /// The content of every file read, to locate problems in.
+    contents: HashMap<PathBuf, String>,
+    /// The tasks and shared variables of every file, merged the way
+    /// [`RobinConfig::load`] does: the first declaration wins.
+    scripts: IndexMap<String, Value>,
+    vars: IndexMap<String, Value>,
+    /// The file each task was first declared in: the one that wins.
+    declared: HashMap<String, PathBuf>,
+    /// The files being visited, the current one last.
+    stack: Vec<PathBuf>,
+}
+
+/// Where an included file was included from: the including file and the index
+/// of the entry in its `include`.
+type IncludeSite = (PathBuf, usize);
+
+impl Walk {
+    /// Checks the file at `path` on its own, then the files it includes, in the
+    /// order their tasks are merged.
+    fn visit(&mut self, path: &Path, site: Option<IncludeSite>) {
+        let identity = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        if let Some(start) = self.stack.iter().position(|p| *p == identity) {
+            let mut chain: Vec<String> = self.stack[start..]
+                .iter()
+                .map(|p| p.display().to_string())
+                .collect();
+            chain.push(path.display().to_string());
+            self.error_at_site(
+                &site,
+                path,
+                format!("Include cycle: {}", chain.join(" -> ")),
+            );
+            return;
+        }
+
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                let message = match &site {
+                    Some(_) => format!("Included config {} can't be read: {}", path.display(), e),
+                    None => format!("Failed to read config file {}: {}", path.display(), e),
+                };
+                self.error_at_site(&site, path, message);
+                self.incomplete = true;
+                return;
+            }
+        };
+        let format = ConfigFormat::of(path);
+        let document = match format.parse(&content) {
+            Ok(document) => document,
+            Err(e) => {
+                let message = format!("Invalid {}: {}", format.name(), e.message);
+                self.error(path, e.position, message);
+                self.incomplete = true;
+                return;
+            }
+        };
+        if format == ConfigFormat::Json {
+            // Reading the file keeps only the last of them.
+            for (key_path, offset) in duplicate_keys(&content) {
+                let message = format!("/{}: duplicate key, the last one wins", key_path.join("/"));
+                self.error(path, Some(line_column(&content, offset)), message);
+            }
+        }
+        self.contents.insert(path.to_path_buf(), content);
+
+        let mismatches = check_schema(&document);
+        for mismatch in &mismatches {
+            let position = self.locate(path, &mismatch.path);
+            let message = match mismatch.path.is_empty() {
+                true => mismatch.message.clone(),
+                false => format!("/{}: {}", mismatch.path.join("/"), mismatch.message),
+            };
+            self.error(path, position, message);
+        }
+        if !mismatches.is_empty() {
+            self.incomplete = true;
+            return;
+        }
+        let config: RobinConfig = match serde_json::from_value(document) {
+            Ok(config) => config,
+            Err(e) => {
+                self.error(path, None, e.to_string());
+                self.incomplete = true;
+                return;
+            }
+        };
+
+        let mut names: Vec<&String> = config.scripts.keys().collect();
+        names.sort();
+        for name in names {
+            self.declare(name, path);
+            self.scripts
+                .entry(name.clone())
+                .or_insert_with(|| config.scripts[name].clone());
+        }
+        for (name, var) in &config.vars {
+            self.vars.entry(name.clone()).or_insert_with(|| var.clone());
+        }
+
+        self.stack.push(identity);
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        for (index, include) in config.include.iter().enumerate() {
+            self.visit(&base_dir.join(include), Some((path.to_path_buf(), index)));
+        }
+        self.stack.pop();
+    }
+
+    /// Merges the walk of the local overrides file over this one, the way
+    /// [`RobinConfig::load`] does: its tasks and variables win.
+    fn overlay(&mut self, local: Walk) {
+        self.problems.extend(local.problems);
+        self.incomplete |= local.incomplete;
+        self.contents.extend(local.contents);
+        self.scripts.extend(local.scripts);
+        self.vars.extend(local.vars);
+        self.declared.extend(local.declared);
+    }
+
+    /// Records that `path` declares the task `name`, reporting it when an
+    /// earlier file already did.
+    fn declare(&mut self, name: &str, path: &Path) {
+        let position = self.locate(path, &["scripts".to_string(), name.to_string()]);
+        let Some(winner) = self.declared.get(name).cloned() else {
+            self.declared.insert(name.to_string(), path.to_path_buf());
+            return;
+        };
+        let identity = |p: &Path| p.canonicalize().unwrap_or_else(|_| p.to_path_buf());
+        if identity(&winner) == identity(path) {
+            // The same file, included twice.
+            return;
+        }
+        if self.stack.contains(&identity(&winner)) {
+            self.problems.push(Problem {
+                severity: Severity::Warning,
+                file: path.to_path_buf(),
+                position,
+                message: format!(
+                    "Task '{}' is overridden by the one in {}",
+                    name,
+                    winner.display()
+                ),
+            });
+        } else {
+            self.error(
+                path,
+                position,
+                format!(
+                    "Task '{}' is also declared in {}, which wins",
+                    name,
+                    winner.display()
+                ),
+            );
+        }
+    }
+
+    /// Checks the merged tasks: references, dependencies, variables and
+    /// placeholders. Each problem is reported once, at the first task (by
+    /// name) it shows up in. When a file couldn't be read, its tasks are
+    /// missing, so references to unknown tasks aren't reported.
+    fn check_tasks(&mut self, path: &Path) {
+        let shared = match config_vars(&self.vars) {
+            Ok(shared) => shared,
+            Err(e) => {
+                let position = self.locate(path, &["vars".to_string()]);
+                self.error(path, position, format!("{:#}", e));
+                Vec::new()
+            }
+        };
+        let scripts = std::mem::take(&mut self.scripts);
+        let scripts = &scripts;
+        let mut reported = HashSet::new();
+        let mut names: Vec<&String> = scripts.keys().collect();
+        names.sort();
+
+        for name in names {
+            let entry = &scripts[name];
+            let mut messages = Vec::new();
+            if let Err(e) = task_order(name, scripts) {
+                messages.push(e.to_string());
+            }
+            for platform in platforms_of(entry) {
+                if let Some(cmd) = task_command(entry, platform) {
+                    if let Err(e) = resolve_lines(&cmd, scripts, platform) {
+                        messages.push(e.to_string());
+                    }
+                }
+            }
+            let task = std::slice::from_ref(name);
+            if let Err(e) = task_variables(task, scripts, current_platform(), &shared) {
+                messages.push(format!("Task '{}': {:#}", name, e));
+            }
+            for text in command_texts(entry) {
+                for problem in placeholder_problems(text) {
+                    messages.push(format!("Task '{}': {}", name, problem));
+                }
+            }
+
+            if self.incomplete {
+                messages.retain(|message| !names_unknown_task(message));
+            }
+
+            let file = self.declared[name].clone();
+            for message in messages {
+                if reported.insert(report_key(&message)) {
+                    let position = self.locate(&file, &["scripts".to_string(), name.clone()]);
+                    self.error(&file, position, message);
+                }
+            }
+        }
+    }
+
+    fn error(&mut self, file: &Path, position: Option<(usize, usize)>, message: String) {
+        self.problems.push(Problem {
+            severity: Severity::Error,
+            file: file.to_path_buf(),
+            position,
+            message,
+        });
+    }
+
+    /// Reports a problem with an included file at the `include` entry naming
+    /// it, or in the file itself when it is the config being validated.
+    fn error_at_site(&mut self, site: &Option<IncludeSite>, path: &Path, message: String) {
+        match site {
+            Some((includer, index)) => {
+                let position = self.locate(includer, &["include".to_string(), index.to_string()]);
+                self.error(includer, position, message);
+            }
+            None => self.error(path, None, message),
+        }
+    }
+
+    /// Where the value at `path` is in `file`. Only JSON files are located;
+    /// problems in YAML and TOML files are reported by path alone.
+    fn locate(&self, file: &Path, path: &[String]) -> Option<(usize, usize)> {
+        if ConfigFormat::of(file) != ConfigFormat::Json {
+            return None;
+        }
+        let content = self.contents.get(file)?;
+        locate(content, path).map(|offset| line_column(content, offset))
+    }
+}
+
+/// The platforms to resolve a task's references for: the current one, and
+/// every one the task has a dedicated `cmd` variant for.
+fn platforms_of(entry: &Value) -> Vec<&'static str> {
+    let mut platforms = vec![current_platform()];
+    for platform in script_platforms(entry) {
+        if let Some(platform) = PLATFORMS.iter().find(|p| **p == platform) {
+            if !platforms.contains(platform) {
+                platforms.push(platform);
+            }
+        }
+    }
+    platforms
+}
+
+/// Every command string of a task: its `cmd` (with all platform variants) and
+/// its `script` block.
+fn command_texts(entry: &Value) -> Vec<&str> {
+    fn collect<'a>(value: &'a Value, out: &mut Vec<&'a str>) {
+        match value {
+            Value::String(s) => out.push(s),
+            Value::Array(items) => items.iter().for_each(|item| collect(item, out)),
+            Value::Object(variants) => variants.values().for_each(|v| collect(v, out)),
+            _ => {}
+        }
+    }
+    let mut texts = Vec::new();
+    match entry {
+        Value::Object(map) => {
+            for key in ["cmd", "script"] {
+                if let Some(value) = map.get(key) {
+                    collect(value, &mut texts);
+                }
+            }
+        }
+        entry => collect(entry, &mut texts),
+    }
+    texts
+}
+
+/// Whether `message` is about a reference to a task that doesn't exist.
+fn names_unknown_task(message: &str) -> bool {
+    message.ends_with("not found")
+        && (message.contains("Referenced task '") || message.contains("Dependency '"))
+}
+
+/// What makes two messages the same problem: a cycle is the same whichever of
+/// its tasks it was found from, so it is keyed by the tasks in it.
+fn report_key(message: &str) -> String {
+    let Some((_, chain)) = message.split_once("Cycle detected in task references: ") else {
+        return message.to_string();
+    };
+    let chain: Vec<&str> = chain.split(" -> ").collect();
+    let last = chain.last().copied().unwrap_or_default();
+    let start = chain.iter().position(|t| *t == last).unwrap_or(0);
+    let members: BTreeSet<&str> = chain[start..].iter().copied().collect();
+    format!("cycle: {:?}", members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cycles_are_reported_once_whichever_task_finds_them() {
+        assert_eq!(
+            report_key("Cycle detected in task references: a -> b -> a"),
+            report_key("Cycle detected in task references: c -> b -> a -> b")
+        );
+        assert_ne!(
+            report_key("Referenced task 'x' not found"),
+            report_key("Referenced task 'y' not found")
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use regex::Regex;
+use serde_json::{Map, Value};
+
+/// The schema bundled with robin: the one `$schema` points editors at.
+pub(crate) const SCHEMA: &str = include_str!("../../schema/robin.schema.json");
+
+/// A place where a document doesn't match the schema: the path of the
+/// offending value (object keys and array indices) and what is wrong with it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Mismatch {
+    pub path: Vec<String>,
+    pub message: String,
+}
+
+/// Checks `document` against the bundled schema.
+///
+/// Only the keywords the bundled schema uses are supported: `$ref` to its
+/// `definitions`, `type`, `enum`, `oneOf`, `properties`,
+/// `additionalProperties`, `required`, `minProperties`, `items`, `minItems`,
+/// `uniqueItems`, `minLength` and the `regex` format. `oneOf` is checked as
+/// "at least one", which is all the schema's alternatives need.
+pub(crate) fn check_schema(document: &Value) -> Vec<Mismatch> {
+    let schema: Value = serde_json::from_str(SCHEMA).expect("the bundled schema is valid JSON");
+    let mut mismatches = Vec::new();
+    check(document, &schema, &schema, &mut Vec::new(), &mut mismatches);
+    mismatches
+}
+
+fn check(
+    value: &Value,
+    schema: &Value,
+    root: &Value,
+    path: &mut Vec<String>,
+    out: &mut Vec<Mismatch>,
+) {
+    let schema = resolve(schema, root);
+    let Some(schema) = schema.as_object() else {
+        return;
+    };
+    let types = schema_types(schema);
+    if !types.is_empty() && !types.iter().any(|t| has_type(value, t)) {
+        push(
+            out,
+            path,
+            format!("expected {}, found {}", describe(&types), type_name(value)),
+        );
+        return;
+    }
+    if let Some(Value::Array(allowed)) = schema.get("enum") {
+        if !allowed.contains(value) {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            push(
+                out,
+                path,
+                format!("{} must be one of: {}", value, allowed.join(", ")),
+            );
+            return;
+        }
+    }
+    if let Some(Value::Array(branches)) = schema.get("oneOf") {
+        if let Some(errors) = one_of(value, branches, root, path) {
+            out.extend(errors);
+        }
+    }
+
+    match value {
+        Value::Object(map) => check_object(map, schema, root, path, out),
+        Value::Array(items) => {
+            let min = schema.get("minItems").and_then(Value::as_u64).unwrap_or(0);
+            if (items.len() as u64) < min {
+                push(out, path, format!("needs at least {} item(s)", min));
+            }
+            if schema.get("uniqueItems") == Some(&Value::Bool(true)) {
+                for (i, item) in items.iter().enumerate() {
+                    if items[..i].contains(item) {
+                        path.push(i.to_string());
+                        push(out, path, format!("duplicate item {}", item));
+                        path.pop();
+                    }
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    path.push(i.to_string());
+                    check(item, item_schema, root, path, out);
+                    path.pop();
+                }
+            }
+        }
+        Value::String(s) => {
+            let min = schema.get("minLength").and_then(Value::as_u64).unwrap_or(0);
+            if (s.chars().count() as u64) < min {
+                push(out, path, "must not be empty".to_string());
+            }
+            if schema.get("format").and_then(Value::as_str) == Some("regex") {
+                if let Err(e) = Regex::new(s) {
+                    let reason = e.to_string();
+                    let reason = reason.lines().last().unwrap_or_default().trim();
+                    push(
+                        out,
+                        path,
+                        format!("invalid regular expression ({})", reason),
+                    );
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_object(
+    map: &Map<String, Value>,
+    schema: &Map<String, Value>,
+    root: &Value,
+    path: &mut Vec<String>,
+    out: &mut Vec<Mismatch>,
+) {
+    if let Some(Value::Array(required)) = schema.get("required") {
+        for field in required.iter().filter_map(Value::as_str) {
+            if !map.contains_key(field) {
+                out.push(Mismatch {
+                    path: path.clone(),
+                    message: format!("missing required field '{}'", field),
+                });
+            }
+        }
+    }
+    let min = schema
+        .get("minProperties")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    if (map.len() as u64) < min {
+        out.push(Mismatch {
+            path: path.clone(),
+            message: "must not be empty".to_string(),
+        });
+    }
+
+    let properties = schema.get("properties").and_then(Value::as_object);
+    for (key, item) in map {
+        path.push(key.clone());
+        match (
+            properties.and_then(|p| p.get(key)),
+            schema.get("additionalProperties"),
+        ) {
+            (Some(property), _) => check(item, property, root, path, out),
+            (None, Some(Value::Bool(false))) => out.push(Mismatch {
+                path: path.clone(),
+                message: format!("unknown field '{}'", key),
+            }),
+            (None, Some(additional)) => check(item, additional, root, path, out),
+            (None, None) => {}
+        }
+        path.pop();
+    }
+}
+
+/// The mismatches to report when `value` matches none of `branches`, or `None`
+/// when it matches one. The branches whose type fits the value are the ones
+/// the author most likely meant, so their mismatches are reported; when the
+/// value has a type no branch accepts, that is the mismatch.
+fn one_of(
+    value: &Value,
+    branches: &[Value],
+    root: &Value,
+    path: &[String],
+) -> Option<Vec<Mismatch>> {
+    let mut candidates = Vec::new();
+    let mut types = Vec::new();
+    for branch in branches {
+        let branch_types = resolve(branch, root)
+            .as_object()
+            .map(schema_types)
+            .unwrap_or_default();
+        if !branch_types.is_empty() && !branch_types.iter().any(|t| has_type(value, t)) {
+            types.extend(branch_types);
+            continue;
+        }
+        let mut errors = Vec::new();
+        check(value, branch, root, &mut path.to_vec(), &mut errors);
+        if errors.is_empty() {
+            return None;
+        }
+        candidates.push(errors);
+    }
+    match candidates.into_iter().min_by_key(Vec::len) {
+        Some(errors) => Some(errors),
+        None => {
+            types.dedup();
+            Some(vec![Mismatch {
+                path: path.to_vec(),
+                message: format!("expected {}, found {}", describe(&types), type_name(value)),
+            }])
+        }
+    }
+}
+
+fn push(out: &mut Vec<Mismatch>, path: &[String], message: String) {
+    out.push(Mismatch {
+        path: path.to_vec(),
+        message,
+    });
+}
+
+/// Follows a `$ref` to the schema's `definitions`.
+fn resolve<'a>(schema: &'a Value, root: &'a Value) -> &'a Value {
+    match schema.get("$ref").and_then(Value::as_str) {
+        Some(reference) => reference
+            .strip_prefix("#/")
+            .and_then(|pointer| root.pointer(&format!("/{}", pointer)))
+            .map_or(schema, |target| resolve(target, root)),
+        None => schema,
+    }
+}
+
+fn schema_types(schema: &Map<String, Value>) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn has_type(value: &Value, kind: &str) -> bool {
+    match kind {
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        kind => type_name(value) == kind,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// "a string, an array or an object"
+fn describe(types: &[&str]) -> String {
+    let named: Vec<String> = types
+        .iter()
+        .map(|t| match *t {
+            "array" | "object" | "integer" => format!("an {}", t),
+            "null" => "null".to_string(),
+            t => format!("a {}", t),
+        })
+        .collect();
+    match named.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn messages(document: Value) -> Vec<String> {
+        check_schema(&document)
+            .into_iter()
+            .map(|m| format!("/{}: {}", m.path.join("/"), m.message))
+            .collect()
+    }
+
+    #[test]
+    fn valid_configs_have_no_mismatches() {
+        let config = json!({
+            "$schema": "https://example.com/robin.schema.json",
+            "include": ["../common.json"],
+            "vars": { "registry": "ghcr.io", "env": { "type": "enum", "choices": ["a", "b"] } },
+            "scripts": {
+                "build": "cargo build",
+                "test": ["@build", "cargo test"],
+                "open": { "cmd": { "macos": "open x", "linux": "xdg-open x" }, "desc": "Open" }
+            }
+        });
+        assert_eq!(messages(config), Vec::<String>::new());
+    }
+
+    #[test]
+    fn mismatches_name_the_offending_value() {
+        let config = json!({
+            "scripts": {
+                "build": { "cmdd": "cargo build" },
+                "test": 3,
+                "lint": { "cmd": "x", "vars": { "v": { "type": "float" } } }
+            },
+            "include": "other.json"
+        });
+        let found = messages(config);
+        assert!(found.contains(&"/include: expected an array, found string".to_string()));
+        assert!(found.contains(&"/scripts/build: missing required field 'cmd'".to_string()));
+        assert!(found.contains(&"/scripts/build/cmdd: unknown field 'cmdd'".to_string()));
+        assert!(found.contains(
+            &"/scripts/test: expected a string, an array or an object, found number".to_string()
+        ));
+        assert!(
+            found
+                .iter()
+                .any(|m| m.starts_with("/scripts/lint/vars/v/type: \"float\" must be one of"))
+        );
+    }
+
+    #[test]
+    fn the_bundled_schema_only_uses_supported_keywords() {
+        const CHECKED: &[&str] = &[
+            "$ref",
+            "type",
+            "enum",
+            "oneOf",
+            "properties",
+            "additionalProperties",
+            "required",
+            "minProperties",
+            "items",
+            "minItems",
+            "uniqueItems",
+            "minLength",
+            "format",
+        ];
+        const ANNOTATIONS: &[&str] = &[
+            "$schema",
+            "$id",
+            "title",
+            "description",
+            "default",
+            "definitions",
+        ];
+        fn walk(schema: &Value, path: &str, unsupported: &mut Vec<String>) {
+            let Some(schema) = schema.as_object() else {
+                return;
+            };
+            for (keyword, value) in schema {
+                if !CHECKED.contains(&keyword.as_str()) && !ANNOTATIONS.contains(&keyword.as_str())
+                {
+                    unsupported.push(format!("{}/{}", path, keyword));
+                }
+                let path = format!("{}/{}", path, keyword);
+                match keyword.as_str() {
+                    "properties" | "definitions" => value
+                        .as_object()
+                        .into_iter()
+                        .flatten()
+                        .for_each(|(name, schema)| {
+                            walk(schema, &format!("{}/{}", path, name), unsupported)
+                        }),
+                    "oneOf" => value.as_array().into_iter().flatten().enumerate().for_each(
+                        |(i, schema)| walk(schema, &format!("{}/{}", path, i), unsupported),
+                    ),
+                    "items" | "additionalProperties" => walk(value, &path, unsupported),
+                    "format" => {
+                        assert_eq!(value, "regex", "{}: only the regex format is checked", path)
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        let mut unsupported = Vec::new();
+        walk(&schema, "", &mut unsupported);
+        assert_eq!(unsupported, Vec::<String>::new());
+    }
+
+    #[test]
+    fn describe_lists_types_in_prose() {
+        assert_eq!(describe(&["string"]), "a string");
+        assert_eq!(
+            describe(&["string", "array", "object"]),
+            "a string, an array or an object"
+        );
+    }
+}
@@ -1,4 +1,8 @@
-use robin::utils::{replace_variables, split_command_and_args};
+use robin::utils::{
+    Quoting, replace_variables, replace_variables_with_env, shell_quote, split_command_and_args,
+    split_passthrough, template_variables,
+};
+use robin::{Cli, Commands};
 use serde_json::Value;
 
 #[test]
@@ -113,6 +117,15 @@ fn split_positional_after_first_flag_stays_with_args() {
     assert_eq!(var_args, vec!["--flag=1", "trailing"]);
 }
 
+#[test]
+fn split_short_flag_ends_the_command_name() {
+    // `robin ci -j 2`: `-j` is robin's flag, not part of a task called "ci -j 2".
+    let args = vec!["ci".to_string(), "-j".to_string(), "2".to_string()];
+    let (command, var_args) = split_command_and_args(&args);
+    assert_eq!(command, "ci");
+    assert_eq!(var_args, vec!["-j", "2"]);
+}
+
 // --- replace_variables error / override cases ---
 
 #[test]
@@ -263,3 +276,322 @@ fn bare_env_var_is_left_for_the_shell() {
     let result = replace_variables(&script, &[]).unwrap();
     assert_eq!(result.as_str().unwrap(), "echo ${HOME}");
 }
+
+#[test]
+fn test_template_variables_reports_defaults_and_choices() {
+    let vars = template_variables(&[
+        "deploy {{env=[staging, prod]}} {{tag}}",
+        "notify {{channel=#ops}} {{tag}}",
+    ]);
+
+    let names: Vec<_> = vars.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, vec!["env", "tag", "channel"]);
+    assert_eq!(vars[0].choices, vec!["staging", "prod"]);
+    assert!(vars[0].required);
+    assert_eq!(vars[1].default, None);
+    assert!(vars[1].required);
+    assert_eq!(vars[2].default.as_deref(), Some("#ops"));
+    assert!(!vars[2].required);
+}
+
+#[test]
+fn test_positional_placeholders_use_the_arguments_after_the_separator() {
+    let args: Vec<String> = ["--mode=fast", "--", "first", "two words", "it's"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+
+    let script = Value::String("run {{mode}} {{1}} {{3}} {{4=none}}".to_string());
+    let result = replace_variables(&script, &args).unwrap();
+    assert_eq!(result.as_str().unwrap(), "run fast first 'it'\\''s' none");
+
+    let script = Value::String("pytest {{@}}".to_string());
+    let result = replace_variables(&script, &args).unwrap();
+    assert_eq!(
+        result.as_str().unwrap(),
+        "pytest first 'two words' 'it'\\''s'"
+    );
+
+    let script = Value::String("echo {{args}}".to_string());
+    assert_eq!(
+        replace_variables(&script, &args[..1])
+            .unwrap()
+            .as_str()
+            .unwrap(),
+        "echo "
+    );
+}
+
+#[test]
+fn test_missing_positional_argument_is_an_error() {
+    let script = Value::String("cat {{1}}".to_string());
+    let err = replace_variables(&script, &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Missing positional argument 1 (pass it after --)"
+    );
+}
+
+#[test]
+fn test_split_passthrough_and_shell_quote() {
+    let args: Vec<String> = ["--a=1", "--", "--nocapture", "--"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let (named, positional) = split_passthrough(&args);
+    assert_eq!(named, &args[..1]);
+    assert_eq!(positional, &args[2..]);
+
+    assert_eq!(shell_quote("--nocapture"), "--nocapture");
+    assert_eq!(shell_quote("a b"), "'a b'");
+    assert_eq!(shell_quote(""), "''");
+    assert_eq!(shell_quote("$HOME"), "'$HOME'");
+}
+
+#[test]
+fn test_positional_placeholders_are_not_named_variables() {
+    let vars = template_variables(&["run {{1}} {{@}} {{args}} {{name}}"]);
+    let names: Vec<_> = vars.iter().map(|v| v.name.as_str()).collect();
+    assert_eq!(names, vec!["name"]);
+}
+
+fn substituted(command: &str, args: &[&str], quoting: Quoting) -> String {
+    let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+    replace_variables_with_env(
+        &Value::String(command.to_string()),
+        &args,
+        &[],
+        &[],
+        quoting,
+    )
+    .unwrap()
+    .as_str()
+    .unwrap()
+    .to_string()
+}
+
+#[test]
+fn test_values_are_quoted_for_where_the_placeholder_sits() {
+    let command = r#"echo {{msg}} "{{msg}}" '{{msg}}' {{msg|raw}}"#;
+    assert_eq!(
+        substituted(command, &["--msg=it's $HOME; rm"], Quoting::Posix),
+        r#"echo 'it'\''s $HOME; rm' "it's \$HOME; rm" 'it'\''s $HOME; rm' it's $HOME; rm"#
+    );
+    assert_eq!(
+        substituted(command, &["--msg=it's $HOME"], Quoting::PowerShell),
+        r#"echo 'it''s $HOME' "it's `$HOME" 'it''s $HOME' it's $HOME"#
+    );
+    assert_eq!(
+        substituted(
+            "echo {{msg}} \"{{msg}}\"",
+            &["--msg=say \"hi\""],
+            Quoting::Cmd
+        ),
+        r#"echo "say ""hi""" "say ""hi""""#
+    );
+    assert_eq!(
+        substituted("print({{msg}})", &["--msg='a b'"], Quoting::Raw),
+        "print('a b')"
+    );
+}
+
+#[test]
+fn test_quoting_skips_plain_and_empty_values() {
+    assert_eq!(
+        substituted(
+            "deploy {{env}} {{notes}}",
+            &["--env=prod", "--notes="],
+            Quoting::Posix
+        ),
+        "deploy prod "
+    );
+    assert_eq!(
+        substituted("git commit -m '{{notes}}'", &["--notes="], Quoting::Posix),
+        "git commit -m ''"
+    );
+    // A quote in a comment doesn't open a quoted string.
+    assert_eq!(
+        substituted("# don't\necho {{msg}}", &["--msg=a b"], Quoting::Posix),
+        "# don't\necho 'a b'"
+    );
+}
+
+#[test]
+fn test_positional_arguments_use_the_same_quoting() {
+    assert_eq!(
+        substituted(
+            "grep {{1}} {{@|raw}}",
+            &["--", "a b", "*.rs"],
+            Quoting::PowerShell
+        ),
+        "grep 'a b' a b *.rs"
+    );
+}
+
+#[test]
+fn test_positional_values_are_not_expanded_as_placeholders() {
+    assert_eq!(
+        substituted(
+            "echo {{1}} {{name}}",
+            &["--name=x", "--", "{{name}}"],
+            Quoting::Posix
+        ),
+        "echo '{{name}}' x"
+    );
+}
+
+#[test]
+fn test_defaults_from_the_config_are_not_quoted() {
+    let command = "cargo build {{flags=--release --locked}} {{1=a b}} {{extra|default(-v -q)}}";
+    assert_eq!(
+        substituted(command, &[], Quoting::Posix),
+        "cargo build --release --locked a b -v -q"
+    );
+    assert_eq!(
+        substituted(command, &["--flags=--x y", "--", "c d"], Quoting::Posix),
+        "cargo build '--x y' 'c d' -v -q"
+    );
+}
+
+#[test]
+fn test_quoting_for_shell() {
+    let shell = |argv: &[&str]| {
+        let argv: Vec<String> = argv.iter().map(|a| a.to_string()).collect();
+        Quoting::for_shell(Some(&argv))
+    };
+    assert_eq!(shell(&["bash", "-c"]), Quoting::Posix);
+    assert_eq!(shell(&["/usr/bin/zsh", "-c"]), Quoting::Posix);
+    assert_eq!(shell(&["pwsh.exe", "-Command"]), Quoting::PowerShell);
+    assert_eq!(shell(&["cmd", "/C"]), Quoting::Cmd);
+    assert_eq!(shell(&["python3", "-c"]), Quoting::Raw);
+    assert_eq!(Quoting::for_shell(None), Quoting::default());
+}
+
+#[test]
+fn test_raw_placeholders_keep_defaults_and_choices() {
+    let vars = template_variables(&["run {{mode=[fast, slow]|raw}} {{flags=-v|raw}}"]);
+    assert_eq!(vars[0].choices, vec!["fast", "slow"]);
+    assert_eq!(vars[1].default.as_deref(), Some("-v"));
+    assert_eq!(
+        substituted("run {{flags=-v --all|raw}}", &[], Quoting::Posix),
+        "run -v --all"
+    );
+}
+
+#[test]
+fn test_filters_transform_values_before_quoting() {
+    assert_eq!(
+        substituted(
+            "docker build -t app:{{branch|slug}} --build-arg ENV={{env|upper}}",
+            &["--branch=Feature/Add Login!", "--env=Staging"],
+            Quoting::Posix
+        ),
+        "docker build -t app:feature-add-login --build-arg ENV=STAGING"
+    );
+    assert_eq!(
+        substituted("echo {{name|lower|raw}}", &["--name=A B"], Quoting::Posix),
+        "echo a b"
+    );
+    assert_eq!(
+        substituted(
+            "deploy {{region|default(eu-west-1)}} {{1|default(all)}}",
+            &["--region="],
+            Quoting::Posix
+        ),
+        "deploy eu-west-1 all"
+    );
+    assert_eq!(
+        substituted("deploy {{region|default(eu)|upper}}", &[], Quoting::Posix),
+        "deploy EU"
+    );
+}
+
+#[test]
+fn test_unknown_filters_are_an_error() {
+    let script = Value::String("echo {{name|shout}}".to_string());
+    let err = replace_variables(&script, &["--name=x".to_string()]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Unknown filter '|shout' (expected raw, upper, lower, slug or default(...))"
+    );
+}
+
+#[test]
+fn test_computed_defaults_run_only_without_a_value() {
+    let command = "git push origin {{branch=$(echo main)}}";
+    assert_eq!(
+        substituted(command, &[], Quoting::Posix),
+        "git push origin main"
+    );
+    assert_eq!(
+        substituted(command, &["--branch=dev"], Quoting::Posix),
+        "git push origin dev"
+    );
+    // A pipe inside the command isn't taken for a filter.
+    let vars = template_variables(&["tag {{sha=$(echo abcdef | cut -c1-3)}}"]);
+    assert_eq!(
+        vars[0].default.as_deref(),
+        Some("$(echo abcdef | cut -c1-3)")
+    );
+    assert_eq!(
+        substituted(
+            "tag {{sha=$(echo abcdef | cut -c1-3)}}",
+            &[],
+            Quoting::Posix
+        ),
+        "tag abc"
+    );
+}
+
+fn cli(args: &[&str], tasks: &[&str]) -> Cli {
+    let args = args.iter().map(|a| a.to_string()).collect();
+    Cli::try_parse_with_tasks(args, |name| tasks.contains(&name)).unwrap()
+}
+
+#[test]
+fn test_a_task_named_watch_runs_instead_of_the_subcommand() {
+    let parsed = cli(&["robin", "--dry-run", "watch"], &["watch"]);
+    assert!(parsed.dry_run);
+    assert!(matches!(&parsed.command, Some(Commands::Run(args)) if args == &["watch"]));
+
+    let parsed = cli(&["robin", "-j", "2", "watch", "--dry-run"], &["watch"]);
+    assert_eq!(parsed.jobs, Some(2));
+    assert!(
+        matches!(&parsed.command, Some(Commands::Run(args)) if args == &["watch", "--dry-run"])
+    );
+
+    // Without such a task, `watch` is still the subcommand.
+    let parsed = cli(&["robin", "watch", "test"], &["test"]);
+    assert!(matches!(parsed.command, Some(Commands::Watch { .. })));
+}
+
+#[test]
+fn test_a_task_named_completions_runs_instead_of_the_subcommand() {
+    let parsed = cli(&["robin", "completions"], &["completions"]);
+    assert!(matches!(&parsed.command, Some(Commands::Run(args)) if args == &["completions"]));
+
+    let parsed = cli(&["robin", "completions", "zsh"], &[]);
+    assert!(matches!(parsed.command, Some(Commands::Completions { .. })));
+}
+
+#[test]
+fn test_a_task_named_validate_runs_instead_of_the_subcommand() {
+    let parsed = cli(&["robin", "validate", "--strict"], &["validate"]);
+    assert!(
+        matches!(&parsed.command, Some(Commands::Run(args)) if args == &["validate", "--strict"])
+    );
+
+    let parsed = cli(&["robin", "validate", "ci/robin.json"], &[]);
+    assert!(matches!(parsed.command, Some(Commands::Validate { .. })));
+}
+
+#[test]
+fn test_a_task_named_convert_runs_instead_of_the_subcommand() {
+    let parsed = cli(&["robin", "convert", "--to=png"], &["convert"]);
+    assert!(
+        matches!(&parsed.command, Some(Commands::Run(args)) if args == &["convert", "--to=png"])
+    );
+
+    let parsed = cli(&["robin", "convert", "--to", "yaml"], &[]);
+    assert!(matches!(parsed.command, Some(Commands::Convert { .. })));
+}
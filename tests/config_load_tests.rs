@@ -1,4 +1,6 @@
-use robin::config::{RobinConfig, find_config_from};
+use robin::config::{
+    ConfigFormat, RobinConfig, TaskSources, config_vars, find_config_from, script_cwd, script_shell,
+};
 use std::fs;
 use tempfile::tempdir;
 
@@ -29,6 +31,23 @@ fn find_config_prefers_the_nearest_config() {
     assert_eq!(found, sub.join(".robin.json"));
 }
 
+#[test]
+fn find_config_picks_json_then_yaml_then_toml() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    fs::write(root.join("robin.toml"), "[scripts]\n").unwrap();
+    assert_eq!(find_config_from(root), Some(root.join("robin.toml")));
+
+    fs::write(root.join(".robin.yml"), "scripts: {}\n").unwrap();
+    assert_eq!(find_config_from(root), Some(root.join(".robin.yml")));
+
+    fs::write(root.join(".robin.yaml"), "scripts: {}\n").unwrap();
+    assert_eq!(find_config_from(root), Some(root.join(".robin.yaml")));
+
+    fs::write(root.join(".robin.json"), r#"{"scripts":{}}"#).unwrap();
+    assert_eq!(find_config_from(root), Some(root.join(".robin.json")));
+}
+
 #[test]
 fn find_config_returns_none_when_absent() {
     let dir = tempdir().unwrap();
@@ -42,7 +61,8 @@ fn load_missing_file_gives_helpful_error() {
 
     let err = RobinConfig::load(&path).unwrap_err();
     assert!(
-        err.to_string().contains("No .robin.json found"),
+        err.to_string()
+            .starts_with("No .robin.json, .robin.yaml, .robin.yml or robin.toml found"),
         "unexpected error: {err}"
     );
 }
@@ -60,6 +80,32 @@ fn load_malformed_json_gives_helpful_error() {
     );
 }
 
+#[test]
+fn load_accepts_comments_and_trailing_commas() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+    "$schema": "https://example.com/robin.json",
+    // Tasks
+    "scripts": {
+        "build": "cargo build", /* the default */
+        lint: 'cargo clippy',
+    },
+}"#,
+    )
+    .unwrap();
+
+    let config = RobinConfig::load(&path).unwrap();
+    assert_eq!(
+        config.schema.as_deref(),
+        Some("https://example.com/robin.json")
+    );
+    assert_eq!(config.scripts["lint"], "cargo clippy");
+    assert_eq!(config.scripts.len(), 2);
+}
+
 #[test]
 fn load_defaults_include_to_empty() {
     // `include` is optional; a config without it must still load.
@@ -72,6 +118,34 @@ fn load_defaults_include_to_empty() {
     assert_eq!(config.scripts.len(), 1);
 }
 
+#[test]
+fn top_level_vars_of_includes_are_merged_with_base_taking_precedence() {
+    let dir = tempdir().unwrap();
+    let base = dir.path().join(".robin.json");
+    fs::write(
+        &base,
+        r#"{"include":["child.json"],"vars":{"app_id":"com.acme.app"},"scripts":{}}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("child.json"),
+        r#"{"vars":{"app_id":"com.child","registry":{"default":"ghcr.io"}},"scripts":{}}"#,
+    )
+    .unwrap();
+
+    let config = RobinConfig::load(&base).unwrap();
+
+    let vars = config_vars(&config.vars).unwrap();
+    let defaults: Vec<_> = vars
+        .iter()
+        .map(|v| (v.name.as_str(), v.default.as_deref().unwrap()))
+        .collect();
+    assert_eq!(
+        defaults,
+        vec![("app_id", "com.acme.app"), ("registry", "ghcr.io")]
+    );
+}
+
 #[test]
 fn includes_are_merged_with_base_taking_precedence() {
     let dir = tempdir().unwrap();
@@ -106,6 +180,147 @@ fn includes_are_merged_with_base_taking_precedence() {
     assert_eq!(config.scripts.len(), 3);
 }
 
+#[test]
+fn load_with_sources_records_the_declaring_file_of_each_task() {
+    let dir = tempdir().unwrap();
+    let base = dir.path().join(".robin.json");
+    let nested = dir.path().join("nested");
+    fs::create_dir(&nested).unwrap();
+
+    fs::write(
+        &base,
+        r#"{"include":["nested/child.json"],"scripts":{"shared":"base","only_base":"b"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        nested.join("child.json"),
+        r#"{"include":["grandchild.json"],"scripts":{"shared":"child","only_child":"c"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        nested.join("grandchild.json"),
+        r#"{"scripts":{"deep":"d"}}"#,
+    )
+    .unwrap();
+
+    let (
+        config,
+        TaskSources {
+            files: sources,
+            overridden,
+            ..
+        },
+    ) = RobinConfig::load_with_global(&base, None).unwrap();
+
+    assert_eq!(config.scripts.len(), 4);
+    assert_eq!(sources["shared"], base);
+    assert_eq!(sources["only_base"], base);
+    assert_eq!(sources["only_child"], dir.path().join("nested/child.json"));
+    assert_eq!(
+        sources["deep"],
+        dir.path()
+            .join("nested/child.json")
+            .parent()
+            .unwrap()
+            .join("grandchild.json")
+    );
+    assert!(overridden.is_empty());
+}
+
+#[test]
+fn local_overrides_replace_and_extend_the_shared_config() {
+    let dir = tempdir().unwrap();
+    let base = dir.path().join(".robin.json");
+    let local = dir.path().join(".robin.local.yaml");
+    fs::write(
+        &base,
+        r#"{
+            "include": ["shared.json"],
+            "vars": { "device": "emulator-5554", "flavor": "dev" },
+            "scripts": { "run": "adb -s {{device}} install", "lint": "cargo clippy" }
+        }"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("shared.json"),
+        r#"{"scripts":{"deploy":"ship it"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        &local,
+        "vars:\n  device: R58M123\nscripts:\n  deploy: echo skip\n  run: adb -s {{device}} install -r\n  debug: adb logcat\n",
+    )
+    .unwrap();
+
+    let (config, sources) = RobinConfig::load_with_global(&base, None).unwrap();
+
+    let names: Vec<&str> = config.scripts.keys().map(String::as_str).collect();
+    assert_eq!(names, vec!["run", "lint", "deploy", "debug"]);
+    assert_eq!(config.scripts["run"], "adb -s {{device}} install -r");
+    assert_eq!(config.scripts["deploy"], "echo skip");
+    assert_eq!(config.vars["device"], "R58M123");
+    assert_eq!(config.vars["flavor"], "dev");
+
+    assert_eq!(sources.files["run"], local);
+    assert_eq!(sources.files["debug"], local);
+    assert_eq!(sources.files["lint"], base);
+    assert_eq!(sources.overridden.len(), 2);
+    assert_eq!(sources.overridden["run"], base);
+    assert_eq!(sources.overridden["deploy"], dir.path().join("shared.json"));
+
+    // Commands that edit the config only ever see the shared file.
+    let raw = RobinConfig::load_raw(&base).unwrap();
+    assert_eq!(raw.scripts["run"], "adb -s {{device}} install");
+    assert!(!raw.scripts.contains_key("debug"));
+}
+
+#[test]
+fn global_tasks_are_merged_under_the_project_ones() {
+    let home = tempdir().unwrap();
+    let global = home.path().join("robin").join("global.json");
+    fs::create_dir_all(global.parent().unwrap()).unwrap();
+    fs::write(
+        &global,
+        r#"{
+            "shell": "zsh",
+            "vars": { "port": 3000, "env": "global" },
+            "scripts": { "kill port": "lsof -ti :{{port}} | xargs kill", "build": "make" }
+        }"#,
+    )
+    .unwrap();
+
+    let project = tempdir().unwrap();
+    let base = project.path().join(".robin.json");
+    fs::write(
+        &base,
+        r#"{"vars":{"env":"project"},"scripts":{"build":"cargo build"}}"#,
+    )
+    .unwrap();
+
+    let (config, sources) = RobinConfig::load_with_global(&base, Some(&global)).unwrap();
+    let names: Vec<&str> = config.scripts.keys().map(String::as_str).collect();
+    assert_eq!(names, vec!["build", "kill port"]);
+    assert_eq!(config.scripts["build"], "cargo build");
+    assert_eq!(
+        script_shell(&config.scripts["kill port"]).unwrap(),
+        Some(vec!["zsh".to_string(), "-c".to_string()])
+    );
+    assert_eq!(config.vars["env"], "project");
+    assert_eq!(config.vars["port"], 3000);
+    assert_eq!(sources.files["kill port"], global);
+    assert!(sources.global.contains("kill port"));
+    assert!(!sources.global.contains("build"));
+
+    // Outside any project, the global tasks are all there is.
+    let elsewhere = tempdir().unwrap();
+    let missing = elsewhere.path().join(".robin.json");
+    let (config, sources) = RobinConfig::load_with_global(&missing, Some(&global)).unwrap();
+    assert_eq!(config.scripts.len(), 2);
+    assert_eq!(sources.global.len(), 2);
+
+    assert!(RobinConfig::load_with_global(&missing, None).is_err());
+}
+
 #[test]
 fn includes_resolve_relative_to_config_dir() {
     // The included path is resolved against the parent of the config file,
@@ -123,6 +338,78 @@ fn includes_resolve_relative_to_config_dir() {
     assert!(config.scripts.contains_key("b"));
 }
 
+#[test]
+fn included_task_paths_stay_relative_to_their_own_file() {
+    // packages/api/.robin.json includes ../shared/robin.json, which declares a
+    // task with `cwd: "."`; the root config includes the api file.
+    let dir = tempdir().unwrap();
+    let api = dir.path().join("packages/api");
+    let shared = dir.path().join("packages/shared");
+    fs::create_dir_all(&api).unwrap();
+    fs::create_dir_all(&shared).unwrap();
+
+    let base = dir.path().join(".robin.json");
+    fs::write(
+        &base,
+        r#"{"include":["packages/api/.robin.json"],"scripts":{"root":{"cmd":"x","cwd":"tools"}}}"#,
+    )
+    .unwrap();
+    fs::write(
+        api.join(".robin.json"),
+        r#"{"include":["../shared/robin.json"],"scripts":{"api:test":{"cmd":"cargo test","cwd":"."}}}"#,
+    )
+    .unwrap();
+    fs::write(
+        shared.join("robin.json"),
+        r#"{"scripts":{"lint":{"cmd":"lint","cwd":"."}}}"#,
+    )
+    .unwrap();
+
+    let config = RobinConfig::load(&base).unwrap();
+    assert_eq!(script_cwd(&config.scripts["root"]), Some("tools"));
+    assert_eq!(
+        script_cwd(&config.scripts["api:test"]),
+        Some("packages/api/.")
+    );
+    assert_eq!(
+        script_cwd(&config.scripts["lint"]),
+        Some("packages/api/../shared/.")
+    );
+
+    // Editing commands work on the raw file, which keeps the paths as written.
+    let raw = RobinConfig::load_raw(&api.join(".robin.json")).unwrap();
+    assert_eq!(script_cwd(&raw.scripts["api:test"]), Some("."));
+}
+
+#[test]
+fn included_tasks_keep_their_files_default_shell() {
+    let dir = tempdir().unwrap();
+    let base = dir.path().join(".robin.json");
+    fs::write(
+        &base,
+        r#"{"include":["bash.json"],"shell":"zsh","scripts":{"local":"echo hi"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join("bash.json"),
+        r#"{"shell":["bash","-c"],"scripts":{"strict":"set -o pipefail","own":{"cmd":"x","shell":"fish"}}}"#,
+    )
+    .unwrap();
+
+    let config = RobinConfig::load(&base).unwrap();
+    assert_eq!(config.shell, Some(serde_json::json!("zsh")));
+    // The root file's own tasks rely on the top-level default at run time.
+    assert_eq!(script_shell(&config.scripts["local"]).unwrap(), None);
+    assert_eq!(
+        script_shell(&config.scripts["strict"]).unwrap(),
+        Some(vec!["bash".to_string(), "-c".to_string()])
+    );
+    assert_eq!(
+        script_shell(&config.scripts["own"]).unwrap(),
+        Some(vec!["fish".to_string(), "-c".to_string()])
+    );
+}
+
 #[test]
 fn missing_include_reports_which_file_failed() {
     let dir = tempdir().unwrap();
@@ -184,3 +471,84 @@ fn save_then_load_roundtrips() {
     let loaded = RobinConfig::load(&path).unwrap();
     assert_eq!(loaded.scripts.len(), config.scripts.len());
 }
+
+#[test]
+fn convert_rewrites_the_config_in_another_format() {
+    let dir = tempdir().unwrap();
+    let json = dir.path().join(".robin.json");
+    fs::write(
+        &json,
+        r#"{ "scripts": { "build": "cargo build", "setup": { "script": "set -e\nnpm ci\n", "desc": "Install" } } }"#,
+    )
+    .unwrap();
+    let original = RobinConfig::load(&json).unwrap();
+
+    let yaml = RobinConfig::convert(&json, ConfigFormat::Yaml).unwrap();
+    assert_eq!(yaml, dir.path().join(".robin.yaml"));
+    assert!(!json.exists());
+    assert!(fs::read_to_string(&yaml).unwrap().contains("script: |"));
+
+    let toml = RobinConfig::convert(&yaml, ConfigFormat::Toml).unwrap();
+    assert_eq!(toml, dir.path().join("robin.toml"));
+    assert_eq!(RobinConfig::load(&toml).unwrap().scripts, original.scripts);
+
+    fs::write(&json, r#"{"scripts":{}}"#).unwrap();
+    let err = RobinConfig::convert(&toml, ConfigFormat::Json).unwrap_err();
+    assert!(err.to_string().contains("already exists"), "{err}");
+}
+
+#[test]
+fn save_edits_an_existing_json_config_in_place() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    let original = r#"{
+    "scripts": {
+        "zeta": "echo z",
+        "alpha": { "cmd": "echo a", "desc": "First" }
+    },
+    "x-team": "platform"
+}
+"#;
+    fs::write(&path, original).unwrap();
+
+    let mut config = RobinConfig::load_raw(&path).unwrap();
+    config.rename_script("zeta", "omega").unwrap();
+    config
+        .scripts
+        .insert("build".to_string(), serde_json::json!("cargo build"));
+    config.save(&path).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        original.replace("zeta", "omega").replace(
+            "\"First\" }\n",
+            "\"First\" },\n        \"build\": \"cargo build\"\n"
+        )
+    );
+    let names: Vec<String> = RobinConfig::load(&path)
+        .unwrap()
+        .scripts
+        .keys()
+        .cloned()
+        .collect();
+    assert_eq!(names, vec!["omega", "alpha", "build"]);
+}
+
+#[test]
+fn save_edits_an_existing_toml_config_in_place() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("robin.toml");
+    let original = "# Team tasks\n[scripts]\nzeta = \"echo z\" # keep me\nalpha = \"echo a\"\n";
+    fs::write(&path, original).unwrap();
+
+    let mut config = RobinConfig::load_raw(&path).unwrap();
+    config
+        .scripts
+        .insert("build".to_string(), serde_json::json!("cargo build"));
+    config.save(&path).unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        format!("{}build = \"cargo build\"\n", original)
+    );
+}
@@ -1,16 +1,19 @@
+use indexmap::IndexMap;
 use robin::config::RobinConfig;
 use robin::tools::{check_environment, update_tools};
 use serde_json::{Value, json};
-use std::collections::HashMap;
 
 fn config_with(scripts: &[(&str, Value)]) -> RobinConfig {
-    let mut map = HashMap::new();
+    let mut map = IndexMap::new();
     for (name, script) in scripts {
         map.insert((*name).to_string(), script.clone());
     }
     RobinConfig {
         schema: None,
         include: vec![],
+        shell: None,
+        vars: IndexMap::new(),
+        secret_env: vec![],
         scripts: map,
     }
 }
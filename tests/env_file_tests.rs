@@ -1,4 +1,5 @@
 use robin::load_env_file;
+use robin::utils::{expand_env, read_env_file};
 use std::fs;
 use tempfile::tempdir;
 
@@ -26,3 +27,44 @@ fn missing_dotenv_is_not_an_error() {
     // No .env in the directory — must simply be a no-op.
     load_env_file(&dir.path().join(".robin.json"));
 }
+
+#[test]
+fn read_env_file_leaves_the_process_environment_alone() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".env.staging");
+    fs::write(&path, "# staging\nROBIN_TEST_READ_ONLY=1\nQUOTED=\"a b\"\n").unwrap();
+
+    let vars = read_env_file(&path).unwrap();
+    assert_eq!(
+        vars,
+        vec![
+            ("ROBIN_TEST_READ_ONLY".to_string(), "1".to_string()),
+            ("QUOTED".to_string(), "a b".to_string()),
+        ]
+    );
+    assert!(std::env::var("ROBIN_TEST_READ_ONLY").is_err());
+}
+
+#[test]
+fn read_env_file_errors_when_missing() {
+    let dir = tempdir().unwrap();
+    let err = read_env_file(&dir.path().join(".env.missing")).unwrap_err();
+    assert!(err.to_string().contains(".env.missing"), "{err}");
+}
+
+#[test]
+fn expand_env_handles_plain_and_default_references() {
+    let lookup = |name: &str| match name {
+        "HOST" => Some("example.com".to_string()),
+        "EMPTY" => Some(String::new()),
+        _ => None,
+    };
+    assert_eq!(
+        expand_env("https://${HOST}/api", lookup),
+        "https://example.com/api"
+    );
+    assert_eq!(expand_env("${MISSING}", lookup), "");
+    assert_eq!(expand_env("${EMPTY:-fallback}", lookup), "fallback");
+    assert_eq!(expand_env("${EMPTY-fallback}", lookup), "");
+    assert_eq!(expand_env("$HOST stays", lookup), "$HOST stays");
+}
@@ -1,11 +1,13 @@
 mod common;
 
+use indexmap::IndexMap;
 use robin::config::RobinConfig;
 use robin::scripts::{
-    command_lines, list_commands, resolve_task_command, run_script, run_script_in,
+    RunOptions, command_lines, list_commands, plan_task, resolve_task_command, run_plan,
+    run_script, run_script_in, task_order,
 };
 use serde_json::{Value, json};
-use std::collections::HashMap;
+use std::path::Path;
 use tempfile::tempdir;
 
 #[test]
@@ -27,7 +29,14 @@ fn run_script_in_without_dir_does_not_see_the_marker() {
     assert!(run_script_in(&script, false, None).is_err());
 }
 
-fn scripts_from(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+fn in_dir(dir: &Path) -> RunOptions {
+    RunOptions {
+        root: Some(dir.to_path_buf()),
+        ..RunOptions::default()
+    }
+}
+
+fn scripts_from(pairs: &[(&str, Value)]) -> IndexMap<String, Value> {
     pairs
         .iter()
         .map(|(k, v)| (k.to_string(), v.clone()))
@@ -106,6 +115,603 @@ fn resolve_follows_reference_into_object_form() {
     assert_eq!(resolved, json!(["cargo build", "deploy"]));
 }
 
+#[test]
+fn task_order_runs_dependencies_first() {
+    let scripts = scripts_from(&[
+        ("codegen", json!("gen")),
+        (
+            "build",
+            json!({ "cmd": "cargo build", "deps": ["codegen"] }),
+        ),
+        ("test", json!({ "cmd": "cargo test", "deps": ["build"] })),
+    ]);
+    assert_eq!(
+        task_order("test", &scripts).unwrap(),
+        vec!["codegen", "build", "test"]
+    );
+}
+
+#[test]
+fn task_order_runs_a_shared_dependency_once() {
+    // Diamond: both `lint` and `unit` need `install`; it must appear once.
+    let scripts = scripts_from(&[
+        ("install", json!("npm ci")),
+        ("lint", json!({ "cmd": "eslint .", "deps": ["install"] })),
+        ("unit", json!({ "cmd": "jest", "deps": ["install"] })),
+        (
+            "ci",
+            json!({ "cmd": "echo done", "deps": ["lint", "unit"] }),
+        ),
+    ]);
+    assert_eq!(
+        task_order("ci", &scripts).unwrap(),
+        vec!["install", "lint", "unit", "ci"]
+    );
+}
+
+#[test]
+fn task_order_detects_dependency_cycles() {
+    let scripts = scripts_from(&[
+        ("a", json!({ "cmd": "a", "deps": ["b"] })),
+        ("b", json!({ "cmd": "b", "deps": ["a"] })),
+    ]);
+    let err = task_order("a", &scripts).unwrap_err();
+    assert!(err.to_string().contains("a -> b -> a"), "{err}");
+}
+
+#[test]
+fn task_order_errors_on_unknown_dependency() {
+    let scripts = scripts_from(&[("a", json!({ "cmd": "a", "deps": ["missing"] }))]);
+    let err = task_order("a", &scripts).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Dependency 'missing' of task 'a' not found"),
+        "{err}"
+    );
+}
+
+#[test]
+fn plan_task_resolves_and_substitutes_every_step() {
+    let scripts = scripts_from(&[
+        ("clean", json!("rm -rf {{dir=build}}")),
+        ("pre", json!(["@clean", "mkdir {{dir=build}}"])),
+        ("build", json!({ "cmd": "make {{mode}}", "deps": ["pre"] })),
+    ]);
+    let plan = plan_task(
+        "build",
+        &scripts,
+        &["--mode=release".to_string()],
+        &RunOptions::default(),
+    )
+    .unwrap();
+
+    assert_eq!(plan.len(), 2);
+    assert_eq!(plan[0].name, "pre");
+    assert_eq!(plan[0].script(), json!(["rm -rf build", "mkdir build"]));
+    assert_eq!(plan[1].name, "build");
+    assert_eq!(plan[1].script(), json!("make release"));
+}
+
+#[test]
+fn plan_task_loads_env_files_and_env_per_task() {
+    let dir = tempdir().unwrap();
+    std::fs::write(
+        dir.path().join(".env.staging"),
+        "API_HOST=staging.example.com\nAPI_TOKEN=secret\n",
+    )
+    .unwrap();
+    let scripts = scripts_from(&[
+        (
+            "login",
+            json!({ "cmd": "login", "env": { "STEP": "login" } }),
+        ),
+        (
+            "deploy",
+            json!({
+                "cmd": ["@login", "ship ${PORT:-80}"],
+                "env_file": [".env.staging"],
+                "env": { "URL": "https://${API_HOST}/v1", "PORT": "8443" }
+            }),
+        ),
+    ]);
+    let plan = plan_task("deploy", &scripts, &[], &in_dir(dir.path())).unwrap();
+    let steps = &plan[0].steps;
+
+    let env = |i: usize, key: &str| {
+        steps[i]
+            .env
+            .iter()
+            .rev()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.clone())
+    };
+    assert_eq!(
+        env(1, "URL").as_deref(),
+        Some("https://staging.example.com/v1")
+    );
+    assert_eq!(env(1, "API_TOKEN").as_deref(), Some("secret"));
+    // Defaults in the command see the task's own environment.
+    assert_eq!(steps[1].command, "ship 8443");
+    // A referenced task's steps get its own env on top of the caller's.
+    assert_eq!(env(0, "STEP").as_deref(), Some("login"));
+    assert_eq!(env(0, "API_TOKEN").as_deref(), Some("secret"));
+    assert_eq!(env(1, "STEP"), None);
+}
+
+#[test]
+fn plan_task_errors_on_missing_env_file() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[("deploy", json!({ "cmd": "ship", "env_file": ".env.prod" }))]);
+    let err = plan_task("deploy", &scripts, &[], &in_dir(dir.path())).unwrap_err();
+    assert!(format!("{err:#}").contains(".env.prod"), "{err:#}");
+}
+
+#[test]
+fn task_env_reaches_only_that_tasks_commands() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[
+        (
+            "setup",
+            json!({ "cmd": "printf \"$GREETING\" > setup.txt", "env": { "GREETING": "hi" } }),
+        ),
+        (
+            "main",
+            json!({ "cmd": "printf \"${GREETING:-none}\" > main.txt", "deps": ["setup"] }),
+        ),
+    ]);
+    let plan = plan_task("main", &scripts, &[], &in_dir(dir.path())).unwrap();
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..RunOptions::default()
+    };
+    run_plan(&plan, &options).unwrap();
+
+    let read = |f: &str| std::fs::read_to_string(dir.path().join(f)).unwrap();
+    assert_eq!(read("setup.txt"), "hi");
+    assert_eq!(read("main.txt"), "none");
+    assert!(std::env::var("GREETING").is_err());
+}
+
+#[test]
+fn referenced_tasks_run_in_their_own_directory() {
+    let dir = tempdir().unwrap();
+    for pkg in ["api", "web"] {
+        std::fs::create_dir_all(dir.path().join(pkg)).unwrap();
+    }
+    let scripts = scripts_from(&[
+        ("api:test", json!({ "cmd": "pwd > out.txt", "cwd": "api" })),
+        ("web:test", json!({ "cmd": "pwd > out.txt", "cwd": "web" })),
+        ("test", json!(["@api:test", "@web:test", "pwd > out.txt"])),
+    ]);
+    let plan = plan_task("test", &scripts, &[], &in_dir(dir.path())).unwrap();
+    let dirs: Vec<_> = plan[0].steps.iter().map(|s| s.cwd.clone()).collect();
+    assert_eq!(
+        dirs,
+        vec![
+            Some(dir.path().join("api")),
+            Some(dir.path().join("web")),
+            None
+        ]
+    );
+
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..RunOptions::default()
+    };
+    run_plan(&plan, &options).unwrap();
+    for pkg in ["api", "web"] {
+        let written = std::fs::read_to_string(dir.path().join(pkg).join("out.txt")).unwrap();
+        assert!(written.trim_end().ends_with(pkg), "{written}");
+    }
+    assert!(dir.path().join("out.txt").exists());
+}
+
+#[test]
+fn plan_task_errors_on_missing_task_directory() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[("test", json!({ "cmd": "make", "cwd": "nope" }))]);
+    let err = plan_task("test", &scripts, &[], &in_dir(dir.path())).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Working directory of task 'test' not found"),
+        "{err}"
+    );
+}
+
+#[test]
+fn plan_task_picks_the_requested_platform_variant() {
+    let scripts = scripts_from(&[
+        (
+            "open",
+            json!({ "cmd": { "macos": "open cov.html", "default": "xdg-open cov.html" } }),
+        ),
+        ("win", json!({ "cmd": { "windows": "start cov.html" } })),
+        ("report", json!(["@open", "echo done"])),
+    ]);
+    let on = |platform: &str| RunOptions {
+        platform: Some(platform.to_string()),
+        ..RunOptions::default()
+    };
+
+    let plan = plan_task("report", &scripts, &[], &on("macos")).unwrap();
+    assert_eq!(plan[0].script(), json!(["open cov.html", "echo done"]));
+    let plan = plan_task("report", &scripts, &[], &on("linux")).unwrap();
+    assert_eq!(plan[0].script(), json!(["xdg-open cov.html", "echo done"]));
+
+    let err = plan_task("win", &scripts, &[], &on("linux")).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Task 'win' has no command for linux (available: windows)"
+    );
+}
+
+#[test]
+fn tasks_run_under_their_own_or_the_default_shell() {
+    let scripts = scripts_from(&[
+        ("plain", json!("false | true")),
+        (
+            "strict",
+            json!({ "cmd": "false | true", "shell": ["bash", "-o", "pipefail", "-c"] }),
+        ),
+        ("calls-strict", json!(["@strict"])),
+    ]);
+    let defaults = RunOptions {
+        shell: Some(vec!["sh".to_string(), "-c".to_string()]),
+        ..RunOptions::default()
+    };
+
+    let plan = plan_task("plain", &scripts, &[], &defaults).unwrap();
+    assert_eq!(plan[0].steps[0].shell, defaults.shell);
+    assert!(run_plan(&plan, &defaults).is_ok());
+
+    // The referenced task's shell wins over the default.
+    let plan = plan_task("calls-strict", &scripts, &[], &defaults).unwrap();
+    assert_eq!(plan[0].steps[0].shell.as_ref().unwrap()[0], "bash");
+    let err = run_plan(&plan, &defaults).unwrap_err();
+    assert!(err.to_string().contains("Script failed"), "{err}");
+}
+
+#[test]
+fn script_blocks_run_as_one_process_and_stop_at_the_first_failure() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[
+        (
+            "setup",
+            json!({ "script": ["mkdir -p ios", "cd ios", "export GREETING=hi", "echo $GREETING > here.txt"] }),
+        ),
+        ("block-string", json!({ "cmd": "cd ios\ntouch from-cmd" })),
+        ("broken", json!({ "script": ["false", "touch after"] })),
+        ("all", json!(["@setup", "@block-string"])),
+    ]);
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..in_dir(dir.path())
+    };
+
+    let plan = plan_task("all", &scripts, &[], &options).unwrap();
+    assert_eq!(plan[0].steps.len(), 2);
+    assert_eq!(plan[0].steps[0].summary(), "mkdir -p ios …");
+    run_plan(&plan, &options).unwrap();
+    let here = std::fs::read_to_string(dir.path().join("ios/here.txt")).unwrap();
+    assert_eq!(here.trim(), "hi");
+    assert!(dir.path().join("ios/from-cmd").exists());
+
+    let plan = plan_task("broken", &scripts, &[], &options).unwrap();
+    assert!(run_plan(&plan, &options).is_err());
+    assert!(!dir.path().join("after").exists());
+}
+
+#[test]
+fn defaults_from_the_config_are_not_quoted() {
+    let scripts = scripts_from(&[(
+        "build",
+        json!({
+            "cmd": "cargo build {{flags=--release --locked}} {{features}}",
+            "vars": { "features": { "default": "--features a,b" } }
+        }),
+    )]);
+    let plan = plan_task("build", &scripts, &[], &RunOptions::default()).unwrap();
+    assert_eq!(
+        plan[0].steps[0].command,
+        "cargo build --release --locked --features a,b"
+    );
+
+    let args = vec!["--features=x y".to_string()];
+    let plan = plan_task("build", &scripts, &args, &RunOptions::default()).unwrap();
+    assert_eq!(
+        plan[0].steps[0].command,
+        "cargo build --release --locked 'x y'"
+    );
+}
+
+#[test]
+fn a_value_with_a_newline_does_not_turn_a_command_into_a_script_block() {
+    let scripts = scripts_from(&[("say", json!("echo {{msg}}"))]);
+    let args = vec!["--msg=one\ntwo".to_string()];
+    let plan = plan_task("say", &scripts, &args, &RunOptions::default()).unwrap();
+
+    let step = &plan[0].steps[0];
+    assert_eq!(step.command, "echo 'one\ntwo'");
+    assert!(!step.script);
+}
+
+#[test]
+fn script_blocks_run_under_the_configured_interpreter() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[(
+        "py",
+        json!({
+            "script": ["import pathlib", "pathlib.Path('out.txt').write_text('{{word=block}}')"],
+            "shell": "python3 -c"
+        }),
+    )]);
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..in_dir(dir.path())
+    };
+    let plan = plan_task("py", &scripts, &[], &options).unwrap();
+    run_plan(&plan, &options).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("out.txt")).unwrap(),
+        "block"
+    );
+}
+
+#[test]
+fn declared_vars_are_validated_before_any_task_runs() {
+    let dir = tempdir().unwrap();
+    let scripts = scripts_from(&[
+        ("prepare", json!("touch prepared")),
+        (
+            "release",
+            json!({
+                "cmd": "echo {{version}} {{dry=false}} > released",
+                "deps": ["prepare"],
+                "vars": {
+                    "version": { "type": "semver", "pattern": "^v" },
+                    "dry": { "type": "bool" }
+                }
+            }),
+        ),
+    ]);
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..in_dir(dir.path())
+    };
+    let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+
+    let err = plan_task("release", &scripts, &args(&["--version=v1.2"]), &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value 'v1.2' for version must be a semantic version (e.g. 1.2.3)"
+    );
+    let err = plan_task("release", &scripts, &args(&["--version=1.2.3"]), &options).unwrap_err();
+    assert!(err.to_string().contains("must match ^v"), "{err}");
+    assert!(!dir.path().join("prepared").exists());
+
+    let plan = plan_task("release", &scripts, &args(&["--version=v1.2.3"]), &options).unwrap();
+    run_plan(&plan, &options).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("released")).unwrap(),
+        "v1.2.3 false\n"
+    );
+}
+
+#[test]
+fn choices_from_lists_the_allowed_values_at_run_time() {
+    let dir = tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("lanes")).unwrap();
+    std::fs::write(dir.path().join("lanes/beta"), "").unwrap();
+    std::fs::write(dir.path().join("lanes/release"), "").unwrap();
+    let scripts = scripts_from(&[(
+        "lane",
+        json!({
+            "cmd": "echo {{lane}} > ran",
+            "vars": { "lane": { "type": "enum", "choices_from": "ls lanes" } }
+        }),
+    )]);
+    let options = RunOptions {
+        cwd: Some(dir.path().to_path_buf()),
+        ..in_dir(dir.path())
+    };
+
+    let err = plan_task("lane", &scripts, &["--lane=alpha".to_string()], &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value 'alpha' for lane must be one of: beta, release"
+    );
+
+    std::fs::write(dir.path().join("lanes/alpha"), "").unwrap();
+    let plan = plan_task("lane", &scripts, &["--lane=alpha".to_string()], &options).unwrap();
+    run_plan(&plan, &options).unwrap();
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("ran")).unwrap(),
+        "alpha\n"
+    );
+}
+
+#[test]
+fn choices_from_runs_in_the_config_directory_and_only_when_needed() {
+    let dir = tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("sub")).unwrap();
+    std::fs::write(dir.path().join("flavors.txt"), "free\npaid\n").unwrap();
+    let scripts = scripts_from(&[(
+        "build",
+        json!({
+            "cmd": "echo {{flavor}} {{lane}}",
+            "vars": {
+                "flavor": { "type": "enum", "choices_from": { "file": "flavors.txt" } },
+                "lane": { "type": "enum", "choices_from": "exit 1", "default": "beta" }
+            }
+        }),
+    )]);
+    let options = RunOptions {
+        cwd: Some(dir.path().join("sub")),
+        prompt: false,
+        ..in_dir(dir.path())
+    };
+
+    // `lane` isn't passed, so its failing `choices_from` never runs.
+    let plan = plan_task("build", &scripts, &["--flavor=paid".to_string()], &options).unwrap();
+    assert_eq!(plan[0].steps[0].command, "echo paid beta");
+    let err = plan_task("build", &scripts, &["--flavor=pro".to_string()], &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value 'pro' for flavor must be one of: free, paid"
+    );
+}
+
+#[test]
+fn computed_defaults_and_choices_run_in_the_config_directory() {
+    let dir = tempdir().unwrap();
+    std::fs::create_dir(dir.path().join("app")).unwrap();
+    std::fs::create_dir(dir.path().join("elsewhere")).unwrap();
+    std::fs::write(dir.path().join("marker.txt"), "root\n").unwrap();
+    let scripts = scripts_from(&[(
+        "build",
+        json!({
+            "cmd": "echo {{inline=$(cat marker.txt)}} {{declared}} {{picked}}",
+            "cwd": "app",
+            "vars": {
+                "declared": { "from_cmd": "cat marker.txt" },
+                "picked": { "type": "enum", "choices_from": "cat marker.txt" }
+            }
+        }),
+    )]);
+    // Neither the task's own `cwd` nor `--cwd` moves them.
+    let options = RunOptions {
+        cwd: Some(dir.path().join("elsewhere")),
+        prompt: false,
+        ..in_dir(dir.path())
+    };
+
+    let plan = plan_task("build", &scripts, &["--picked=root".to_string()], &options).unwrap();
+    assert_eq!(plan[0].steps[0].command, "echo root root root");
+}
+
+#[test]
+fn variable_values_come_from_the_command_line_then_var_files_then_defaults() {
+    let scripts = scripts_from(&[(
+        "ship",
+        json!({
+            "cmd": "ship {{app_id}} {{registry}} {{replicas=1}} -- {{@}}",
+            "vars": { "registry": { "default": "docker.io" } }
+        }),
+    )]);
+    let shared = robin::config::config_vars(
+        &serde_json::from_value(json!({
+            "app_id": "com.acme.app",
+            "registry": "ghcr.io",
+            "replicas": { "type": "int" }
+        }))
+        .unwrap(),
+    )
+    .unwrap();
+    let options = RunOptions {
+        vars: shared,
+        var_values: vec![
+            ("replicas".to_string(), "3".to_string()),
+            ("app_id".to_string(), "com.acme.staging".to_string()),
+            ("replicas".to_string(), "5".to_string()),
+        ],
+        ..RunOptions::default()
+    };
+    let args: Vec<String> = ["--app_id=com.acme.cli", "--", "extra"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+
+    let plan = plan_task("ship", &scripts, &args, &options).unwrap();
+
+    // The CLI beats the var file, a later file value beats an earlier one, and
+    // the task's own default beats the shared one.
+    assert!(
+        plan[0].steps[0]
+            .command
+            .starts_with("ship com.acme.cli docker.io"),
+        "{}",
+        plan[0].steps[0].command
+    );
+    assert!(plan[0].steps[0].command.ends_with(" 5 -- extra"));
+
+    // Var file values are validated against the shared declaration.
+    let options = RunOptions {
+        var_values: vec![("replicas".to_string(), "many".to_string())],
+        ..options
+    };
+    let err = plan_task("ship", &scripts, &args, &options).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Value 'many' for replicas must be an integer"
+    );
+}
+
+#[test]
+fn secret_variables_and_env_values_are_masked_in_what_robin_prints() {
+    let scripts = scripts_from(&[(
+        "publish",
+        json!({
+            "cmd": "publish --token {{token}} --key $SIGNING_KEY --user {{user}}",
+            "env": { "SIGNING_KEY": "k3y-from-env" },
+            "vars": {
+                "token": { "secret": true, "pattern": "^tok_" },
+                "user": {}
+            }
+        }),
+    )]);
+    let options = RunOptions {
+        secret_env: vec!["SIGNING_KEY".to_string()],
+        ..RunOptions::default()
+    };
+    let args = |token: &str| vec![format!("--token={}", token), "--user=ana".to_string()];
+
+    let plan = plan_task("publish", &scripts, &args("tok_s3cr3t value"), &options).unwrap();
+
+    // The command keeps the real values; only what gets printed is masked.
+    let step = &plan[0].steps[0];
+    assert!(step.command.contains("'tok_s3cr3t value'"));
+    assert_eq!(
+        step.summary(),
+        "publish --token **** --key $SIGNING_KEY --user ana"
+    );
+    assert_eq!(robin::redact("echo k3y-from-env"), "echo ****");
+
+    let err = plan_task("publish", &scripts, &args("not-a-t0ken"), &options).unwrap_err();
+    assert_eq!(
+        robin::redact(&err.to_string()),
+        "Value '****' for token must match ^tok_"
+    );
+}
+
+#[test]
+fn passthrough_arguments_go_to_the_last_command_unless_placeholders_take_them() {
+    let scripts = scripts_from(&[
+        ("build", json!("cargo build")),
+        ("test", json!(["@build", "cargo test"])),
+        ("grep", json!("grep {{1}} {{@}}")),
+    ]);
+    let args: Vec<String> = ["--", "--nocapture", "-k", "foo bar"]
+        .iter()
+        .map(|a| a.to_string())
+        .collect();
+    let commands = |name: &str| -> Vec<String> {
+        plan_task(name, &scripts, &args, &RunOptions::default())
+            .unwrap()
+            .into_iter()
+            .flat_map(|t| t.steps.into_iter().map(|s| s.command))
+            .collect()
+    };
+
+    assert_eq!(
+        commands("test"),
+        vec!["cargo build", "cargo test --nocapture -k 'foo bar'"]
+    );
+    assert_eq!(
+        commands("grep"),
+        vec!["grep --nocapture --nocapture -k 'foo bar'"]
+    );
+}
+
 #[tokio::test]
 async fn test_list_commands() {
     let (_temp_dir, config_path) = common::setup().await;
@@ -0,0 +1,215 @@
+use robin::validate::{Severity, validate_config};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+/// The problems found in `path`, as `line:column: message` (the file name
+/// is left out unless it isn't `path` itself).
+fn problems(path: &Path) -> Vec<String> {
+    validate_config(path)
+        .into_iter()
+        .map(|p| {
+            let file = match p.file == path {
+                true => String::new(),
+                false => format!("{} ", p.file.file_name().unwrap().to_string_lossy()),
+            };
+            let (line, column) = p.position.unwrap_or_default();
+            format!("{}{}:{}: {}", file, line, column, p.message)
+        })
+        .collect()
+}
+
+#[test]
+fn a_well_formed_config_has_no_problems() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+            "vars": { "env": { "type": "enum", "choices": ["staging", "prod"] } },
+            "scripts": {
+                "build": "cargo build",
+                "ship": { "cmd": ["@build", "deploy {{env}} {{tag|slug}} -- {{@}}"], "deps": ["build"] },
+                "state": "docker inspect --format '{{.State.Status}}' api"
+            }
+        }"#,
+    )
+    .unwrap();
+
+    assert_eq!(problems(&path), Vec::<String>::new());
+}
+
+#[test]
+fn problems_point_at_the_line_and_column_they_are_on() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+
+    fs::write(
+        &path,
+        "{\n  \"scripts\": {\n    \"a\": \"x\"\n    \"b\": \"y\"\n  }\n}\n",
+    )
+    .unwrap();
+    assert_eq!(
+        problems(&path),
+        vec!["4:5: Invalid JSON: expected `,` or `}`"]
+    );
+
+    fs::write(
+        &path,
+        r#"{
+  "scripts": {
+    "build": { "cmd": "cargo build", "desc": 3 },
+    "lint": { "command": "cargo clippy" }
+  }
+}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        problems(&path),
+        vec![
+            "3:38: /scripts/build/desc: expected a string, found number",
+            "4:5: /scripts/lint: missing required field 'cmd'",
+            "4:15: /scripts/lint/command: unknown field 'command'",
+        ]
+    );
+}
+
+#[test]
+fn task_references_cycles_and_placeholders_are_checked() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+  "scripts": {
+    "a": "@b",
+    "b": "@a",
+    "release": { "cmd": ["@build", "git tag {{version|upcase}} {{na-me}}"], "deps": ["lint"] }
+  }
+}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        problems(&path),
+        vec![
+            "3:5: Cycle detected in task references: b -> a -> b",
+            "5:5: Dependency 'lint' of task 'release' not found",
+            "5:5: Referenced task 'build' not found",
+            "5:5: Task 'release': Unknown filter '|upcase' (expected raw, upper, lower, slug or \
+             default(...)) in '{{version|upcase}}'",
+            "5:5: Task 'release': Malformed placeholder '{{na-me}}'",
+        ]
+    );
+}
+
+#[test]
+fn includes_are_checked_for_missing_files_cycles_and_clashing_tasks() {
+    let dir = tempdir().unwrap();
+    let root = dir.path();
+    let path = root.join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+  "include": ["a.json", "b.json", "gone.json"],
+  "scripts": { "build": "cargo build" }
+}"#,
+    )
+    .unwrap();
+    fs::write(
+        root.join("a.json"),
+        r#"{ "include": [".robin.json"], "scripts": { "build": "make", "fmt": "cargo fmt" } }"#,
+    )
+    .unwrap();
+    fs::write(
+        root.join("b.json"),
+        r#"{ "scripts": { "fmt": "rustfmt" } }"#,
+    )
+    .unwrap();
+
+    let found = validate_config(&path);
+    let messages = problems(&path);
+
+    assert!(messages[0].starts_with("a.json 1:44: Task 'build' is overridden by the one in"));
+    assert_eq!(found[0].severity, Severity::Warning);
+    assert!(messages[1].starts_with("a.json 1:15: Include cycle: "));
+    assert!(messages[2].starts_with("b.json 1:16: Task 'fmt' is also declared in"));
+    assert!(messages[3].starts_with("2:35: Included config"));
+    assert!(messages[3].contains("gone.json can't be read"));
+    assert_eq!(messages.len(), 4);
+}
+
+#[test]
+fn an_unreadable_include_only_hides_unknown_task_errors() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+  "include": ["gone.json"],
+  "scripts": {
+    "a": "@b",
+    "b": "@a",
+    "ship": { "cmd": "@build", "deps": ["lint"] }
+  }
+}"#,
+    )
+    .unwrap();
+
+    let messages = problems(&path);
+    assert!(messages[0].starts_with("2:15: Included config"));
+    assert_eq!(
+        messages[1..],
+        ["4:5: Cycle detected in task references: b -> a -> b"]
+    );
+}
+
+#[test]
+fn a_key_repeated_in_the_same_object_is_an_error() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{
+  "scripts": {
+    "build": "cargo build",
+    "build": "make"
+  }
+}"#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        problems(&path),
+        vec!["4:5: /scripts/build: duplicate key, the last one wins"]
+    );
+}
+
+#[test]
+fn local_overrides_are_checked_over_the_shared_config() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join(".robin.json");
+    fs::write(
+        &path,
+        r#"{ "scripts": { "run": "adb install", "ship": ["@run", "@upload"] } }"#,
+    )
+    .unwrap();
+    fs::write(
+        dir.path().join(".robin.local.json"),
+        r#"{
+  "scripts": {
+    "run": "adb -s {{device}} install",
+    "upload": "echo skip",
+    "debug": "@missing"
+  }
+}"#,
+    )
+    .unwrap();
+
+    // Overriding a shared task is the point of the file, not a clash, and its
+    // tasks can be referenced from the shared ones.
+    assert_eq!(
+        problems(&path),
+        vec![".robin.local.json 5:5: Referenced task 'missing' not found"]
+    );
+}